@@ -1,31 +1,131 @@
 use crate::{
-    cursor::GlobalCursor,
-    layout::{self, JexLayout},
+    cursor::{parse_path, LeafCursor, SearchHit, SearchScope},
+    diff::{diff_lines, DiffLines},
+    jq::{
+        jv::JV,
+        query::{jq_command, DEFAULT_RESULT_LIMIT, JQ, QUERY_TIMEOUT},
+    },
+    layout::{self, JexLayout, LayoutMode, DEFAULT_SPLIT_PERCENT},
+    lines::{escaped_str, DisplayOptions},
+    session,
+    theme::Theme,
     view_tree::{
-        View, ViewForest, ViewForestIndex, ViewTree, ViewTreeIndex, ViewWithParent,
-        ViewWithParentMut,
+        JsonView, LoadProgress, NamedView, PendingLoad, View, ViewForest, ViewForestIndex,
+        ViewTree, ViewTreeIndex, ViewWithParent, ViewWithParentMut,
     },
+    watch::absolute_path,
 };
+use arboard::Clipboard;
 use log::{debug, trace};
 use regex::Regex;
-use std::{default::Default, fs, io};
+use std::{
+    cell::RefCell, collections::HashSet, default::Default, error::Error, io, path::PathBuf, rc::Rc,
+    time::Duration,
+};
 use tui::{
-    layout::{Alignment, Rect},
-    text::Text,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
     widgets::{Block, Borders, Clear, Paragraph, Wrap},
     Frame,
 };
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 const README: &str = include_str!("../README.md");
+const MOUSE_SCROLL_LINES: usize = 3;
+// Percent of the screen < and > move the divider between the panes by
+const SPLIT_STEP: u16 = 5;
+pub const DEFAULT_INLINE_WIDTH: usize = 40;
+
+pub enum SearchQuery {
+    Regex(Regex),
+    // A jq program, matching values it outputs something truthy for
+    Jq { prog: JQ, source: String },
+}
+
+impl SearchQuery {
+    fn matches(&mut self, cursor: &LeafCursor, scope: SearchScope) -> bool {
+        match self {
+            SearchQuery::Regex(re) => cursor.regex_matches(re, scope),
+            SearchQuery::Jq { prog, .. } => cursor.jq_matches(prog),
+        }
+    }
+    pub fn pattern(&self) -> &str {
+        match self {
+            SearchQuery::Regex(re) => re.as_str(),
+            SearchQuery::Jq { source, .. } => source,
+        }
+    }
+    // A jq program runs on every value searched, so each search gets as long as a query does
+    fn start_timer(&mut self) {
+        if let SearchQuery::Jq { prog, .. } = self {
+            prog.start_timer(QUERY_TIMEOUT);
+        }
+    }
+    fn timed_out(&self) -> bool {
+        matches!(self, SearchQuery::Jq { prog, .. } if prog.timed_out())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SearchResult {
+    Found,
+    // Found by going around past the end (or start) of the values. When search_wrap is off, the
+    // cursor stays where it was.
+    Wrapped,
+    // Holds the pattern that wasn't found
+    NotFound(String),
+    // There's no search to repeat, or nothing to search through
+    NoSearch,
+    // A jq search ran out of time. Holds the program.
+    TimedOut(String),
+}
+
+// The (left, right) values a diff was computed from, and the diff itself
+type CachedDiff = (Rc<[JV]>, Rc<[JV]>, Rc<DiffLines>);
 
 pub struct App {
     pub views: ViewForest,
     pub left_index: ViewForestIndex,
     pub right_index: ViewForestIndex,
     pub focus: Focus,
-    pub search_re: Option<Regex>,
+    search: Option<SearchQuery>,
+    search_scope: SearchScope,
+    // Whether n and N go around to the start (or end) after the last hit
+    pub search_wrap: bool,
+    // Number of hits for search in the given values, so we don't have to rescan them on every
+    // search.
+    search_total: Option<(Rc<[JV]>, usize)>,
+    // (index, total) of the last search hit
+    pub search_status: Option<(usize, usize)>,
     pub show_tree: bool,
+    // Whether the keys around the cursor are listed in a panel on the right
+    pub show_outline: bool,
+    pub layout_mode: LayoutMode,
+    // Percent of the room for the views that goes to the left (or top) one
+    pub split_percent: u16,
+    // Whether only the focused view is shown, with no query line
+    pub single_pane: bool,
+    // The highlighted key in the outline while it has focus
+    pub outline_selection: Option<usize>,
+    pub show_line_numbers: bool,
+    // Whether lines that differ between the two panes are highlighted
+    show_diff: bool,
+    // So we don't recompute the diff on every render
+    diff: RefCell<Option<CachedDiff>>,
+    // Widest a container can be and still get drawn on one line in compact mode
+    pub inline_width: usize,
+    // How characters and values are drawn in every view
+    display: DisplayOptions,
+    // How many results a query keeps before the rest are dropped
+    pub result_limit: usize,
+    pub theme: Theme,
     pub flash: Option<Flash>,
+    // On X11, the clipboard contents are served by whoever owns them, so we keep this around
+    // instead of making a new one every time.
+    clipboard: Option<Clipboard>,
+    // (tree index, load) for root views that are still being parsed
+    pending_loads: Vec<(usize, PendingLoad)>,
 }
 
 pub struct Flash {
@@ -36,6 +136,8 @@ pub struct Flash {
 pub enum AppRenderMode {
     Normal,
     InputEditor,
+    // A prompt we're drawing ourselves (instead of rustyline), with what's been typed so far
+    Prompt(String),
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -54,28 +156,67 @@ impl Focus {
 }
 
 impl App {
-    pub fn new<R: io::Read>(r: R, name: String, layout: JexLayout) -> io::Result<Self> {
-        let views = ViewForest {
-            trees: vec![ViewTree::new_from_reader(r, name, layout)?],
-        };
+    pub fn new<R: io::Read + Send + 'static>(
+        r: R,
+        name: String,
+        layout: JexLayout,
+    ) -> io::Result<Self> {
+        let (tree, pending) = ViewTree::new_from_reader(r, name, layout)?;
+        Ok(App::from_tree(tree, pending))
+    }
+    pub fn new_from_file(path: String, layout: JexLayout) -> io::Result<Self> {
+        let (tree, pending) = ViewTree::new_from_file(path, layout)?;
+        Ok(App::from_tree(tree, pending))
+    }
+    // Restores a session saved with save_session
+    pub fn new_from_session(path: &str, layout: JexLayout) -> Result<Self, Box<dyn Error>> {
+        let session = session::load(path, layout, DEFAULT_RESULT_LIMIT)?;
+        let mut app = App::from_trees(session.trees, Vec::new());
+        app.restore_panes(session.left_index, session.right_index, session.focus);
+        Ok(app)
+    }
+    fn from_tree(tree: ViewTree, pending: Option<PendingLoad>) -> Self {
+        let pending_loads = pending.into_iter().map(|load| (0, load)).collect();
+        App::from_trees(vec![tree], pending_loads)
+    }
+    fn from_trees(trees: Vec<ViewTree>, pending_loads: Vec<(usize, PendingLoad)>) -> Self {
+        let views = ViewForest { trees };
         let left_index = ViewForestIndex {
             tree: 0,
             within_tree: ViewTreeIndex { path: Vec::new() },
         };
-        let right_index = ViewForestIndex {
-            tree: 0,
-            within_tree: ViewTreeIndex { path: vec![0] },
-        };
-        let app = App {
+        let right_index = left_index.clone();
+        let mut app = App {
             views,
             left_index,
             right_index,
             focus: Focus::Left,
-            search_re: None,
+            search: None,
+            search_scope: SearchScope::Both,
+            search_wrap: true,
+            search_total: None,
+            search_status: None,
             show_tree: false,
+            show_outline: false,
+            layout_mode: LayoutMode::SideBySide,
+            split_percent: DEFAULT_SPLIT_PERCENT,
+            single_pane: false,
+            outline_selection: None,
+            show_line_numbers: false,
+            show_diff: false,
+            diff: RefCell::new(None),
+            inline_width: DEFAULT_INLINE_WIDTH,
+            display: DisplayOptions::default(),
+            result_limit: DEFAULT_RESULT_LIMIT,
+            theme: Theme::default(),
             flash: None,
+            clipboard: None,
+            pending_loads,
         };
-        Ok(app)
+        app.focus_tree(0);
+        app.warn_about_lossy_integers();
+        app.warn_about_skipped_values();
+        app
     }
     fn current_views(&self) -> (ViewWithParent, ViewWithParent) {
         trace!(
@@ -111,18 +252,33 @@ impl App {
             .index_mut(&self.right_index)
             .expect("App index invalidated")
     }
-    pub fn focused_view_mut(&mut self) -> ViewWithParentMut {
-        match self.focus {
+    pub fn view_mut(&mut self, focus: Focus) -> ViewWithParentMut<'_> {
+        match focus {
             Focus::Left => self.left_view_mut(),
             Focus::Right => self.right_view_mut(),
         }
     }
+    pub fn focused_view_mut(&mut self) -> ViewWithParentMut {
+        self.view_mut(self.focus)
+    }
     pub fn focused_query_mut(&mut self) -> Option<&mut String> {
         match self.focused_view_mut() {
             ViewWithParentMut::Root { .. } => None,
             ViewWithParentMut::Child { query, .. } => Some(query),
         }
     }
+    // As a bare query, or with command wrapped as a jq invocation
+    pub fn copy_query(&mut self, command: bool) {
+        let query = match self.focused_view() {
+            ViewWithParent::Root { .. } => None,
+            ViewWithParent::Child { query, .. } => Some(query.clone()),
+        };
+        match query {
+            None => self.set_flash("root node has no query".to_owned()),
+            Some(query) if command => self.copy_to_clipboard(jq_command(&query)),
+            Some(query) => self.copy_to_clipboard(query),
+        }
+    }
     pub fn focused_index(&self) -> &ViewForestIndex {
         match self.focus {
             Focus::Left => &self.left_index,
@@ -136,6 +292,7 @@ impl App {
         }
     }
     pub fn recompute_focused_view(&mut self, focused_rect: Rect) {
+        let result_limit = self.result_limit;
         match self.focused_view_mut() {
             ViewWithParentMut::Root { .. } => panic!("Can't recompute root node"),
             ViewWithParentMut::Child {
@@ -144,7 +301,7 @@ impl App {
                 frame,
             } => match &parent.view {
                 View::Json(Some(left)) => {
-                    frame.view = left.apply_query(query, focused_rect);
+                    frame.replace_view(left.apply_query(query, focused_rect, result_limit));
                 }
                 View::Json(None) | View::Error(_) => {
                     frame.view = View::Json(None);
@@ -152,6 +309,36 @@ impl App {
             },
         }
     }
+    // Adds a child running query on the focused view, and shows the two side by side so the
+    // query can be refined further.
+    pub fn push_child_with_query(&mut self, query: String, layout: JexLayout) {
+        let mut index = self.focused_index().clone();
+        let tree = self.views.trees[index.tree]
+            .index_tree_mut(&index.within_tree.path)
+            .expect("App index invalidated");
+        tree.push_trivial_child(layout.right);
+        let child_ix = tree.children.len() - 1;
+        match tree.children.last_mut() {
+            Some((child_query, _)) => *child_query = query,
+            None => return,
+        }
+        self.left_index = index.clone();
+        index.within_tree.path.push(child_ix);
+        self.right_index = index;
+        self.focus = Focus::Right;
+        self.recompute_focused_view(layout.right);
+    }
+    // Opens the json inside the string under the cursor as a child view
+    pub fn push_embedded_json_child(&mut self, layout: JexLayout) {
+        let query = match &self.focused_view().frame().view {
+            View::Json(Some(view)) => view.embedded_json_query(),
+            _ => return,
+        };
+        match query {
+            Ok(query) => self.push_child_with_query(query, layout),
+            Err(err) => self.set_flash(err),
+        }
+    }
     pub fn re_root(&mut self, index: &ViewForestIndex) {
         if index.within_tree.path.is_empty() {
             return;
@@ -177,66 +364,168 @@ impl App {
             self.views,
         );
     }
+    // Removes the focused view and everything below it, moving anything that was showing them to
+    // their parent.
+    pub fn remove_focused_view(&mut self) {
+        let index = self.focused_index().clone();
+        if self.views.remove(&index).is_none() {
+            self.set_flash("Can't remove the only tree".to_owned());
+            return;
+        }
+        self.left_index.update_after_removal(&index, &self.views);
+        self.right_index.update_after_removal(&index, &self.views);
+        if index.within_tree.path.is_empty() {
+            self.pending_loads = std::mem::take(&mut self.pending_loads)
+                .into_iter()
+                .filter(|(tree_ix, _)| *tree_ix != index.tree)
+                .map(|(tree_ix, load)| {
+                    let tree_ix = if tree_ix > index.tree {
+                        tree_ix - 1
+                    } else {
+                        tree_ix
+                    };
+                    (tree_ix, load)
+                })
+                .collect();
+        }
+    }
     pub fn render<B: tui::backend::Backend>(
         &self,
         mode: AppRenderMode,
     ) -> impl FnMut(&mut Frame<B>) + '_ {
         let App { focus, .. } = self;
         let (left, right) = self.current_views();
+        let diff = self.current_diff(&left, &right);
         move |f| {
             let size = f.size();
-            let layout = JexLayout::new(size, self.show_tree);
-            let left_block = Block::default()
-                .title(left.frame().name.to_owned())
-                .borders(Borders::ALL);
-            let left_paragraph = left
-                .frame()
-                .view
-                .render(left_block.inner(layout.left), *focus == Focus::Left)
-                .block(left_block);
-            f.render_widget(left_paragraph, layout.left);
-            let right_block = Block::default()
-                .title(right.frame().name.to_owned())
-                .borders(Borders::ALL);
-            let right_paragraph = right
-                .frame()
-                .view
-                .render(right_block.inner(layout.right), *focus == Focus::Right)
-                .block(right_block);
-            f.render_widget(right_paragraph, layout.right);
+            let layout = self.layout_with_query(size, !matches!(mode, AppRenderMode::Normal));
+            // In single pane mode, the hidden pane has no room
+            if layout.left.area() > 0 {
+                let left_block = Block::default()
+                    .title(view_title(
+                        left.frame(),
+                        self.load_progress(&self.left_index),
+                        self.result_limit,
+                    ))
+                    .borders(Borders::ALL);
+                let left_paragraph = left
+                    .frame()
+                    .view
+                    .render(
+                        left_block.inner(layout.left),
+                        *focus == Focus::Left,
+                        self.show_line_numbers,
+                        diff.as_ref()
+                            .map(|diff| (&diff.removed, self.theme.removed)),
+                        &self.theme,
+                    )
+                    .block(left_block);
+                f.render_widget(left_paragraph, layout.left);
+            }
+            if layout.right.area() > 0 {
+                let right_block = Block::default()
+                    .title(view_title(
+                        right.frame(),
+                        self.load_progress(&self.right_index),
+                        self.result_limit,
+                    ))
+                    .borders(Borders::ALL);
+                let right_paragraph = right
+                    .frame()
+                    .view
+                    .render(
+                        right_block.inner(layout.right),
+                        *focus == Focus::Right,
+                        self.show_line_numbers,
+                        diff.as_ref().map(|diff| (&diff.added, self.theme.added)),
+                        &self.theme,
+                    )
+                    .block(right_block);
+                f.render_widget(right_paragraph, layout.right);
+            }
             if let Some(tree_rect) = layout.tree {
                 let tree_block = Block::default().borders(Borders::ALL);
                 f.render_widget(
                     self.views
-                        .render_tree(&self.left_index, &self.right_index)
+                        .render_tree(&self.left_index, &self.right_index, &self.theme)
                         .block(tree_block),
                     tree_rect,
                 );
             }
+            if let Some(outline_rect) = layout.outline {
+                let outline_block = Block::default().title("Outline").borders(Borders::ALL);
+                let height = outline_block.inner(outline_rect).height;
+                f.render_widget(
+                    self.render_outline(height).block(outline_block),
+                    outline_rect,
+                );
+            }
+            let focused_view = match self.focus {
+                Focus::Left => left,
+                Focus::Right => right,
+            };
+            if let View::Json(Some(view)) = &focused_view.frame().view {
+                let breadcrumbs = view.cursor.breadcrumbs().join(" ▸ ");
+                let breadcrumbs = truncate_left(&breadcrumbs, layout.breadcrumb.width as usize);
+                f.render_widget(
+                    Paragraph::new(breadcrumbs).style(Style::default().add_modifier(Modifier::DIM)),
+                    layout.breadcrumb,
+                );
+            }
             match mode {
                 AppRenderMode::Normal => {
-                    let focused_view = match self.focus {
-                        Focus::Left => left,
-                        Focus::Right => right,
+                    let mut status_parts = Vec::new();
+                    if let View::Json(Some(view)) = &focused_view.frame().view {
+                        status_parts.push(view.cursor.describe_focus(view.display));
+                        if view.is_grepping() {
+                            status_parts.push("grep".to_owned());
+                        }
+                    }
+                    if let Some((index, total)) = self.search_status {
+                        status_parts.push(format!("{}/{}", index, total));
+                    }
+                    let status = status_parts.join("  ");
+                    let (query_rect, status_rect) = if status.is_empty() {
+                        (layout.query, None)
+                    } else {
+                        let status_width = status.width() as u16 + 1;
+                        let chunks = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints(
+                                [Constraint::Min(0), Constraint::Length(status_width)].as_ref(),
+                            )
+                            .split(layout.query);
+                        (chunks[0], Some(chunks[1]))
                     };
                     match focused_view {
                         ViewWithParent::Root { .. } => {
                             let placeholder = Paragraph::new("Root Node")
                                 .alignment(Alignment::Left)
                                 .wrap(Wrap { trim: false });
-                            f.render_widget(placeholder, layout.query);
+                            f.render_widget(placeholder, query_rect);
                         }
                         ViewWithParent::Child { query, .. } => {
                             let query = Paragraph::new(query.as_str())
                                 .alignment(Alignment::Left)
                                 .wrap(Wrap { trim: false });
-                            f.render_widget(query, layout.query);
+                            f.render_widget(query, query_rect);
                         }
                     }
+                    if let Some(status_rect) = status_rect {
+                        let status = Paragraph::new(status).alignment(Alignment::Right);
+                        f.render_widget(status, status_rect);
+                    }
                 }
                 AppRenderMode::InputEditor => {
                     f.set_cursor(0, layout.query.y);
                 }
+                AppRenderMode::Prompt(ref line) => {
+                    // Leaves a column for the cursor after the end
+                    let line = truncate_left(line, layout.query.width.saturating_sub(1) as usize);
+                    let cursor_x = layout.query.x + line.width() as u16;
+                    f.render_widget(Paragraph::new(line), layout.query);
+                    f.set_cursor(cursor_x, layout.query.y);
+                }
             }
             if let Some(flash) = self.flash.as_ref() {
                 let area = layout::flash(size);
@@ -255,42 +544,534 @@ impl App {
             }
         }
     }
-    pub fn search(&mut self, reverse: bool) {
-        let re = if let Some(re) = self.search_re.clone() {
-            re
-        } else {
-            return;
+    pub fn search(&mut self, reverse: bool) -> SearchResult {
+        // Taken out so we can run it while the focused view is borrowed
+        let mut query = match self.search.take() {
+            Some(query) => query,
+            None => return SearchResult::NoSearch,
         };
+        let result = self.search_with(&mut query, reverse);
+        self.search = Some(query);
+        result
+    }
+    fn search_with(&mut self, query: &mut SearchQuery, reverse: bool) -> SearchResult {
+        let scope = self.search_scope;
+        let wrap = self.search_wrap;
+        query.start_timer();
+        let mut matches = |cursor: &LeafCursor| query.matches(cursor, scope);
         let mut view_with_parents = self.focused_view_mut();
         let view_frame = view_with_parents.frame();
         let view = if let View::Json(Some(view)) = &mut view_frame.view {
             view
         } else {
-            return;
+            return SearchResult::NoSearch;
         };
         let search_hit = if reverse {
-            view.cursor.clone().search_back(&re)
+            view.cursor.clone().search_back_by(&mut matches)
+        } else {
+            view.cursor.clone().search_by(&mut matches)
+        };
+        if query.timed_out() {
+            self.search_status = None;
+            return SearchResult::TimedOut(query.pattern().to_owned());
+        }
+        let mut matches = |cursor: &LeafCursor| query.matches(cursor, scope);
+        let result = match search_hit {
+            SearchHit::Found(hit) => {
+                view.jump_to(hit);
+                SearchResult::Found
+            }
+            SearchHit::Wrapped(hit) => {
+                if wrap {
+                    view.jump_to(hit);
+                }
+                SearchResult::Wrapped
+            }
+            SearchHit::NotFound => {
+                self.search_status = None;
+                return SearchResult::NotFound(query.pattern().to_owned());
+            }
+        };
+        let index = view.cursor.search_position_by(&mut matches);
+        let values = view.values.clone();
+        let total = match &self.search_total {
+            Some((counted, total)) if Rc::ptr_eq(counted, &values) => *total,
+            _ => LeafCursor::count_search_hits_by(values.clone(), &mut matches),
+        };
+        // The hit was found in time, but there's no telling where it falls among the others
+        if query.timed_out() {
+            self.search_status = None;
+            return result;
+        }
+        self.search_total = Some((values, total));
+        self.search_status = Some((index, total));
+        result
+    }
+    pub fn jump_to_path(&mut self, path: &str) {
+        let mut view_with_parents = self.focused_view_mut();
+        let view = match &mut view_with_parents.frame().view {
+            View::Json(Some(view)) => view,
+            _ => return,
+        };
+        match parse_path(path, &view.values) {
+            Some(path) => {
+                let cursor = LeafCursor::from_path(view.values.clone(), &path);
+                view.jump_to(cursor);
+            }
+            None => self.set_flash(format!("No such path: {}", path)),
+        }
+    }
+    pub fn goto_line(&mut self, line: &str) {
+        let n = match line.trim().parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => {
+                self.set_flash(format!("Not a line number: {}", line));
+                return;
+            }
+        };
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            view.goto_line(n);
+        }
+    }
+    pub fn goto_document(&mut self, line: &str) {
+        let n = match line.trim().parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => {
+                self.set_flash(format!("Not a document number: {}", line));
+                return;
+            }
+        };
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            if !view.goto_document(n) {
+                let count = view.values.len();
+                self.set_flash(format!("There are only {} documents", count));
+            }
+        }
+    }
+    // Jumps to the start of the next (or previous, if backward) top level value
+    pub fn step_document(&mut self, backward: bool) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            if !view.step_document(backward) {
+                let message = if backward {
+                    "Already at the first document"
+                } else {
+                    "Already at the last document"
+                };
+                self.set_flash(message.to_owned());
+            }
+        }
+    }
+    // Flashes how many keys and values in the focused view match re
+    pub fn count_matches(&mut self, re: &Regex) {
+        let (keys, values) = match &self.focused_view().frame().view {
+            View::Json(Some(view)) => view.count_matches(re),
+            _ => return,
+        };
+        self.set_flash(format!(
+            "{} matches {} keys and {} values",
+            re.as_str(),
+            keys,
+            values
+        ));
+    }
+    // Folds away everything in the focused view that doesn't lead to a match for re
+    pub fn grep(&mut self, re: &Regex) {
+        let scope = self.search_scope;
+        let hits = match &mut self.focused_view_mut().frame().view {
+            View::Json(Some(view)) => view.grep(re, scope),
+            _ => return,
+        };
+        if hits == 0 {
+            self.set_flash(format!("No matches for {}", re.as_str()));
+        }
+    }
+    pub fn delete_at_cursor(&mut self, layout: JexLayout) {
+        let deleted = match &mut self.focused_view_mut().frame().view {
+            View::Json(Some(view)) => view.delete_at_cursor(),
+            _ => return,
+        };
+        if deleted {
+            self.recompute_focused_children(layout);
         } else {
-            view.cursor.clone().search(&re)
+            self.set_flash("Can't delete the only value".to_owned());
+        }
+    }
+    pub fn undo(&mut self, layout: JexLayout) {
+        let undone = match &mut self.focused_view_mut().frame().view {
+            View::Json(Some(view)) => view.undo(),
+            _ => return,
         };
-        if let Some(search_hit) = search_hit {
-            view.cursor = search_hit;
+        if undone {
+            self.recompute_focused_children(layout);
         } else {
+            self.set_flash("Nothing to undo".to_owned());
+        }
+    }
+    pub fn redo(&mut self, layout: JexLayout) {
+        let redone = match &mut self.focused_view_mut().frame().view {
+            View::Json(Some(view)) => view.redo(),
+            _ => return,
+        };
+        if redone {
+            self.recompute_focused_children(layout);
+        } else {
+            self.set_flash("Nothing to redo".to_owned());
+        }
+    }
+    pub fn replace_at_cursor(&mut self, value: JV, layout: JexLayout) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            view.replace_at_cursor(value);
+            self.recompute_focused_children(layout);
+        }
+    }
+    pub fn rename_key(&mut self, new_key: &str, layout: JexLayout) {
+        let renamed = match &mut self.focused_view_mut().frame().view {
+            View::Json(Some(view)) => view.rename_key(new_key),
+            _ => return,
+        };
+        if renamed {
+            self.recompute_focused_children(layout);
+        } else {
+            self.set_flash(format!("Key {} already exists", new_key));
+        }
+    }
+    pub fn insert_key(&mut self, key: &str, value: JV, layout: JexLayout) {
+        let inserted = match &mut self.focused_view_mut().frame().view {
+            View::Json(Some(view)) => view.insert_key(key, value),
+            _ => return,
+        };
+        if inserted {
+            self.recompute_focused_children(layout);
+        } else {
+            self.set_flash(format!("Key {} already exists", key));
+        }
+    }
+    pub fn append_element(&mut self, value: JV, layout: JexLayout) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            if view.append_element(value) {
+                self.recompute_focused_children(layout);
+            }
+        }
+    }
+    // Reruns the queries of everything below the focused view, after it's been edited
+    fn recompute_focused_children(&mut self, layout: JexLayout) {
+        let index = self.focused_index().clone();
+        if let Some(tree) = self.views.trees[index.tree].index_tree_mut(&index.within_tree.path) {
+            tree.recompute_children(layout.right, self.result_limit);
+        }
+    }
+    pub fn set_mark(&mut self, mark: char) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            view.set_mark(mark);
+        }
+    }
+    pub fn jump_to_mark(&mut self, mark: char) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            if !view.jump_to_mark(mark) {
+                self.set_flash(format!("Mark {} isn't set", mark));
+            }
+        }
+    }
+    pub fn follow_reference(&mut self) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            if !view.follow_reference() {
+                self.set_flash("Reference not found".to_owned());
+            }
+        }
+    }
+    // Jumps to the target of a JSON Pointer string like "#/components/schemas/Foo" under the
+    // cursor. Other values are left alone.
+    pub fn follow_pointer(&mut self) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            let pointer = match view.cursor.focused_value() {
+                JV::String(s) if s.value().starts_with("#/") => s.value().to_owned(),
+                _ => return,
+            };
+            if !view.jump_to_pointer(&pointer) {
+                self.set_flash(format!("Can't resolve {}", pointer));
+            }
+        }
+    }
+    // Puts back a copy of the focused view taken before previewing something in it
+    pub fn restore_focused_view(&mut self, saved: JsonView) {
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            *view = saved;
+        }
+    }
+    // Shows where searching for pattern from the cursor in original would land. Each preview
+    // starts over from original, so folds opened for earlier patterns don't pile up.
+    pub fn preview_search(&mut self, pattern: &str, original: &JsonView) {
+        let mut preview = original.clone();
+        let re = Regex::new(pattern).ok().filter(|_| !pattern.is_empty());
+        if let Some(re) = re {
+            let hit = preview.cursor.clone().search(&re, self.search_scope);
+            if let Some(hit) = hit.into_cursor(self.search_wrap) {
+                preview.jump_to(hit);
+            }
+        }
+        self.restore_focused_view(preview);
+    }
+    // Shows which key of the object around the cursor in original best matches query. Like
+    // preview_search, each preview starts over from original, so the object searched stays put.
+    pub fn preview_fuzzy_key(&mut self, query: &str, original: &JsonView) {
+        let mut preview = original.clone();
+        if let Some(path) = original.fuzzy_find_key(query) {
+            preview.jump_to(LeafCursor::from_path(preview.values.clone(), &path));
+        }
+        self.restore_focused_view(preview);
+    }
+    // Leaves the last search alone if pattern doesn't compile, so n and N keep working
+    pub fn set_search_pattern(&mut self, pattern: &str) -> Result<(), regex::Error> {
+        let re = Regex::new(pattern)?;
+        self.set_search(Some(SearchQuery::Regex(re)));
+        Ok(())
+    }
+    pub fn set_search(&mut self, search: Option<SearchQuery>) {
+        self.search = search;
+        self.search_total = None;
+        self.search_status = None;
+    }
+    pub fn toggle_diff(&mut self) {
+        if self.show_diff {
+            self.show_diff = false;
             return;
+        }
+        let (left, right) = self.current_views();
+        let both_json = matches!(
+            (&left.frame().view, &right.frame().view),
+            (View::Json(Some(_)), View::Json(Some(_)))
+        );
+        if both_json {
+            self.show_diff = true;
+        } else {
+            self.set_flash("Both panes need to hold json to diff them".to_owned());
+        }
+    }
+    // The lines that differ between the two panes, if we're showing that and they're both json
+    fn current_diff(&self, left: &ViewWithParent, right: &ViewWithParent) -> Option<Rc<DiffLines>> {
+        if !self.show_diff {
+            return None;
+        }
+        let (left, right) = match (&left.frame().view, &right.frame().view) {
+            (View::Json(Some(left)), View::Json(Some(right))) => (left, right),
+            _ => return None,
+        };
+        let mut cache = self.diff.borrow_mut();
+        if let Some((old, new, lines)) = &*cache {
+            if Rc::ptr_eq(old, &left.values) && Rc::ptr_eq(new, &right.values) {
+                return Some(lines.clone());
+            }
+        }
+        let lines = Rc::new(diff_lines(&left.values, &right.values));
+        *cache = Some((left.values.clone(), right.values.clone(), lines.clone()));
+        Some(lines)
+    }
+    pub fn cycle_search_scope(&mut self) {
+        self.search_scope = self.search_scope.next();
+        self.search_total = None;
+        self.search_status = None;
+        self.set_flash(format!("Searching {}", self.search_scope.describe()));
+    }
+    pub fn toggle_search_wrap(&mut self) {
+        self.search_wrap = !self.search_wrap;
+        self.set_flash(if self.search_wrap {
+            "Search wraps around the ends".to_owned()
+        } else {
+            "Search stops at the ends".to_owned()
+        });
+    }
+    pub fn scroll_pane_at(&mut self, column: u16, row: u16, layout: JexLayout, down: bool) {
+        let (pane, rect) = match pane_at(layout, column, row) {
+            Some(pane) => pane,
+            None => return,
         };
-        view.unfold_around_cursor();
-        if !view
-            .visible_range(&view.folds)
-            .contains_value(&view.cursor.to_path())
-        {
-            view.scroll = GlobalCursor::new(view.values.clone(), view.rect.width, &view.folds)
-                .expect("values should still exist");
+        let show_line_numbers = self.show_line_numbers;
+        let mut view_with_parent = self.view_mut(pane);
+        if let View::Json(Some(view)) = &mut view_with_parent.frame().view {
+            let json_rect = Block::default().borders(Borders::ALL).inner(rect);
+            view.resize_to(view.text_rect(json_rect, show_line_numbers));
+            for _ in 0..MOUSE_SCROLL_LINES {
+                if down {
+                    view.advance_cursor();
+                } else {
+                    view.regress_cursor();
+                }
+            }
+        }
+    }
+    pub fn click_pane_at(&mut self, column: u16, row: u16, layout: JexLayout) {
+        let (pane, rect) = match pane_at(layout, column, row) {
+            Some(pane) => pane,
+            None => return,
+        };
+        self.focus = pane;
+        let json_rect = Block::default().borders(Borders::ALL).inner(rect);
+        if row < json_rect.top() || row >= json_rect.bottom() {
+            return;
         }
+        let show_line_numbers = self.show_line_numbers;
+        let mut view_with_parent = self.view_mut(pane);
+        if let View::Json(Some(view)) = &mut view_with_parent.frame().view {
+            view.resize_to(view.text_rect(json_rect, show_line_numbers));
+            if let Some(cursor) = view.cursor_at_screen_row(row - json_rect.top()) {
+                view.cursor = cursor;
+            }
+        }
+    }
+    // jq can't store integers past 2^53 exactly, so let the user know if loading rounded any. Trees
+    // still loading are left until they're done.
+    pub fn warn_about_lossy_integers(&mut self) {
+        let mut count = 0;
+        for (tree_ix, tree) in self.views.trees.iter_mut().enumerate() {
+            if !self.pending_loads.iter().any(|(ix, _)| *ix == tree_ix) {
+                count += std::mem::take(&mut tree.lossy_integers);
+            }
+        }
+        self.flash_lossy_integers(count);
+    }
+    pub fn flash_lossy_integers(&mut self, count: usize) {
+        if count > 0 {
+            self.set_flash(format!(
+                "{} integers were too large to load exactly, and have been rounded",
+                count
+            ));
+        }
+    }
+    // Flashes the values that were left out of the trees for not being valid json
+    pub fn warn_about_skipped_values(&mut self) {
+        let mut messages = Vec::new();
+        for tree in self.views.trees.iter_mut() {
+            let skipped = std::mem::take(&mut tree.skipped_values);
+            if skipped.is_empty() {
+                continue;
+            }
+            messages.push(format!(
+                "Skipped {} values in {} that weren't valid json:",
+                skipped.len(),
+                tree.view_frame.name
+            ));
+            messages.extend(skipped);
+        }
+        if !messages.is_empty() {
+            self.set_flash(messages.join("\n"));
+        }
+    }
+    // How far along loading the view at index is, if it's the root of a tree still being loaded
+    fn load_progress(&self, index: &ViewForestIndex) -> Option<LoadProgress> {
+        if !index.within_tree.path.is_empty() {
+            return None;
+        }
+        self.pending_loads
+            .iter()
+            .find(|(tree_ix, _)| *tree_ix == index.tree)
+            .map(|(_, load)| load.progress())
+    }
+    pub fn has_pending_loads(&self) -> bool {
+        !self.pending_loads.is_empty()
+    }
+    // Merges in any values parsed in the background since the last call, returning whether anything
+    // changed. That's always the case while something is loading, since the progress in the title
+    // moves on even when a big value is still being parsed.
+    pub fn poll_pending_loads(&mut self, timeout: Duration, layout: JexLayout) -> bool {
+        let changed = !self.pending_loads.is_empty();
+        for (tree_ix, load) in std::mem::take(&mut self.pending_loads) {
+            let (loaded, status) = load.poll(timeout);
+            let tree = &mut self.views.trees[tree_ix];
+            tree.skipped_values.extend(loaded.skipped);
+            tree.lossy_integers += loaded.lossy_integers;
+            if let View::Json(Some(view)) = &mut tree.view_frame.view {
+                if !loaded.values.is_empty() {
+                    view.append_values(loaded.values);
+                }
+                view.has_pending_load = status.is_none();
+            }
+            match status {
+                None => self.pending_loads.push((tree_ix, load)),
+                Some(result) => {
+                    // Queries were run against whatever had been loaded at the time, so rerun them.
+                    tree.recompute_children(layout.right, self.result_limit);
+                    match result {
+                        Err(err) => {
+                            let msg = format!("Error loading {}:\n{}", tree.view_frame.name, err);
+                            self.set_flash(msg);
+                        }
+                        Ok(()) => {
+                            let count = std::mem::take(&mut tree.lossy_integers);
+                            self.flash_lossy_integers(count);
+                        }
+                    }
+                    self.warn_about_skipped_values();
+                }
+            }
+        }
+        changed
+    }
+    pub fn layout(&self, size: Rect) -> JexLayout {
+        self.layout_with_query(size, false)
+    }
+    // The query line is only hidden in single pane mode, and comes back while it's being edited
+    fn layout_with_query(&self, size: Rect, show_query: bool) -> JexLayout {
+        let layout = JexLayout::new(
+            size,
+            self.show_tree,
+            self.show_outline,
+            self.layout_mode,
+            self.split_percent,
+        );
+        if self.single_pane {
+            layout.single_pane(self.focus == Focus::Left, show_query)
+        } else {
+            layout
+        }
+    }
+    pub fn toggle_single_pane(&mut self, size: Rect) {
+        self.single_pane = !self.single_pane;
+        self.resize(self.layout(size));
+    }
+    // Moves the divider between the views to make the focused one bigger or smaller
+    pub fn resize_focused_pane(&mut self, grow: bool, size: Rect) {
+        let grow_left = grow == (self.focus == Focus::Left);
+        // Kept to what JexLayout can show, so presses past a pane's minimum size don't pile up
+        // unseen and have to be undone before the divider moves back
+        let layout = JexLayout::new(
+            size,
+            self.show_tree,
+            self.show_outline,
+            self.layout_mode,
+            self.split_percent,
+        );
+        let (min, max) = layout
+            .split_percent_range(self.layout_mode)
+            .unwrap_or((0, 100));
+        let split_percent = self.split_percent.clamp(min, max);
+        self.split_percent = if grow_left {
+            (split_percent + SPLIT_STEP).min(max)
+        } else {
+            split_percent.saturating_sub(SPLIT_STEP).max(min)
+        };
+        self.resize(self.layout(size));
+    }
+    // Switches between the views side by side and one above the other
+    pub fn toggle_layout_mode(&mut self, size: Rect) {
+        self.layout_mode = self.layout_mode.toggle();
+        self.resize(self.layout(size));
     }
     pub fn resize(&mut self, layout: JexLayout) {
         debug!("Resizing to new layout: {:?}", layout);
-        self.left_view_mut().frame().view.resize_to(layout.left);
-        self.right_view_mut().frame().view.resize_to(layout.right);
+        let show_line_numbers = self.show_line_numbers;
+        // A pane hidden by single pane mode keeps its size until it's shown again
+        if layout.left.area() > 0 {
+            self.left_view_mut()
+                .frame()
+                .view
+                .resize_to(layout.left, show_line_numbers);
+        }
+        if layout.right.area() > 0 {
+            self.right_view_mut()
+                .frame()
+                .view
+                .resize_to(layout.right, show_line_numbers);
+        }
     }
     pub fn set_flash(&mut self, s: String) {
         self.flash = Some(Flash {
@@ -298,6 +1079,52 @@ impl App {
             scroll: 0,
         });
     }
+    // Falls back to showing the text in a flash if the clipboard isn't available.
+    pub fn copy_to_clipboard(&mut self, text: String) {
+        if self.clipboard.is_none() {
+            match Clipboard::new() {
+                Ok(clipboard) => self.clipboard = Some(clipboard),
+                Err(err) => {
+                    self.set_flash(format!("Couldn't access clipboard ({}):\n{}", err, text));
+                    return;
+                }
+            }
+        }
+        let clipboard = self.clipboard.as_mut().unwrap();
+        if let Err(err) = clipboard.set_text(text.clone()) {
+            self.set_flash(format!("Couldn't access clipboard ({}):\n{}", err, text));
+        }
+    }
+    // Switches between drawing control characters as control pictures, and as \u escapes
+    pub fn toggle_control_pictures(&mut self) {
+        self.display.control_pictures = !self.display.control_pictures;
+        self.redraw_all();
+    }
+    pub fn toggle_literal_glyphs(&mut self) {
+        self.display.literal_glyphs = !self.display.literal_glyphs;
+        self.redraw_all();
+    }
+    pub fn toggle_literal_newlines(&mut self) {
+        self.display.literal_newlines = !self.display.literal_newlines;
+        self.redraw_all();
+    }
+    pub fn set_result_limit(&mut self, limit: &str, layout: JexLayout) {
+        match limit.trim().parse::<usize>() {
+            Ok(limit) if limit > 0 => self.result_limit = limit,
+            _ => {
+                self.set_flash(format!("Not a result limit: {}", limit));
+                return;
+            }
+        }
+        for tree in self.views.trees.iter_mut() {
+            tree.recompute_children(layout.right, self.result_limit);
+        }
+    }
+    fn redraw_all(&mut self) {
+        for tree in self.views.trees.iter_mut() {
+            tree.set_display(self.display);
+        }
+    }
     pub fn show_help(&mut self) {
         let controls = README
             .rsplit("<!-- START CONTROLS POPUP -->\n")
@@ -308,19 +1135,275 @@ impl App {
             .unwrap();
         self.set_flash(controls.to_string());
     }
-    pub fn open_file(
+    // The keys of the object around the focused view's cursor, and the index of the cursor's
+    fn outline_keys(&self) -> Option<(Vec<String>, usize)> {
+        match &self.focused_view().frame().view {
+            View::Json(Some(view)) => view.cursor.sibling_keys(),
+            _ => None,
+        }
+    }
+    fn render_outline(&self, height: u16) -> Paragraph<'static> {
+        let (keys, cursor_index) = match self.outline_keys() {
+            Some(keys) => keys,
+            None => {
+                return Paragraph::new("Not in an object")
+                    .style(Style::default().add_modifier(Modifier::DIM))
+            }
+        };
+        let selected = self.outline_selection.unwrap_or(cursor_index);
+        let lines: Vec<Spans> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let style = if i == selected {
+                    self.theme.cursor
+                } else {
+                    self.theme.base
+                };
+                Spans::from(Span::styled(escaped_str(key), style))
+            })
+            .collect();
+        // Keeps the selection on screen
+        let scroll = (selected + 1).saturating_sub(height as usize) as u16;
+        Paragraph::new(lines).scroll((scroll, 0))
+    }
+    // Shows the outline and gives it focus, or hides it if it already has focus
+    pub fn toggle_outline(&mut self) {
+        if self.outline_selection.is_some() {
+            self.show_outline = false;
+            self.outline_selection = None;
+        } else {
+            self.show_outline = true;
+            self.outline_selection = Some(self.outline_keys().map_or(0, |(_, index)| index));
+        }
+    }
+    pub fn move_outline_selection(&mut self, down: bool) {
+        let len = match self.outline_keys() {
+            Some((keys, _)) => keys.len(),
+            None => return,
+        };
+        if let Some(selected) = self.outline_selection.as_mut() {
+            *selected = if down {
+                (*selected + 1).min(len - 1)
+            } else {
+                selected.saturating_sub(1)
+            };
+        }
+    }
+    // Moves the cursor to the key selected in the outline, and gives focus back to the view
+    pub fn select_outline_key(&mut self) {
+        let selected = match self.outline_selection.take() {
+            Some(selected) => selected,
+            None => return,
+        };
+        if let View::Json(Some(view)) = &mut self.focused_view_mut().frame().view {
+            if let Some(cursor) = view.cursor.sibling(selected) {
+                view.jump_to(cursor);
+            }
+        }
+    }
+    // Internals of the focused view, for chasing down memory and cursor bugs
+    #[cfg(feature = "dev-tools")]
+    pub fn show_debug_info(&mut self) {
+        let info = match &self.focused_view().frame().view {
+            View::Json(Some(view)) => format!(
+                "Refcount: {}\nKind: {}\nCursor frames: {}\nFolds: {}",
+                view.cursor.focus.refcount(),
+                view.cursor.describe_focus(view.display),
+                view.cursor.frames.len(),
+                view.folds.len()
+            ),
+            View::Json(None) => "Empty view".to_owned(),
+            View::Error(_) => "Error view".to_owned(),
+        };
+        self.set_flash(info);
+    }
+    // Copies the value under the cursor into a tree of its own, and shows it with its trivial
+    // query.
+    pub fn promote_cursor_to_root(&mut self, layout: JexLayout) {
+        let frame = match self.focused_view() {
+            ViewWithParent::Root { frame } | ViewWithParent::Child { frame, .. } => frame,
+        };
+        let (value, name) = match &frame.view {
+            View::Json(Some(view)) => (
+                view.cursor.focused_value(),
+                format!("{} {}", frame.name, view.cursor.to_jq_path()),
+            ),
+            _ => return,
+        };
+        let mut tree = ViewTree::new_from_values(vec![value], name, layout);
+        tree.set_display(self.display);
+        self.views.trees.push(tree);
+        self.focus_tree(self.views.trees.len() - 1);
+    }
+    // Shows the root of the given tree on the left, and its first query on the right.
+    pub fn focus_tree(&mut self, tree: usize) {
+        self.left_index = ViewForestIndex {
+            tree,
+            within_tree: ViewTreeIndex { path: Vec::new() },
+        };
+        let has_children = !self.views.trees[tree].children.is_empty();
+        self.right_index = ViewForestIndex {
+            tree,
+            within_tree: ViewTreeIndex {
+                path: if has_children { vec![0] } else { Vec::new() },
+            },
+        };
+        self.focus = Focus::Left;
+    }
+    // Rereads the file behind the focused tree from disk. Anything still loading from the old copy
+    // is dropped.
+    pub fn reload_focused(&mut self, layout: JexLayout) {
+        let tree_ix = self.focused_index().tree;
+        if self.views.trees[tree_ix].source.is_none() {
+            self.set_flash("This tree wasn't loaded from a file".to_owned());
+            return;
+        }
+        self.reload_tree(tree_ix, layout);
+    }
+    fn reload_tree(&mut self, tree_ix: usize, layout: JexLayout) {
+        self.pending_loads.retain(|(ix, _)| *ix != tree_ix);
+        let tree = &mut self.views.trees[tree_ix];
+        tree.reload(layout, self.result_limit);
+        // A view that couldn't be loaded before has nothing to take the display from
+        tree.set_display(self.display);
+        self.warn_about_lossy_integers();
+        self.warn_about_skipped_values();
+    }
+    // The files the trees were loaded from
+    pub fn sources(&self) -> impl Iterator<Item = &str> {
+        self.views
+            .trees
+            .iter()
+            .filter_map(|tree| tree.source.as_deref())
+    }
+    // Reloads every tree loaded from one of the changed files. Returns whether there were any.
+    pub fn reload_changed(&mut self, changed: &HashSet<PathBuf>, layout: JexLayout) -> bool {
+        let mut reloaded = false;
+        for tree_ix in 0..self.views.trees.len() {
+            let source = self.views.trees[tree_ix].source.as_deref();
+            if let Some(path) = source.and_then(absolute_path) {
+                if changed.contains(&path) {
+                    self.reload_tree(tree_ix, layout);
+                    reloaded = true;
+                }
+            }
+        }
+        reloaded
+    }
+    pub fn open_file(&mut self, path: String, layout: JexLayout) -> Result<(), Box<dyn Error>> {
+        let (new_tree, pending) = ViewTree::new_from_file(path, layout)?;
+        self.push_tree(new_tree, pending);
+        Ok(())
+    }
+    pub fn open_reader<R: io::Read + Send + 'static>(
         &mut self,
-        path: String,
+        r: R,
+        name: String,
         layout: JexLayout,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let f = fs::File::open(&path)?;
-        let r = io::BufReader::new(f);
-        let new_tree = ViewTree::new_from_reader(r, path, layout)?;
-        self.views.trees.push(new_tree);
+    ) -> io::Result<()> {
+        let (new_tree, pending) = ViewTree::new_from_reader(r, name, layout)?;
+        self.push_tree(new_tree, pending);
+        Ok(())
+    }
+    pub fn save_session(&self, path: &str) -> io::Result<()> {
+        session::save(self, path)
+    }
+    // Swaps the open trees for the ones in a saved session
+    pub fn load_session(&mut self, path: &str, layout: JexLayout) -> Result<(), Box<dyn Error>> {
+        let session = session::load(path, layout, self.result_limit)?;
+        self.views.trees = session.trees;
+        self.redraw_all();
+        self.pending_loads.clear();
+        self.focus_tree(0);
+        self.restore_panes(session.left_index, session.right_index, session.focus);
+        self.resize(layout);
+        self.warn_about_lossy_integers();
+        self.warn_about_skipped_values();
+        Ok(())
+    }
+    // Shows the given views, unless they don't exist
+    pub fn restore_panes(
+        &mut self,
+        left_index: Option<ViewForestIndex>,
+        right_index: Option<ViewForestIndex>,
+        focus: Focus,
+    ) {
+        if let Some(index) = left_index.filter(|index| self.views.index(index).is_some()) {
+            self.left_index = index;
+        }
+        if let Some(index) = right_index.filter(|index| self.views.index(index).is_some()) {
+            self.right_index = index;
+        }
+        self.focus = focus;
+    }
+    fn push_tree(&mut self, mut tree: ViewTree, pending: Option<PendingLoad>) {
+        tree.set_display(self.display);
+        self.views.trees.push(tree);
+        if let Some(load) = pending {
+            self.pending_loads.push((self.views.trees.len() - 1, load));
+        }
+        self.warn_about_lossy_integers();
+        self.warn_about_skipped_values();
         self.left_index = ViewForestIndex {
             tree: self.views.trees.len() - 1,
             within_tree: ViewTreeIndex { path: Vec::new() },
         };
-        Ok(())
+    }
+}
+
+fn view_title(frame: &NamedView, progress: Option<LoadProgress>, result_limit: usize) -> String {
+    match &frame.view {
+        View::Json(Some(view)) if view.has_pending_load => match progress {
+            Some(progress) => format!(
+                "{} (loading… {} values, {})",
+                frame.name,
+                view.values.len(),
+                progress
+            ),
+            None => format!("{} (loading…)", frame.name),
+        },
+        View::Json(Some(view)) if view.truncated => format!(
+            "{} (results truncated: showing first {})",
+            frame.name, result_limit
+        ),
+        // Files with several top level values, like json lines
+        View::Json(Some(view)) if view.values.len() > 1 => format!(
+            "{} (document {} of {})",
+            frame.name,
+            view.cursor.top_index + 1,
+            view.values.len()
+        ),
+        _ => frame.name.to_owned(),
+    }
+}
+
+// Cuts characters off the front of s until it fits in width, marking the cut with an ellipsis.
+fn truncate_left(s: &str, width: usize) -> String {
+    if s.width() <= width {
+        return s.to_string();
+    }
+    let mut out_width = 1; // For the ellipsis
+    let mut start = s.len();
+    for (i, c) in s.char_indices().rev() {
+        out_width += c.width().unwrap_or(0);
+        if out_width > width {
+            break;
+        }
+        start = i;
+    }
+    format!("…{}", &s[start..])
+}
+
+fn pane_at(layout: JexLayout, column: u16, row: u16) -> Option<(Focus, Rect)> {
+    let contains = |rect: Rect| {
+        (rect.left()..rect.right()).contains(&column) && (rect.top()..rect.bottom()).contains(&row)
+    };
+    if contains(layout.left) {
+        Some((Focus::Left, layout.left))
+    } else if contains(layout.right) {
+        Some((Focus::Right, layout.right))
+    } else {
+        None
     }
 }
@@ -4,11 +4,12 @@ use jex::{
     app::App,
     jq::{
         jv::JV,
-        query::{run_jq_query, JQ},
+        query::{run_jq_query, DEFAULT_RESULT_LIMIT, JQ},
     },
-    layout::JexLayout,
+    layout::{JexLayout, LayoutMode, DEFAULT_SPLIT_PERCENT},
     lines::escaped_str,
-    view_tree::View,
+    theme::Theme,
+    view_tree::{parse_ndjson, View},
 };
 use serde_json::{value::Value, Deserializer};
 use std::{fs, io, path::Path};
@@ -23,7 +24,7 @@ fn bench_jq_roundtrip(c: &mut Criterion) {
             .into_iter::<JV>()
             .collect::<Result<Vec<JV>, _>>()
             .expect("serde deserialization error");
-        bench.iter(|| run_jq_query(&content, &mut prog))
+        bench.iter(|| run_jq_query(&content, &mut prog, DEFAULT_RESULT_LIMIT))
     });
 }
 
@@ -40,6 +41,34 @@ fn bench_load_direct(c: &mut Criterion) {
     });
 }
 
+// example.json repeated on every line, to compare single and multi threaded parsing of ndjson
+fn example_ndjson() -> String {
+    let s = fs::read_to_string("testdata/example.json").expect("cannot read file");
+    let value: Value = serde_json::from_str(&s).expect("serde deserialization error");
+    let line = serde_json::to_string(&value).expect("serde serialization error");
+    (0..100).map(|_| format!("{}\n", line)).collect()
+}
+
+fn bench_load_ndjson_direct(c: &mut Criterion) {
+    c.bench_function("bench_load_ndjson_direct", |bench| {
+        let s = example_ndjson();
+        bench.iter(|| {
+            let content: Vec<JV> = Deserializer::from_str(&s)
+                .into_iter::<JV>()
+                .collect::<Result<Vec<JV>, _>>()
+                .expect("serde deserialization error");
+            content
+        })
+    });
+}
+
+fn bench_load_ndjson_parallel(c: &mut Criterion) {
+    c.bench_function("bench_load_ndjson_parallel", |bench| {
+        let s = example_ndjson();
+        bench.iter(|| parse_ndjson(&s).expect("serde deserialization error"))
+    });
+}
+
 fn bench_load_indirect(c: &mut Criterion) {
     c.bench_function("bench_load_indirect", |bench| {
         let s = fs::read_to_string("testdata/example.json").expect("cannot read file");
@@ -67,7 +96,13 @@ fn bench_scroll_long_string(c: &mut Criterion) {
         let f = fs::File::open(&path).expect("couldn't open test file");
         let r = io::BufReader::new(f);
         let rect = Rect::new(0, 0, 100, 100);
-        let initial_layout = JexLayout::new(rect, false);
+        let initial_layout = JexLayout::new(
+            rect,
+            false,
+            false,
+            LayoutMode::SideBySide,
+            DEFAULT_SPLIT_PERCENT,
+        );
         let mut app =
             App::new(r, path.to_string(), initial_layout).expect("couldn't initalize app");
         let view = if let View::Json(Some(view)) = &mut app.focused_view_mut().view {
@@ -88,11 +123,17 @@ fn bench_render_long_string(c: &mut Criterion) {
         let f = fs::File::open(&path).expect("couldn't open test file");
         let r = io::BufReader::new(f);
         let rect = Rect::new(0, 0, 100, 100);
-        let initial_layout = JexLayout::new(rect, false);
+        let initial_layout = JexLayout::new(
+            rect,
+            false,
+            false,
+            LayoutMode::SideBySide,
+            DEFAULT_SPLIT_PERCENT,
+        );
         let mut app =
             App::new(r, path.to_string(), initial_layout).expect("couldn't initalize app");
         let view = &mut app.focused_view_mut().view;
-        bench.iter(|| view.render(rect, true))
+        bench.iter(|| view.render(rect, true, false, &Theme::default()))
     });
 }
 
@@ -129,6 +170,8 @@ criterion_group!(
     targets =
         bench_jq_roundtrip,
         bench_load_direct,
+        bench_load_ndjson_direct,
+        bench_load_ndjson_parallel,
         bench_load_indirect,
         bench_load_native,
         bench_scroll_long_string,
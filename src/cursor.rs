@@ -1,11 +1,30 @@
 use crate::{
-    jq::jv::{JVArray, JVObject, JVString, OwnedObjectIterator, JV},
-    lines::{Leaf, LeafContent, LineCursor, UnstyledSpans},
+    jq::{
+        jv::{JVArray, JVObject, JVString, OwnedObjectIterator, PathSegment, JV},
+        query::JQ,
+    },
+    lines::{
+        escaped_str, format_number, DisplayOptions, FoldedSize, Leaf, LeafContent, LineCursor,
+        UnstyledSpans,
+    },
+    theme::Theme,
 };
 use log::trace;
 use regex::Regex;
-use std::{borrow::Cow, cmp::Ordering, collections::HashSet, fmt, rc::Rc};
-use tui::{layout::Rect, text::Spans};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt,
+    fmt::Write,
+    rc::Rc,
+};
+use tui::{
+    layout::Rect,
+    style::Style,
+    text::{Span, Spans},
+};
+use unicode_width::UnicodeWidthStr;
 
 // Requirements:
 // * Produce the current line
@@ -13,6 +32,23 @@ use tui::{layout::Rect, text::Spans};
 // * (Optionally, for searching): Step backwards
 // * Can be "dehydrated" into something hashable for storing folds (other metadata?)
 
+// Lines to draw in a different style, by the path of the value on them, and the style to patch
+// over them.
+pub type Highlights<'a> = Option<(&'a HashSet<ValuePath>, Style)>;
+
+// Folded containers, by (top index, path), and how each one is drawn.
+pub type Folds = HashMap<(usize, Vec<usize>), Fold>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fold {
+    // Collapsed, showing its number of children
+    Children,
+    // Collapsed, showing its total number of descendants
+    Descendants(usize),
+    // Drawn in full on a single line, because it's short enough to fit on one
+    Inline,
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
 pub enum FocusPosition {
     Start,
@@ -277,28 +313,38 @@ impl CursorFrame {
 pub struct GlobalCursor {
     pub value_cursor: LeafCursor,
     pub line_cursor: LineCursor,
+    // How the lines are drawn, kept for rendering the lines it moves onto
+    pub display: DisplayOptions,
 }
 impl GlobalCursor {
-    pub fn new(jsons: Rc<[JV]>, width: u16, folds: &HashSet<(usize, Vec<usize>)>) -> Option<Self> {
+    pub fn new(
+        jsons: Rc<[JV]>,
+        width: u16,
+        folds: &Folds,
+        display: DisplayOptions,
+    ) -> Option<Self> {
         let cursor = LeafCursor::new(jsons)?;
         let line = cursor.current_line(folds, width);
-        let line_cursor = LineCursor::new_at_start(line.render(), width);
+        let line_cursor = LineCursor::new_at_start(line.render(display), width);
         Some(GlobalCursor {
             value_cursor: cursor,
             line_cursor,
+            display,
         })
     }
     pub fn new_end(
         jsons: Rc<[JV]>,
         width: u16,
-        folds: &HashSet<(usize, Vec<usize>)>,
+        folds: &Folds,
+        display: DisplayOptions,
     ) -> Option<Self> {
         let cursor = LeafCursor::new_end(jsons)?;
         let line = cursor.current_line(folds, width);
-        let line_cursor = LineCursor::new_at_start(line.render(), width);
+        let line_cursor = LineCursor::new_at_start(line.render(display), width);
         Some(GlobalCursor {
             value_cursor: cursor,
             line_cursor,
+            display,
         })
     }
     pub fn current_line(&self) -> UnstyledSpans {
@@ -306,30 +352,96 @@ impl GlobalCursor {
             .current()
             .expect("Global cursor should not be able to have invalid line cursor")
     }
+    // If gutter_width is set, each line is prefixed with its line number, padded to that width.
+    // The first h_scroll columns of each line are cut off, and lines still wider than clip_width
+    // (the columns actually on screen) end in an ellipsis.
+    #[allow(clippy::too_many_arguments)]
     pub fn render_lines(
         &mut self,
         cursor: Option<&LeafCursor>,
-        folds: &HashSet<(usize, Vec<usize>)>,
+        folds: &Folds,
         rect: Rect,
+        gutter_width: Option<u16>,
+        h_scroll: u16,
+        clip_width: u16,
+        highlights: Highlights,
+        theme: &Theme,
     ) -> Vec<Spans<'static>> {
         let mut lines = Vec::with_capacity(rect.height as usize);
         self.resize_to(rect);
-        lines.push(
-            self.current_line()
-                .to_spans(Some(&self.value_cursor) == cursor),
-        );
+        let mut line_number = match gutter_width {
+            Some(_) => self.value_cursor.line_number(),
+            None => 0,
+        };
+        lines.push(self.line_spans(
+            cursor,
+            gutter_width,
+            h_scroll,
+            clip_width,
+            highlights,
+            line_number,
+            theme,
+        ));
         while lines.len() < rect.height as usize {
+            let lines_spanned = match gutter_width {
+                Some(_) => self.value_cursor.lines_spanned(folds),
+                None => 0,
+            };
             if let None = self.advance(folds, rect.width) {
                 break;
             };
-            lines.push(
-                self.current_line()
-                    .to_spans(Some(&self.value_cursor) == cursor),
-            );
+            if self.line_cursor.current_line() == Some(0) {
+                line_number += lines_spanned;
+            }
+            lines.push(self.line_spans(
+                cursor,
+                gutter_width,
+                h_scroll,
+                clip_width,
+                highlights,
+                line_number,
+                theme,
+            ));
         }
         lines
     }
-    pub fn advance(&mut self, folds: &HashSet<(usize, Vec<usize>)>, width: u16) -> Option<()> {
+    #[allow(clippy::too_many_arguments)]
+    fn line_spans(
+        &self,
+        cursor: Option<&LeafCursor>,
+        gutter_width: Option<u16>,
+        h_scroll: u16,
+        clip_width: u16,
+        highlights: Highlights,
+        line_number: usize,
+        theme: &Theme,
+    ) -> Spans<'static> {
+        let is_cursor = Some(&self.value_cursor) == cursor;
+        let mut spans = self
+            .current_line()
+            .skip_columns(h_scroll)
+            .truncate_columns(clip_width)
+            .to_spans(is_cursor, theme);
+        if let Some((paths, style)) = highlights {
+            // The cursor takes priority, so you can still see where it is
+            if !is_cursor && paths.contains(&self.value_cursor.to_path()) {
+                for span in spans.0.iter_mut() {
+                    span.style = span.style.patch(style);
+                }
+            }
+        }
+        if let Some(gutter_width) = gutter_width {
+            // Only the first row of a wrapped line gets a number
+            let gutter = if self.line_cursor.current_line() == Some(0) {
+                format!("{:>1$} ", line_number + 1, gutter_width as usize - 1)
+            } else {
+                " ".repeat(gutter_width as usize)
+            };
+            spans.0.insert(0, Span::styled(gutter, theme.background));
+        }
+        spans
+    }
+    pub fn advance(&mut self, folds: &Folds, width: u16) -> Option<()> {
         trace!("Advancing global cursor (width={}): {:#?}", width, self);
         let lc = &mut self.line_cursor;
         lc.move_next();
@@ -341,11 +453,11 @@ impl GlobalCursor {
         }
         self.value_cursor.advance(folds)?;
         let line = self.value_cursor.current_line(folds, width);
-        self.line_cursor = LineCursor::new_at_start(line.render(), width);
+        self.line_cursor = LineCursor::new_at_start(line.render(self.display), width);
         trace!("Advanced global cursor {:#?}", self);
         Some(())
     }
-    pub fn regress(&mut self, folds: &HashSet<(usize, Vec<usize>)>, width: u16) -> Option<()> {
+    pub fn regress(&mut self, folds: &Folds, width: u16) -> Option<()> {
         let lc = &mut self.line_cursor;
         lc.move_prev();
         if lc.valid() {
@@ -355,7 +467,7 @@ impl GlobalCursor {
         }
         self.value_cursor.regress(folds)?;
         let line = self.value_cursor.current_line(folds, width);
-        self.line_cursor = LineCursor::new_at_end(line.render(), width);
+        self.line_cursor = LineCursor::new_at_end(line.render(self.display), width);
         Some(())
     }
     pub fn to_path(&self) -> GlobalPath {
@@ -371,6 +483,12 @@ impl GlobalCursor {
     pub fn resize_to(&mut self, rect: Rect) {
         self.line_cursor.set_width(rect.width);
     }
+    // Renders the top line again, after the way characters or values are drawn changed
+    pub fn redraw(&mut self, folds: &Folds, width: u16, display: DisplayOptions) {
+        self.display = display;
+        let line = self.value_cursor.current_line(folds, width);
+        self.line_cursor.set_content(line.render(display));
+    }
     pub fn at_line_end(&self) -> bool {
         self.line_cursor
             .at_end()
@@ -426,23 +544,23 @@ impl LeafCursor {
         }
     }
     pub fn from_path(jsons: Rc<[JV]>, path: &ValuePath) -> Self {
-        let mut focus = jsons[path.top_index].clone();
+        Self::try_from_path(jsons, path).expect("Shape of path does not match shape of jsons")
+    }
+    // Returns None if the path doesn't exist in jsons
+    pub fn try_from_path(jsons: Rc<[JV]>, path: &ValuePath) -> Option<Self> {
+        let mut focus = jsons.get(path.top_index)?.clone();
         let mut frames = Vec::new();
         for &index in path.frames.iter() {
             match focus {
                 JV::Array(arr) => {
                     let json = arr.clone();
-                    focus = arr
-                        .get(index as i32)
-                        .expect("Shape of path does not match shape of jsons");
+                    focus = arr.get(index as i32)?;
                     frames.push(CursorFrame::Array { index, json });
                 }
                 JV::Object(obj) => {
                     let json = obj.clone();
                     let mut iterator = obj.clone().into_iter();
-                    let (key, new_focus) = iterator
-                        .nth(index)
-                        .expect("Shape of path does not match shape of jsons");
+                    let (key, new_focus) = iterator.nth(index)?;
                     focus = new_focus;
                     frames.push(CursorFrame::Object {
                         index,
@@ -451,16 +569,21 @@ impl LeafCursor {
                         iterator,
                     });
                 }
-                _ => panic!("Shape of path does not match shape of jsons"),
+                _ => return None,
             }
         }
-        LeafCursor {
+        // A leaf where there used to be a container, or vice versa
+        let is_container = matches!(focus, JV::Array(_) | JV::Object(_));
+        if is_container == (path.focus_position == FocusPosition::Value) {
+            return None;
+        }
+        Some(LeafCursor {
             jsons,
             top_index: path.top_index,
             frames,
             focus,
             focus_position: path.focus_position,
-        }
+        })
     }
     pub fn current_key(&self) -> Option<JVString> {
         match self.focus_position {
@@ -472,20 +595,415 @@ impl LeafCursor {
             },
         }
     }
+    // The keys of the object directly around the focus, in order, and the index of the focused
+    // one. None if the focus isn't inside an object.
+    pub fn sibling_keys(&self) -> Option<(Vec<String>, usize)> {
+        match self.frames.last()? {
+            CursorFrame::Array { .. } => None,
+            CursorFrame::Object { index, json, .. } => {
+                let keys = json
+                    .clone()
+                    .into_iter()
+                    .map(|(key, _)| key.value().to_owned())
+                    .collect();
+                Some((keys, *index))
+            }
+        }
+    }
+    // A cursor on the first line of the top_index'th top level value
+    pub fn document_start(&self, top_index: usize) -> Option<Self> {
+        LeafCursor::at_indices(self.jsons.clone(), top_index, Vec::new())
+    }
+    // Moves to the start of the next top level value, or returns None if this is the last one
+    pub fn next_document(&mut self) -> Option<()> {
+        *self = self.document_start(self.top_index + 1)?;
+        Some(())
+    }
+    // Moves to the start of the previous top level value, or returns None if this is the first one
+    pub fn prev_document(&mut self) -> Option<()> {
+        *self = self.document_start(self.top_index.checked_sub(1)?)?;
+        Some(())
+    }
+    // A cursor on the index'th child of the container around the focus
+    pub fn sibling(&self, index: usize) -> Option<Self> {
+        let mut frames: Vec<usize> = self.frames.iter().map(CursorFrame::index).collect();
+        *frames.last_mut()? = index;
+        LeafCursor::at_indices(self.jsons.clone(), self.top_index, frames)
+    }
+    // For containers, this is the whole container regardless of whether we're focused on the
+    // opening or closing bracket.
+    pub fn focused_value(&self) -> JV {
+        self.focus.clone()
+    }
+    // A jq filter that extracts the focused value from its top level json
+    pub fn to_jq_path(&self) -> String {
+        let mut out = String::new();
+        for frame in self.frames.iter() {
+            match frame {
+                CursorFrame::Array { index, .. } => {
+                    if out.is_empty() {
+                        out.push('.');
+                    }
+                    write!(out, "[{}]", index).unwrap();
+                }
+                CursorFrame::Object { key, .. } => out.push_str(&format_jq_key(key.value())),
+            }
+        }
+        if out.is_empty() {
+            out.push('.');
+        }
+        out
+    }
+    // The jq path and the compact focused value together, like `.users[2].email = "a@b.com"`
+    pub fn to_path_assignment(&self) -> String {
+        format!(
+            "{} = {}",
+            self.to_jq_path(),
+            self.focused_value().to_compact_string()
+        )
+    }
+    // Moves to the first value in the current container (or the first top level value).
+    pub fn first_sibling(&mut self) {
+        match self.frames.pop() {
+            None => {
+                self.top_index = 0;
+                self.focus = self.jsons[0].clone();
+            }
+            Some(CursorFrame::Array { json, .. }) => {
+                self.focus = json.get(0).expect("Array with a child should be nonempty");
+                self.frames.push(CursorFrame::Array { index: 0, json });
+            }
+            Some(CursorFrame::Object { json, .. }) => {
+                let mut iterator = json.clone().into_iter();
+                let (key, focus) = iterator
+                    .next()
+                    .expect("Object with a child should be nonempty");
+                self.focus = focus;
+                self.frames.push(CursorFrame::Object {
+                    index: 0,
+                    key,
+                    json,
+                    iterator,
+                });
+            }
+        }
+        self.focus_position = FocusPosition::starting(&self.focus);
+    }
+    // Moves to the last value in the current container (or the last top level value).
+    pub fn last_sibling(&mut self) {
+        match self.frames.pop() {
+            None => {
+                self.top_index = self.jsons.len() - 1;
+                self.focus = self.jsons[self.top_index].clone();
+            }
+            Some(CursorFrame::Array { json, .. }) => {
+                let index = json.len() as usize - 1;
+                self.focus = json
+                    .get(index as i32)
+                    .expect("Array with a child should be nonempty");
+                self.frames.push(CursorFrame::Array { index, json });
+            }
+            Some(CursorFrame::Object { json, .. }) => {
+                let index = json.len() as usize - 1;
+                let mut iterator = json.clone().into_iter();
+                let (key, focus) = iterator
+                    .nth(index)
+                    .expect("Object with a child should be nonempty");
+                self.focus = focus;
+                self.frames.push(CursorFrame::Object {
+                    index,
+                    key,
+                    json,
+                    iterator,
+                });
+            }
+        }
+        self.focus_position = FocusPosition::starting(&self.focus);
+    }
+    // The top level values with the focused value swapped for `value`, or removed if that's None.
+    // Values are immutable, so every ancestor gets rebuilt on the way up.
+    fn rebuild_values(&self, value: Option<JV>) -> Vec<JV> {
+        let mut replacement = value;
+        for frame in self.frames.iter().rev() {
+            let parent: JV = match (frame, replacement) {
+                (CursorFrame::Array { index, json }, Some(value)) => {
+                    let mut arr = json.clone();
+                    arr.set(*index as i32, value);
+                    arr.into()
+                }
+                (CursorFrame::Array { index, json }, None) => {
+                    let mut arr = JVArray::new();
+                    for (i, child) in json.iter().enumerate() {
+                        if i != *index {
+                            arr.set(arr.len(), child);
+                        }
+                    }
+                    arr.into()
+                }
+                (CursorFrame::Object { key, json, .. }, Some(value)) => {
+                    let mut obj = json.clone();
+                    obj.set(key.value(), value);
+                    obj.into()
+                }
+                (CursorFrame::Object { key, json, .. }, None) => {
+                    let mut obj = JVObject::new();
+                    for (k, child) in json.clone() {
+                        if k != *key {
+                            obj.set(k.value(), child);
+                        }
+                    }
+                    obj.into()
+                }
+            };
+            replacement = Some(parent);
+        }
+        let mut values = self.jsons.to_vec();
+        match replacement {
+            Some(value) => values[self.top_index] = value,
+            None => {
+                values.remove(self.top_index);
+            }
+        }
+        values
+    }
+    // A cursor on the first line of whatever is at the given indices, if anything
+    pub fn at_indices(jsons: Rc<[JV]>, top_index: usize, frames: Vec<usize>) -> Option<Self> {
+        // Only one of these will match the shape of the value
+        [FocusPosition::Start, FocusPosition::Value]
+            .iter()
+            .find_map(|&focus_position| {
+                let path = ValuePath {
+                    top_index,
+                    frames: frames.clone(),
+                    focus_position,
+                };
+                LeafCursor::try_from_path(jsons.clone(), &path)
+            })
+    }
+    // Replaces the focused value, returning a cursor on the new value in the new top level values.
+    pub fn replace_focus(&self, value: JV) -> Self {
+        let path = ValuePath {
+            focus_position: FocusPosition::starting(&value),
+            ..self.to_path()
+        };
+        let jsons: Rc<[JV]> = self.rebuild_values(Some(value)).into();
+        LeafCursor::from_path(jsons, &path)
+    }
+    // Renames the key the focused value is under, returning a cursor on the same value in the new
+    // top level values. Returns None if the focus isn't under a key, or new_key is already taken.
+    // jq objects iterate in hash order, so the siblings can get shuffled: the second return value
+    // maps each of the old indices of the object's children to its new one.
+    pub fn rename_key(&self, new_key: &str) -> Option<(Self, Vec<usize>)> {
+        let (key, json) = match self.frames.last() {
+            Some(CursorFrame::Object { key, json, .. }) => (key, json),
+            _ => return None,
+        };
+        if key.value() != new_key && json.get(new_key).is_some() {
+            return None;
+        }
+        let mut renamed = JVObject::new();
+        for (k, child) in json.clone() {
+            if k == *key {
+                renamed.set(new_key, child);
+            } else {
+                renamed.set(k.value(), child);
+            }
+        }
+        let new_positions: HashMap<String, usize> = renamed
+            .iter()
+            .enumerate()
+            .map(|(i, (k, _))| (k.to_owned(), i))
+            .collect();
+        let new_indices: Vec<usize> = json
+            .iter()
+            .map(|(k, _)| {
+                let k = if k == key.value() { new_key } else { k };
+                new_positions[k]
+            })
+            .collect();
+        let mut parent = self.clone();
+        parent.frames.pop();
+        parent.focus = json.clone().into();
+        parent.focus_position = FocusPosition::Start;
+        let jsons = parent.replace_focus(renamed.into()).jsons;
+        let (top_index, mut frames) = self.to_path().strip_position();
+        let index = frames.last_mut().expect("Focus is under a key");
+        *index = new_indices[*index];
+        let path = ValuePath {
+            top_index,
+            frames,
+            focus_position: self.focus_position,
+        };
+        Some((LeafCursor::from_path(jsons, &path), new_indices))
+    }
+    // A cursor on the opening line of the container a new child would go into: the focus, if it's a
+    // container, or else the container it's in. None for a top level scalar.
+    pub fn enclosing_container(&self) -> Option<Self> {
+        let mut container = self.clone();
+        match &self.focus {
+            JV::Array(_) | JV::Object(_) => {}
+            _ => {
+                let frame = container.frames.pop()?;
+                container.focus = match frame {
+                    CursorFrame::Array { json, .. } => json.into(),
+                    CursorFrame::Object { json, .. } => json.into(),
+                };
+            }
+        }
+        container.focus_position = FocusPosition::Start;
+        Some(container)
+    }
+    // Adds key to the focused object, returning a cursor on the new value in the new top level
+    // values. Returns None if the focus isn't an object, or already has key. Like rename_key, the
+    // second return value maps the old indices of the object's children to their new ones.
+    pub fn insert_key(&self, key: &str, value: JV) -> Option<(Self, Vec<usize>)> {
+        let json = match &self.focus {
+            JV::Object(obj) if !obj.contains_key(key) => obj,
+            _ => return None,
+        };
+        let mut inserted = json.clone();
+        inserted.set(key, value);
+        let new_positions: HashMap<&str, usize> = inserted
+            .iter()
+            .enumerate()
+            .map(|(i, (k, _))| (k, i))
+            .collect();
+        let new_indices: Vec<usize> = json.iter().map(|(k, _)| new_positions[k]).collect();
+        let (top_index, mut frames) = self.to_path().strip_position();
+        frames.push(new_positions[key]);
+        let jsons = self.replace_focus(inserted.clone().into()).jsons;
+        let cursor =
+            LeafCursor::at_indices(jsons, top_index, frames).expect("Inserted key should exist");
+        Some((cursor, new_indices))
+    }
+    // Adds value to the end of the focused array, returning a cursor on it in the new top level
+    // values. Returns None if the focus isn't an array.
+    pub fn append_element(&self, value: JV) -> Option<Self> {
+        let mut appended = match &self.focus {
+            JV::Array(arr) => arr.clone(),
+            _ => return None,
+        };
+        let index = appended.len();
+        appended.set(index, value);
+        let (top_index, mut frames) = self.to_path().strip_position();
+        frames.push(index as usize);
+        let jsons = self.replace_focus(appended.into()).jsons;
+        LeafCursor::at_indices(jsons, top_index, frames)
+    }
+    // Removes the focused value, returning a cursor on what's left in its place: the next sibling,
+    // or else the previous one, or else the parent. Returns None if nothing would be left at all.
+    pub fn delete_focus(&self) -> Option<Self> {
+        if self.frames.is_empty() && self.jsons.len() == 1 {
+            return None;
+        }
+        let jsons: Rc<[JV]> = self.rebuild_values(None).into();
+        let (top_index, frames) = self.to_path().strip_position();
+        if let Some(cursor) = LeafCursor::at_indices(jsons.clone(), top_index, frames.clone()) {
+            return Some(cursor);
+        }
+        let mut previous = frames.clone();
+        match previous.last_mut() {
+            Some(index) if *index > 0 => *index -= 1,
+            Some(_) => {
+                let mut parent = frames;
+                parent.pop();
+                return LeafCursor::at_indices(jsons, top_index, parent);
+            }
+            None => return LeafCursor::at_indices(jsons, top_index - 1, previous),
+        }
+        LeafCursor::at_indices(jsons, top_index, previous)
+    }
+    // Index of the current line in the document with nothing folded
+    pub fn line_number(&self) -> usize {
+        let mut line_number: usize = self.jsons[..self.top_index]
+            .iter()
+            .map(json_line_count)
+            .sum();
+        for frame in self.frames.iter() {
+            // The parent's opening bracket
+            line_number += 1;
+            line_number += match frame {
+                CursorFrame::Array { index, json } => json
+                    .iter()
+                    .take(*index)
+                    .map(|child| json_line_count(&child))
+                    .sum::<usize>(),
+                CursorFrame::Object { index, json, .. } => json
+                    .values()
+                    .take(*index)
+                    .map(|child| json_line_count(&child))
+                    .sum::<usize>(),
+            };
+        }
+        if self.focus_position == FocusPosition::End {
+            line_number += json_line_count(&self.focus) - 1;
+        }
+        line_number
+    }
+    // Number of lines of the unfolded document covered by the current line
+    pub fn lines_spanned(&self, folds: &Folds) -> usize {
+        let is_folded = folds.contains_key(&self.to_path().strip_position());
+        match self.focus_position {
+            FocusPosition::Start if is_folded => json_line_count(&self.focus),
+            _ => 1,
+        }
+    }
+    // The type of the focused value, and its size if it has one, for the status line
+    pub fn describe_focus(&self, display: DisplayOptions) -> String {
+        fn count(n: usize, singular: &str, plural: &str) -> String {
+            format!("{} {}", n, if n == 1 { singular } else { plural })
+        }
+        match &self.focus {
+            JV::Null(_) => "null".to_owned(),
+            JV::Bool(_) => "boolean".to_owned(),
+            JV::Number(_) => "number".to_owned(),
+            JV::String(s) => {
+                let chars = s.value().chars().count();
+                let width = display.escaped_width(s.value());
+                let mut out = format!("string, {}", count(chars, "char", "chars"));
+                // Escapes and wide characters take up more room than they count for
+                if width != chars {
+                    out.push_str(&format!(", {}", count(width, "column", "columns")));
+                }
+                out
+            }
+            JV::Array(arr) => format!("array, {}", count(arr.len() as usize, "item", "items")),
+            JV::Object(obj) => format!("object, {}", count(obj.len() as usize, "key", "keys")),
+        }
+    }
+    // Human readable version of to_jq_path, for display
+    pub fn breadcrumbs(&self) -> Vec<String> {
+        let mut out = vec!["root".to_string()];
+        for frame in self.frames.iter() {
+            match frame {
+                CursorFrame::Array { index, .. } => out.push(format!("[{}]", index)),
+                CursorFrame::Object { key, .. } => out.push(format_jq_key(key.value())),
+            }
+        }
+        out
+    }
     pub fn current_indent(&self, width: u16) -> u16 {
         let desired_indent = (self.frames.len() * 2) as u16;
         std::cmp::min(desired_indent, width - 7)
     }
-    pub fn current_line<'a>(&'a self, folds: &HashSet<(usize, Vec<usize>)>, width: u16) -> Leaf {
+    pub fn current_line<'a>(&'a self, folds: &Folds, width: u16) -> Leaf {
         use FocusPosition::*;
-        let folded = folds.contains(&self.to_path().strip_position());
+        let fold = folds.get(&self.to_path().strip_position());
+        let folded = fold.is_some();
+        let size = |children: i32| match fold {
+            Some(Fold::Descendants(descendants)) => FoldedSize::Descendants(*descendants),
+            _ => FoldedSize::Children(children as usize),
+        };
         let content = match (&self.focus, self.focus_position, folded) {
+            (JV::Object(_) | JV::Array(_), Start, true) if fold == Some(&Fold::Inline) => {
+                LeafContent::Inline(self.focus.clone())
+            }
             (JV::Object(_), Start, false) => LeafContent::ObjectStart,
             (JV::Object(_), End, false) => LeafContent::ObjectEnd,
-            (JV::Object(obj), Start, true) => LeafContent::FoldedObject(obj.len() as usize),
+            (JV::Object(obj), Start, true) => LeafContent::FoldedObject(size(obj.len())),
             (JV::Array(_), Start, false) => LeafContent::ArrayStart,
             (JV::Array(_), End, false) => LeafContent::ArrayEnd,
-            (JV::Array(arr), Start, true) => LeafContent::FoldedArray(arr.len() as usize),
+            (JV::Array(arr), Start, true) => LeafContent::FoldedArray(size(arr.len())),
             (JV::Null(_), Value, _) => LeafContent::Null,
             (JV::Bool(b), Value, _) => LeafContent::Bool(b.value()),
             (JV::Number(x), Value, _) => LeafContent::Number(x.value()),
@@ -494,7 +1012,8 @@ impl LeafCursor {
         };
         let key = self.current_key();
         let comma = match self.focus_position {
-            FocusPosition::Start => false,
+            // A folded container is all on one line, so it gets the comma its closing bracket would
+            FocusPosition::Start if !folded => false,
             _ => match self.frames.last() {
                 None => false,
                 Some(CursorFrame::Array { json, index, .. }) => *index != json.len() as usize - 1,
@@ -509,7 +1028,7 @@ impl LeafCursor {
             indent,
         }
     }
-    pub fn advance(&mut self, folds: &HashSet<(usize, Vec<usize>)>) -> Option<()> {
+    pub fn advance(&mut self, folds: &Folds) -> Option<()> {
         // This gets pretty deep into nested match statements, so an english guide to what's going
         // on here.
         // Cases:
@@ -521,7 +1040,7 @@ impl LeafCursor {
         //     * and there are more leaves, so focus on the next leaf.
         //     * and there are no more leaves, so pop the frame, focus on the parent's close bracket
         // * We're focused on a close bracket. Advance the parent as if we were focused on a leaf.
-        let is_folded = folds.contains(&self.to_path().strip_position());
+        let is_folded = folds.contains_key(&self.to_path().strip_position());
         match self.focus_position {
             FocusPosition::Start if !is_folded => {
                 let (new_frame, new_focus, new_focus_position) = open_container(self.focus.clone());
@@ -549,7 +1068,7 @@ impl LeafCursor {
         }
         Some(())
     }
-    pub fn regress(&mut self, folds: &HashSet<(usize, Vec<usize>)>) -> Option<()> {
+    pub fn regress(&mut self, folds: &Folds) -> Option<()> {
         // Pretty mechanical opposite of advance
         match self.focus_position {
             FocusPosition::End => {
@@ -577,7 +1096,7 @@ impl LeafCursor {
                 }
             },
         }
-        let is_folded = folds.contains(&self.to_path().strip_position());
+        let is_folded = folds.contains_key(&self.to_path().strip_position());
         if is_folded {
             self.focus_position = FocusPosition::Start;
         }
@@ -587,65 +1106,233 @@ impl LeafCursor {
         match &self.focus {
             JV::Null(_) => Some("null".into()),
             JV::Bool(b) => Some(b.value().to_string().into()),
-            JV::Number(x) => Some(x.value().to_string().into()),
+            JV::Number(x) => Some(format_number(x.value()).into()),
             JV::String(s) => Some(s.value().into()),
             _ => None,
         }
     }
+    // The focused leaf as it would be written in json, or None for containers
+    pub fn leaf_to_json(&self) -> Option<String> {
+        match &self.focus {
+            JV::String(s) => {
+                Some(serde_json::to_string(s.value()).expect("Strings should always serialize"))
+            }
+            _ => self.leaf_to_string().map(Cow::into_owned),
+        }
+    }
+    // The focused string or number, as an object key it might refer to
+    pub fn reference_key(&self) -> Option<String> {
+        match &self.focus {
+            JV::Number(_) | JV::String(_) => self.leaf_to_string().map(Cow::into_owned),
+            _ => None,
+        }
+    }
+    // The first value in the current top level value (in document order) whose object key is key
+    pub fn find_key(&self, key: &str) -> Option<ValuePath> {
+        let mock_folds = Folds::new();
+        let top = &self.jsons[self.top_index];
+        let start = ValuePath {
+            top_index: self.top_index,
+            frames: Vec::new(),
+            focus_position: FocusPosition::starting(top),
+        };
+        let mut cursor = LeafCursor::from_path(self.jsons.clone(), &start);
+        loop {
+            if cursor.current_key().is_some_and(|k| k.value() == key) {
+                return Some(cursor.to_path());
+            }
+            cursor.advance(&mock_folds)?;
+            if cursor.top_index != self.top_index {
+                return None;
+            }
+        }
+    }
+    // Resolves a JSON Pointer fragment like #/components/schemas/Foo against the current top
+    // level value
+    pub fn resolve_pointer(&self, pointer: &str) -> Option<ValuePath> {
+        let mut focus = self.jsons[self.top_index].clone();
+        let mut frames = Vec::new();
+        for token in parse_json_pointer(pointer)? {
+            let (index, segment) = match &focus {
+                JV::Array(_) => {
+                    // Pointers don't allow signs or leading zeros in indices
+                    let is_digits = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+                    if !is_digits || (token.starts_with('0') && token != "0") {
+                        return None;
+                    }
+                    let index: i32 = token.parse().ok()?;
+                    (index as usize, PathSegment::Index(index))
+                }
+                JV::Object(_) => (object_child(&focus, &token)?.0, PathSegment::Key(token)),
+                _ => return None,
+            };
+            focus = focus.get_path(&[segment])?;
+            frames.push(index);
+        }
+        Some(ValuePath {
+            top_index: self.top_index,
+            frames,
+            focus_position: FocusPosition::starting(&focus),
+        })
+    }
     // TODO: do something more efficient
     pub fn matches_path(&self, path: &ValuePath) -> bool {
         self.to_path() == *path
     }
-    pub fn regex_matches(&self, re: &Regex) -> bool {
-        if let Some(leaf) = self.leaf_to_string() {
-            if re.is_match(&leaf) {
-                return true;
-            }
+    pub fn regex_matches(&self, re: &Regex, scope: SearchScope) -> bool {
+        (scope != SearchScope::Keys && self.leaf_matches(re))
+            || (scope != SearchScope::Values && self.key_matches(re))
+    }
+    fn leaf_matches(&self, re: &Regex) -> bool {
+        match self.leaf_to_string() {
+            Some(leaf) => re.is_match(&leaf),
+            None => false,
         }
-        if let Some(CursorFrame::Object { key, .. }) = self.frames.last() {
-            if re.is_match(key.value()) {
-                return true;
-            }
+    }
+    fn key_matches(&self, re: &Regex) -> bool {
+        match self.frames.last() {
+            Some(CursorFrame::Object { key, .. }) => re.is_match(key.value()),
+            _ => false,
         }
-        false
     }
-    pub fn search(mut self, re: &Regex) -> Option<Self> {
-        let mock_folds = HashSet::new();
+    // Whether the jq program outputs anything truthy for this value. The closing line of a
+    // container doesn't count, so each container matches at most once.
+    pub fn jq_matches(&self, prog: &mut JQ) -> bool {
+        self.focus_position != FocusPosition::End && prog.any_truthy(&self.focus)
+    }
+    pub fn search(self, re: &Regex, scope: SearchScope) -> SearchHit {
+        self.search_by(|cursor| cursor.regex_matches(re, scope))
+    }
+    pub fn search_back(self, re: &Regex, scope: SearchScope) -> SearchHit {
+        self.search_back_by(|cursor| cursor.regex_matches(re, scope))
+    }
+    pub fn jq_search(self, prog: &mut JQ) -> SearchHit {
+        self.search_by(|cursor| cursor.jq_matches(prog))
+    }
+    pub fn jq_search_back(self, prog: &mut JQ) -> SearchHit {
+        self.search_back_by(|cursor| cursor.jq_matches(prog))
+    }
+    // The number of search hits up to and including this cursor's position
+    pub fn search_position(&self, re: &Regex, scope: SearchScope) -> usize {
+        self.search_position_by(|cursor| cursor.regex_matches(re, scope))
+    }
+    pub fn count_search_hits(jsons: Rc<[JV]>, re: &Regex, scope: SearchScope) -> usize {
+        LeafCursor::count_search_hits_by(jsons, |cursor| cursor.regex_matches(re, scope))
+    }
+    pub fn search_by<F: FnMut(&Self) -> bool>(mut self, mut matches: F) -> SearchHit {
+        let mock_folds = Folds::new();
         let start = self.to_path();
         while let Some(()) = self.advance(&mock_folds) {
-            if self.regex_matches(re) {
-                return Some(self);
+            if matches(&self) {
+                return SearchHit::Found(self);
             }
         }
         let mut cursor = LeafCursor::new(self.jsons).expect("Jsons can't be empty here");
         while !cursor.matches_path(&start) {
-            if cursor.regex_matches(re) {
-                return Some(cursor);
+            if matches(&cursor) {
+                return SearchHit::Wrapped(cursor);
             }
             cursor
                 .advance(&mock_folds)
                 .expect("Shouldn't hit end again before hitting initial position");
         }
-        None
+        SearchHit::NotFound
     }
-    pub fn search_back(mut self, re: &Regex) -> Option<Self> {
-        let mock_folds = HashSet::new();
+    pub fn search_back_by<F: FnMut(&Self) -> bool>(mut self, mut matches: F) -> SearchHit {
+        let mock_folds = Folds::new();
         let start = self.to_path();
         while let Some(()) = self.regress(&mock_folds) {
-            if self.regex_matches(re) {
-                return Some(self);
+            if matches(&self) {
+                return SearchHit::Found(self);
             }
         }
         let mut cursor = LeafCursor::new_end(self.jsons).expect("Jsons can't be empty here");
         while !cursor.matches_path(&start) {
-            if cursor.regex_matches(re) {
-                return Some(cursor);
+            if matches(&cursor) {
+                return SearchHit::Wrapped(cursor);
             }
             cursor
                 .regress(&mock_folds)
                 .expect("Shouldn't hit start again before hitting initial position");
         }
-        None
+        SearchHit::NotFound
+    }
+    pub fn search_position_by<F: FnMut(&Self) -> bool>(&self, mut matches: F) -> usize {
+        let mock_folds = Folds::new();
+        let target = self.to_path();
+        let mut cursor = LeafCursor::new(self.jsons.clone()).expect("Jsons can't be empty here");
+        let mut count = 0;
+        loop {
+            if matches(&cursor) {
+                count += 1;
+            }
+            if cursor.matches_path(&target) {
+                return count;
+            }
+            cursor
+                .advance(&mock_folds)
+                .expect("Shouldn't hit end before hitting initial position");
+        }
+    }
+    pub fn count_search_hits_by<F: FnMut(&Self) -> bool>(jsons: Rc<[JV]>, mut matches: F) -> usize {
+        let mock_folds = Folds::new();
+        let mut cursor = match LeafCursor::new(jsons) {
+            Some(cursor) => cursor,
+            None => return 0,
+        };
+        let mut count = 0;
+        loop {
+            if matches(&cursor) {
+                count += 1;
+            }
+            if cursor.advance(&mock_folds).is_none() {
+                return count;
+            }
+        }
+    }
+    // (keys, values) matching re anywhere in jsons
+    pub fn count_matches(jsons: Rc<[JV]>, re: &Regex) -> (usize, usize) {
+        let mock_folds = Folds::new();
+        let mut cursor = match LeafCursor::new(jsons) {
+            Some(cursor) => cursor,
+            None => return (0, 0),
+        };
+        let mut keys = 0;
+        let mut values = 0;
+        loop {
+            // Containers are visited at both ends, but only the start has the key
+            if cursor.focus_position != FocusPosition::End && cursor.key_matches(re) {
+                keys += 1;
+            }
+            if cursor.leaf_matches(re) {
+                values += 1;
+            }
+            if cursor.advance(&mock_folds).is_none() {
+                return (keys, values);
+            }
+        }
+    }
+    // Returns a cursor on the opening line of the outermost folded container enclosing this
+    // cursor, if any. A closing bracket counts as enclosed by its own (folded) container.
+    pub fn outermost_folded_ancestor(&self, folds: &Folds) -> Option<Self> {
+        let (top_index, path) = self.to_path().strip_position();
+        let folded_depth = (0..self.frames.len())
+            .find(|&depth| folds.contains_key(&(top_index, path[..depth].to_vec())));
+        let mut out = self.clone();
+        match folded_depth {
+            Some(depth) => {
+                out.focus = match &self.frames[depth] {
+                    CursorFrame::Array { json, .. } => json.clone().into(),
+                    CursorFrame::Object { json, .. } => json.clone().into(),
+                };
+                out.frames.truncate(depth);
+            }
+            None if self.focus_position == FocusPosition::End
+                && folds.contains_key(&(top_index, path)) => {}
+            None => return None,
+        }
+        out.focus_position = FocusPosition::Start;
+        Some(out)
     }
     pub fn descends_from_or_matches(&self, other: &Self) -> bool {
         if self.top_index != other.top_index {
@@ -661,6 +1348,239 @@ impl LeafCursor {
     }
 }
 
+// Resolves a jq path made of .key, ."key", [n] and ["key"] segments. The path is tried against each
+// of jsons in turn, and the first one it exists in wins.
+pub fn parse_path(path: &str, jsons: &[JV]) -> Option<ValuePath> {
+    let path = path.trim();
+    let path = if path == "." { "" } else { path };
+    jsons.iter().enumerate().find_map(|(top_index, json)| {
+        let mut focus = json.clone();
+        let mut frames = Vec::new();
+        let mut rest = path;
+        while !rest.is_empty() {
+            let (index, child, new_rest) = parse_path_segment(rest, &focus)?;
+            frames.push(index);
+            focus = child;
+            rest = new_rest;
+        }
+        Some(ValuePath {
+            top_index,
+            frames,
+            focus_position: FocusPosition::starting(&focus),
+        })
+    })
+}
+
+// Returns the index of the child the segment refers to, the child, and the rest of the path.
+fn parse_path_segment<'a>(path: &'a str, json: &JV) -> Option<(usize, JV, &'a str)> {
+    if let Some(rest) = path.strip_prefix('[') {
+        let rest = rest.trim_start();
+        if rest.starts_with('"') {
+            let (key, rest) = parse_quoted_key(rest)?;
+            let rest = rest.trim_start().strip_prefix(']')?;
+            let (index, child) = object_child(json, &key)?;
+            return Some((index, child, rest));
+        }
+        let end = rest.find(']')?;
+        let index: i64 = rest[..end].trim().parse().ok()?;
+        let arr = match json {
+            JV::Array(arr) => arr,
+            _ => return None,
+        };
+        let len = arr.len() as i64;
+        let index = if index < 0 { index + len } else { index };
+        if index < 0 || index >= len {
+            return None;
+        }
+        let child = arr.get(index as i32)?;
+        return Some((index as usize, child, &rest[end + 1..]));
+    }
+    let rest = path.strip_prefix('.')?;
+    if rest.starts_with('[') {
+        return parse_path_segment(rest, json);
+    }
+    if rest.starts_with('"') {
+        let (key, rest) = parse_quoted_key(rest)?;
+        let (index, child) = object_child(json, &key)?;
+        return Some((index, child, rest));
+    }
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    let key = &rest[..end];
+    if !is_jq_identifier(key) {
+        return None;
+    }
+    let (index, child) = object_child(json, key)?;
+    Some((index, child, &rest[end..]))
+}
+
+fn parse_quoted_key(path: &str) -> Option<(String, &str)> {
+    let mut stream = serde_json::Deserializer::from_str(path).into_iter::<String>();
+    let key = stream.next()?.ok()?;
+    Some((key, &path[stream.byte_offset()..]))
+}
+
+fn object_child(json: &JV, key: &str) -> Option<(usize, JV)> {
+    match json {
+        JV::Object(obj) => obj
+            .iter()
+            .enumerate()
+            .find(|(_, (k, _))| *k == key)
+            .map(|(index, (_, child))| (index, child)),
+        _ => None,
+    }
+}
+
+// Splits a JSON Pointer URI fragment (#/a/b~1c) into its unescaped reference tokens
+pub fn parse_json_pointer(pointer: &str) -> Option<Vec<String>> {
+    let pointer = pointer.strip_prefix('#')?;
+    if pointer.is_empty() {
+        return Some(Vec::new());
+    }
+    let pointer = pointer.strip_prefix('/')?;
+    Some(
+        pointer
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+    )
+}
+
+// Number of values nested anywhere inside json
+pub fn descendant_count(json: &JV) -> usize {
+    match json {
+        JV::Array(arr) => arr.iter().map(|child| 1 + descendant_count(&child)).sum(),
+        JV::Object(obj) => obj.values().map(|child| 1 + descendant_count(&child)).sum(),
+        _ => 0,
+    }
+}
+
+// Columns json takes up when drawn on a single line, or None if that's more than limit. Stops
+// early on big containers, so it's cheap to call on everything.
+pub fn inline_width(json: &JV, limit: usize, display: DisplayOptions) -> Option<usize> {
+    let width = match json {
+        JV::Null(_) => display.null_str().width(),
+        JV::Bool(b) => display.bool_str(b.value()).width(),
+        JV::Number(x) => format_number(x.value()).len(),
+        // Newlines that end lines can't go on one
+        JV::String(s) if display.literal_newlines && s.value().contains('\n') => return None,
+        JV::String(s) => display.escaped_width(s.value()) + 2,
+        JV::Array(arr) => {
+            // Brackets, plus ", " between elements
+            let mut width = 2 + 2 * (arr.len().max(1) as usize - 1);
+            for child in arr.iter() {
+                if width > limit {
+                    return None;
+                }
+                width += inline_width(&child, limit - width, display)?;
+            }
+            width
+        }
+        JV::Object(obj) => {
+            let mut width = 2 + 2 * (obj.len().max(1) as usize - 1);
+            for (key, child) in obj.clone().into_iter() {
+                // Quotes, and ": "
+                width += display.escaped_width(key.value()) + 4;
+                if width > limit {
+                    return None;
+                }
+                width += inline_width(&child, limit - width, display)?;
+            }
+            width
+        }
+    };
+    Some(width).filter(|&width| width <= limit)
+}
+
+// Number of lines json takes up when rendered with nothing folded
+pub fn json_line_count(json: &JV) -> usize {
+    match json {
+        JV::Array(arr) => {
+            2 + arr
+                .iter()
+                .map(|child| json_line_count(&child))
+                .sum::<usize>()
+        }
+        JV::Object(obj) => {
+            2 + obj
+                .values()
+                .map(|child| json_line_count(&child))
+                .sum::<usize>()
+        }
+        _ => 1,
+    }
+}
+
+pub fn is_jq_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// jq's lexer reads these as keywords even after a ., so keys named after them need quoting
+const JQ_KEYWORDS: &[&str] = &[
+    "__loc__", "and", "as", "catch", "def", "elif", "else", "end", "foreach", "if", "import",
+    "include", "label", "or", "reduce", "then", "try",
+];
+
+// A key as a jq path segment: .foo when that parses, and ."foo bar" otherwise
+pub fn format_jq_key(key: &str) -> String {
+    if is_jq_identifier(key) && !JQ_KEYWORDS.contains(&key) {
+        format!(".{}", key)
+    } else {
+        format!(".\"{}\"", escaped_str(key))
+    }
+}
+
+// Where a search from a cursor ended up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchHit {
+    Found(LeafCursor),
+    // Only found by going past the end (or start, searching backwards) and around
+    Wrapped(LeafCursor),
+    NotFound,
+}
+
+impl SearchHit {
+    // The hit, if there is one that can be reached with or without wrapping around
+    pub fn into_cursor(self, wrap: bool) -> Option<LeafCursor> {
+        match self {
+            SearchHit::Found(cursor) => Some(cursor),
+            SearchHit::Wrapped(cursor) if wrap => Some(cursor),
+            SearchHit::Wrapped(_) | SearchHit::NotFound => None,
+        }
+    }
+}
+
+// What a search regex is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchScope {
+    Both,
+    Keys,
+    Values,
+}
+
+impl SearchScope {
+    pub fn next(self) -> Self {
+        match self {
+            SearchScope::Both => SearchScope::Keys,
+            SearchScope::Keys => SearchScope::Values,
+            SearchScope::Values => SearchScope::Both,
+        }
+    }
+    pub fn describe(self) -> &'static str {
+        match self {
+            SearchScope::Both => "keys and values",
+            SearchScope::Keys => "keys only",
+            SearchScope::Values => "values only",
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub struct ValuePath {
     top_index: usize,
@@ -668,6 +1588,45 @@ pub struct ValuePath {
     focus_position: FocusPosition,
 }
 impl ValuePath {
+    pub fn new(top_index: usize, frames: Vec<usize>, focus_position: FocusPosition) -> Self {
+        ValuePath {
+            top_index,
+            frames,
+            focus_position,
+        }
+    }
+    // {"top_index": 0, "frames": [1, 2], "position": "start"}, for saving in a session
+    pub fn to_json(&self) -> serde_json::Value {
+        let position = match self.focus_position {
+            FocusPosition::Start => "start",
+            FocusPosition::Value => "value",
+            FocusPosition::End => "end",
+        };
+        serde_json::json!({
+            "top_index": self.top_index,
+            "frames": self.frames,
+            "position": position,
+        })
+    }
+    pub fn from_json(json: &serde_json::Value) -> Option<Self> {
+        let top_index = json["top_index"].as_u64()? as usize;
+        let frames = json["frames"]
+            .as_array()?
+            .iter()
+            .map(|index| index.as_u64().map(|index| index as usize))
+            .collect::<Option<Vec<usize>>>()?;
+        let focus_position = match json["position"].as_str()? {
+            "start" => FocusPosition::Start,
+            "value" => FocusPosition::Value,
+            "end" => FocusPosition::End,
+            _ => return None,
+        };
+        Some(ValuePath {
+            top_index,
+            frames,
+            focus_position,
+        })
+    }
     pub fn strip_position(self) -> (usize, Vec<usize>) {
         let ValuePath {
             top_index,
@@ -676,6 +1635,142 @@ impl ValuePath {
         } = self;
         (top_index, frames)
     }
+    // See path_after_reorder
+    pub fn after_reorder(self, parent: &(usize, Vec<usize>), new_indices: &[usize]) -> Self {
+        let focus_position = self.focus_position;
+        let (top_index, frames) = path_after_reorder(self.strip_position(), parent, new_indices);
+        ValuePath {
+            top_index,
+            frames,
+            focus_position,
+        }
+    }
+    // See path_after_key_reorder
+    pub fn after_key_reorder(self, from: &[JV], to: &[JV]) -> Option<Self> {
+        let focus_position = self.focus_position;
+        let (top_index, frames) = path_after_key_reorder(self.strip_position(), from, to)?;
+        Some(ValuePath {
+            top_index,
+            frames,
+            focus_position,
+        })
+    }
+    // See path_after_deletion
+    pub fn after_deletion(self, deleted: &(usize, Vec<usize>)) -> Option<Self> {
+        let focus_position = self.focus_position;
+        let (top_index, frames) = path_after_deletion(self.strip_position(), deleted)?;
+        Some(ValuePath {
+            top_index,
+            frames,
+            focus_position,
+        })
+    }
+}
+
+// Where the value at `path` ends up once the children of the container at `parent` are shuffled,
+// with the child at index i moving to new_indices[i].
+pub fn path_after_reorder(
+    path: (usize, Vec<usize>),
+    parent: &(usize, Vec<usize>),
+    new_indices: &[usize],
+) -> (usize, Vec<usize>) {
+    let (top_index, mut frames) = path;
+    let (parent_top_index, parent_frames) = parent;
+    if top_index == *parent_top_index
+        && frames.len() > parent_frames.len()
+        && frames[..parent_frames.len()] == *parent_frames
+    {
+        let index = &mut frames[parent_frames.len()];
+        *index = new_indices[*index];
+    }
+    (top_index, frames)
+}
+
+// Where the value at `path` in `from` is in `to`, which holds the same values with the keys of its
+// objects in a different order. Returns None if `to` doesn't have it after all.
+pub fn path_after_key_reorder(
+    path: (usize, Vec<usize>),
+    from: &[JV],
+    to: &[JV],
+) -> Option<(usize, Vec<usize>)> {
+    let (top_index, frames) = path;
+    let mut from_json = from.get(top_index)?.clone();
+    let mut to_json = to.get(top_index)?.clone();
+    let mut new_frames = Vec::with_capacity(frames.len());
+    for index in frames {
+        let (new_index, from_child, to_child) = match (&from_json, &to_json) {
+            (JV::Array(from_arr), JV::Array(to_arr)) => (
+                index,
+                from_arr.get(index as i32)?,
+                to_arr.get(index as i32)?,
+            ),
+            (JV::Object(from_obj), JV::Object(_)) => {
+                let (key, from_child) = from_obj.iter().nth(index)?;
+                let (new_index, to_child) = object_child(&to_json, key)?;
+                (new_index, from_child, to_child)
+            }
+            _ => return None,
+        };
+        new_frames.push(new_index);
+        from_json = from_child;
+        to_json = to_child;
+    }
+    Some((top_index, new_frames))
+}
+
+// A copy of json with the keys of every object in it sorted, like jq -S
+pub fn sort_keys(json: &JV) -> JV {
+    match json {
+        JV::Array(arr) => arr
+            .iter()
+            .map(|child| sort_keys(&child))
+            .collect::<JVArray>()
+            .into(),
+        JV::Object(obj) => {
+            let mut entries: Vec<(&str, JV)> = obj.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            entries
+                .into_iter()
+                .map(|(key, child)| (key.to_owned(), sort_keys(&child)))
+                .collect::<JVObject>()
+                .into()
+        }
+        _ => json.clone(),
+    }
+}
+
+// Where the value at `path` ends up once the value at `deleted` is removed, shifting later siblings
+// back one. Returns None if it was removed along with it.
+pub fn path_after_deletion(
+    path: (usize, Vec<usize>),
+    deleted: &(usize, Vec<usize>),
+) -> Option<(usize, Vec<usize>)> {
+    let (mut top_index, mut frames) = path;
+    let (deleted_top_index, deleted_frames) = deleted;
+    let (deleted_index, deleted_parent) = match deleted_frames.split_last() {
+        None => {
+            match top_index.cmp(deleted_top_index) {
+                Ordering::Less => {}
+                Ordering::Equal => return None,
+                Ordering::Greater => top_index -= 1,
+            }
+            return Some((top_index, frames));
+        }
+        Some(split) => split,
+    };
+    if top_index != *deleted_top_index
+        || frames.len() < deleted_frames.len()
+        || frames[..deleted_parent.len()] != *deleted_parent
+    {
+        return Some((top_index, frames));
+    }
+    let index = &mut frames[deleted_parent.len()];
+    match (*index).cmp(deleted_index) {
+        Ordering::Less => {}
+        Ordering::Equal => return None,
+        Ordering::Greater => *index -= 1,
+    }
+    Some((top_index, frames))
 }
 
 impl PartialOrd for ValuePath {
@@ -725,20 +1820,30 @@ pub struct GlobalPath {
 
 #[cfg(test)]
 mod tests {
-    use super::{GlobalCursor, LeafCursor};
+    use super::{
+        descendant_count, format_jq_key, inline_width, json_line_count, parse_json_pointer,
+        parse_path, path_after_deletion, path_after_key_reorder, path_after_reorder, sort_keys,
+        FocusPosition, Fold, Folds, GlobalCursor, LeafCursor, SearchHit, SearchScope,
+    };
     use crate::{
-        jq::jv::JV,
-        lines::LineCursor,
+        jq::{
+            jv::{JVObject, JV},
+            query::JQ,
+        },
+        lines::{DisplayOptions, Leaf, LeafContent, LineCursor},
         testing::{arb_json, json_to_lines},
+        theme::Theme,
     };
     use pretty_assertions::assert_eq;
     use proptest::proptest;
+    use regex::Regex;
     use serde_json::{json, Value};
     use std::{collections::HashSet, rc::Rc};
+    use tui::layout::Rect;
 
     fn check_advancing_terminates(jsons: Vec<Value>) {
         let jsons: Vec<JV> = jsons.iter().map(|v| v.into()).collect();
-        let folds = HashSet::new();
+        let folds = Folds::new();
         if let Some(mut cursor) = LeafCursor::new(jsons.into()) {
             let mut last_path = cursor.to_path();
             while let Some(()) = cursor.advance(&folds) {
@@ -754,26 +1859,32 @@ mod tests {
     }
     fn check_lines(values: Vec<Value>) {
         let jsons: Vec<JV> = values.iter().map(|v| v.into()).collect();
-        let folds = HashSet::new();
+        let folds = Folds::new();
         let width = u16::MAX;
         let mut expected_lines = json_to_lines(values.iter()).into_iter();
-        if let Some(mut cursor) = GlobalCursor::new(jsons.into(), width, &folds) {
+        if let Some(mut cursor) =
+            GlobalCursor::new(jsons.into(), width, &folds, DisplayOptions::default())
+        {
             let mut actual_lines = Vec::new();
             actual_lines.push(cursor.current_line());
             let expected_line = expected_lines
                 .next()
                 .expect("Expected lines shorter than actual lines");
-            let expected = LineCursor::new_at_start(expected_line.render(), width)
-                .current()
-                .unwrap();
+            let expected =
+                LineCursor::new_at_start(expected_line.render(DisplayOptions::default()), width)
+                    .current()
+                    .unwrap();
             assert_eq!(cursor.current_line(), expected);
             while let Some(()) = cursor.advance(&folds, width) {
                 let expected_line = expected_lines
                     .next()
                     .expect("Expected lines shorter than actual lines");
-                let expected = LineCursor::new_at_start(expected_line.render(), width)
-                    .current()
-                    .unwrap();
+                let expected = LineCursor::new_at_start(
+                    expected_line.render(DisplayOptions::default()),
+                    width,
+                )
+                .current()
+                .unwrap();
                 assert_eq!(cursor.current_line(), expected);
             }
         }
@@ -797,7 +1908,7 @@ mod tests {
     fn check_path_roundtrip(values: Vec<serde_json::Value>) {
         let jsons: Vec<JV> = values.iter().map(|v| v.into()).collect();
         let jsons: Rc<[JV]> = jsons.into();
-        let folds = HashSet::new();
+        let folds = Folds::new();
         if let Some(mut cursor) = LeafCursor::new(jsons.clone()) {
             check_path_roundtrip_inner(&cursor, jsons.clone());
             while let Some(()) = cursor.advance(&folds) {
@@ -809,17 +1920,61 @@ mod tests {
     fn unit_path_roundtrip() {
         check_path_roundtrip(vec![json!([{ "": null }])])
     }
+    fn check_line_number(values: Vec<serde_json::Value>) {
+        let jsons: Vec<JV> = values.iter().map(|v| v.into()).collect();
+        let jsons: Rc<[JV]> = jsons.into();
+        let folds = Folds::new();
+        let mut cursor = LeafCursor::new(jsons.clone()).unwrap();
+        let mut expected = 0;
+        assert_eq!(cursor.line_number(), expected);
+        while let Some(()) = cursor.advance(&folds) {
+            expected += 1;
+            assert_eq!(cursor.line_number(), expected);
+        }
+        let line_count: usize = jsons.iter().map(json_line_count).sum();
+        assert_eq!(line_count, expected + 1);
+    }
+    #[test]
+    fn unit_line_number() {
+        check_line_number(vec![json!({"a": [1, {}], "b": 2}), json!(3)])
+    }
+    #[test]
+    fn unit_render_line_numbers() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2]})).into()];
+        let mut folds = Folds::new();
+        folds.insert((0, vec![0]), Fold::Children);
+        let rect = Rect::new(0, 0, 40, 10);
+        let mut cursor =
+            GlobalCursor::new(jsons.into(), rect.width, &folds, DisplayOptions::default()).unwrap();
+        let gutters: Vec<String> = cursor
+            .render_lines(
+                None,
+                &folds,
+                rect,
+                Some(2),
+                0,
+                rect.width,
+                None,
+                &Theme::default(),
+            )
+            .into_iter()
+            .map(|spans| spans.0[0].content.to_string())
+            .collect();
+        assert_eq!(gutters, vec!["1 ", "2 ", "6 "]);
+    }
+    proptest! {
+        #[test]
+        fn prop_line_number(values in proptest::collection::vec(arb_json(), 1..10)) {
+            check_line_number(values)
+        }
+    }
     proptest! {
         #[test]
         fn prop_path_roundtrip(values in proptest::collection::vec(arb_json(), 1..10)) {
             check_path_roundtrip(values)
         }
     }
-    fn check_advance_regress(
-        cursor: &GlobalCursor,
-        folds: &HashSet<(usize, Vec<usize>)>,
-        width: u16,
-    ) {
+    fn check_advance_regress(cursor: &GlobalCursor, folds: &Folds, width: u16) {
         let mut actual = cursor.clone();
         if actual.advance(folds, width).is_none() {
             return;
@@ -837,9 +1992,9 @@ mod tests {
         fn prop_advance_regress(values in proptest::collection::vec(arb_json(), 1..10), width in 8u16..250) {
             let jsons : Vec<JV> = values.iter().map(|v| v.into()).collect();
             let jsons : Rc<[JV]> = jsons.into();
-            let folds = HashSet::new();
+            let folds = Folds::new();
             let mut seen = HashSet::new();
-            if let Some(mut cursor) = GlobalCursor::new(jsons.clone(), width, &folds) {
+            if let Some(mut cursor) = GlobalCursor::new(jsons.clone(), width, &folds, DisplayOptions::default()) {
                 check_advance_regress(&cursor, &folds, width);
                 while let Some(()) = cursor.advance(&folds, width) {
                     let key = hashable_cursor_key(&cursor);
@@ -867,9 +2022,11 @@ mod tests {
         for (values, width) in tests {
             let jsons: Vec<JV> = values.iter().map(|v| v.into()).collect();
             let jsons: Rc<[JV]> = jsons.into();
-            let folds = HashSet::new();
+            let folds = Folds::new();
             let mut seen = HashSet::new();
-            if let Some(mut cursor) = GlobalCursor::new(jsons.clone(), width, &folds) {
+            if let Some(mut cursor) =
+                GlobalCursor::new(jsons.clone(), width, &folds, DisplayOptions::default())
+            {
                 check_advance_regress(&cursor, &folds, width);
                 while let Some(()) = cursor.advance(&folds, width) {
                     let key = hashable_cursor_key(&cursor);
@@ -882,12 +2039,601 @@ mod tests {
             }
         }
     }
+    #[test]
+    fn unit_search_position() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"foo": 1, "bar": "foo"})).into(),
+            (&json!(["food", "baz"])).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let re = Regex::new("foo").unwrap();
+        assert_eq!(
+            LeafCursor::count_search_hits(jsons.clone(), &re, SearchScope::Both),
+            3
+        );
+        let cursor = LeafCursor::new(jsons.clone()).unwrap();
+        let mut positions = Vec::new();
+        let mut hit = cursor.search(&re, SearchScope::Both);
+        while let SearchHit::Found(cursor) = hit {
+            let position = cursor.search_position(&re, SearchScope::Both);
+            positions.push(position);
+            hit = cursor.search(&re, SearchScope::Both);
+        }
+        assert_eq!(positions, vec![1, 2, 3]);
+        // Past the last hit, the search goes around to the first
+        match hit {
+            SearchHit::Wrapped(cursor) => {
+                assert_eq!(cursor.search_position(&re, SearchScope::Both), 1)
+            }
+            _ => panic!("Expected the search to wrap"),
+        }
+        let first = LeafCursor::new(jsons.clone()).unwrap();
+        assert!(matches!(
+            first
+                .clone()
+                .search_back(&Regex::new("baz").unwrap(), SearchScope::Both),
+            SearchHit::Wrapped(_)
+        ));
+        let nothing = Regex::new("nothing").unwrap();
+        assert_eq!(
+            first.search(&nothing, SearchScope::Both),
+            SearchHit::NotFound
+        );
+    }
+    #[test]
+    fn unit_search_scope() {
+        let jsons: Vec<JV> = vec![(&json!({"foo": 1, "bar": "foo", "baz": "food"})).into()];
+        let jsons: Rc<[JV]> = jsons.into();
+        let re = Regex::new("foo").unwrap();
+        let count = |scope| LeafCursor::count_search_hits(jsons.clone(), &re, scope);
+        assert_eq!(count(SearchScope::Both), 3);
+        assert_eq!(count(SearchScope::Keys), 1);
+        assert_eq!(count(SearchScope::Values), 2);
+        let cursor = LeafCursor::new(jsons.clone()).unwrap();
+        let hit = cursor.clone().search(&re, SearchScope::Values);
+        let hit = hit.into_cursor(true).unwrap();
+        assert_eq!(Value::from(&hit.focus), json!("foo"));
+        let hit = cursor
+            .search_back(&re, SearchScope::Keys)
+            .into_cursor(true)
+            .unwrap();
+        assert_eq!(Value::from(&hit.focus), json!(1.0));
+    }
+    #[test]
+    fn unit_count_matches() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"foo": {"food": 1}, "bar": "foo", "baz": ["foo", 2]})).into(),
+            (&json!("foo")).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let re = Regex::new("foo").unwrap();
+        assert_eq!(LeafCursor::count_matches(jsons.clone(), &re), (2, 3));
+        let re = Regex::new("^x").unwrap();
+        assert_eq!(LeafCursor::count_matches(jsons, &re), (0, 0));
+    }
+    #[test]
+    fn unit_jq_search() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"people": [{"age": 20}, {"age": 40}, {"age": 50}]})).into(),
+            (&json!({"age": 35})).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let mut prog = JQ::compile(".age? > 30").unwrap();
+        let cursor = LeafCursor::new(jsons.clone()).unwrap();
+        let hit = cursor
+            .clone()
+            .jq_search(&mut prog)
+            .into_cursor(true)
+            .unwrap();
+        assert_eq!(Value::from(&hit.focus), json!({"age": 40.0}));
+        assert_eq!(hit.focus_position, FocusPosition::Start);
+        let hit = hit.jq_search(&mut prog).into_cursor(true).unwrap();
+        assert_eq!(Value::from(&hit.focus), json!({"age": 50.0}));
+        let hit = cursor.jq_search_back(&mut prog).into_cursor(true).unwrap();
+        assert_eq!(Value::from(&hit.focus), json!({"age": 35.0}));
+        assert_eq!(
+            LeafCursor::count_search_hits_by(jsons, |cursor| cursor.jq_matches(&mut prog)),
+            3
+        );
+    }
+    #[test]
+    fn unit_delete_focus() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"a": [1, 2], "b": {"c": 3}})).into(),
+            (&json!(4)).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let at = |path: &str| {
+            let path = parse_path(path, &jsons).unwrap();
+            LeafCursor::from_path(jsons.clone(), &path)
+        };
+        let values =
+            |cursor: &LeafCursor| -> Vec<Value> { cursor.jsons.iter().map(Value::from).collect() };
+        // The next sibling takes its place
+        let cursor = at(".a[0]").delete_focus().unwrap();
+        assert_eq!(
+            values(&cursor),
+            vec![json!({"a": [2.0], "b": {"c": 3.0}}), json!(4.0)]
+        );
+        assert_eq!(Value::from(&cursor.focus), json!(2.0));
+        // Falls back to the previous sibling
+        let cursor = at(".b").delete_focus().unwrap();
+        assert_eq!(values(&cursor), vec![json!({"a": [1.0, 2.0]}), json!(4.0)]);
+        assert_eq!(cursor.to_path().strip_position(), (0, vec![0]));
+        assert_eq!(cursor.focus_position, FocusPosition::Start);
+        // And then the parent
+        let cursor = at(".b.c").delete_focus().unwrap();
+        assert_eq!(cursor.to_path().strip_position(), (0, vec![1]));
+        assert_eq!(Value::from(&cursor.focus), json!({}));
+        // Top level values
+        let cursor = LeafCursor::new_end(jsons.clone()).unwrap();
+        let cursor = cursor.delete_focus().unwrap();
+        assert_eq!(cursor.top_index, 0);
+        assert_eq!(cursor.jsons.len(), 1);
+        assert!(cursor.delete_focus().is_none());
+    }
+    #[test]
+    fn unit_replace_focus() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, "two"], "b": null})).into()];
+        let jsons: Rc<[JV]> = jsons.into();
+        let path = parse_path(".a[1]", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.clone(), &path);
+        assert_eq!(cursor.leaf_to_json(), Some("\"two\"".to_owned()));
+        let cursor = cursor.replace_focus((&json!({"c": 2})).into());
+        assert_eq!(
+            Value::from(&cursor.jsons[0]),
+            json!({"a": [1.0, {"c": 2.0}], "b": null})
+        );
+        assert_eq!(cursor.to_path().strip_position(), (0, vec![0, 1]));
+        assert_eq!(cursor.focus_position, FocusPosition::Start);
+        assert_eq!(cursor.leaf_to_json(), None);
+        let path = parse_path(".a[0]", &cursor.jsons).unwrap();
+        let cursor = LeafCursor::from_path(cursor.jsons.clone(), &path);
+        assert_eq!(cursor.leaf_to_json(), Some("1".to_owned()));
+    }
+    #[test]
+    fn unit_insert_key() {
+        let jsons: Vec<JV> = vec![
+            (&json!([{"z": 1, "a": [2], "m": 3}])).into(),
+            (&json!(4)).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let indices = |path: &str| parse_path(path, &jsons).unwrap().strip_position();
+        let container = |path: &str| {
+            let path = parse_path(path, &jsons).unwrap();
+            let cursor = LeafCursor::from_path(jsons.clone(), &path);
+            cursor
+                .enclosing_container()
+                .map(|c| c.to_path().strip_position())
+        };
+        // Leaves go into their parent, containers take children themselves
+        assert_eq!(container("[0].z"), Some(indices("[0]")));
+        assert_eq!(container("[0].a[0]"), Some(indices("[0].a")));
+        assert_eq!(container("[0].a"), Some(indices("[0].a")));
+        assert_eq!(container("."), Some(indices(".")));
+        let top_scalar = LeafCursor::at_indices(jsons.clone(), 1, vec![]).unwrap();
+        assert!(top_scalar.enclosing_container().is_none());
+        let object = LeafCursor::at_indices(jsons.clone(), 0, vec![0]).unwrap();
+        // Not an object, or the key is taken
+        let array = LeafCursor::at_indices(jsons.clone(), 0, vec![]).unwrap();
+        assert!(array.insert_key("b", (&json!(5)).into()).is_none());
+        assert!(object.insert_key("m", (&json!(5)).into()).is_none());
+        let (inserted, new_indices) = object.insert_key("b", (&json!({"c": 5})).into()).unwrap();
+        assert_eq!(
+            Value::from(&inserted.jsons[0]),
+            json!([{"z": 1.0, "a": [2.0], "m": 3.0, "b": {"c": 5.0}}])
+        );
+        assert_eq!(inserted.current_key().unwrap().value(), "b");
+        assert_eq!(Value::from(&inserted.focus), json!({"c": 5.0}));
+        assert_eq!(inserted.focus_position, FocusPosition::Start);
+        for (old_index, &new_index) in new_indices.iter().enumerate() {
+            let old = LeafCursor::at_indices(jsons.clone(), 0, vec![0, old_index]).unwrap();
+            let new =
+                LeafCursor::at_indices(inserted.jsons.clone(), 0, vec![0, new_index]).unwrap();
+            assert_eq!(Value::from(&old.focus), Value::from(&new.focus));
+        }
+    }
+    #[test]
+    fn unit_find_key() {
+        let jsons: Vec<JV> = vec![
+            (&json!({
+                "users": {"u1": {"friend": "u2"}, "u2": {"friend": 7}},
+                "nums": {"7": null}
+            }))
+                .into(),
+            (&json!({"u2": 1})).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let cursor =
+            |path: &str| LeafCursor::from_path(jsons.clone(), &parse_path(path, &jsons).unwrap());
+        let follow = |path: &str| {
+            let cursor = cursor(path);
+            cursor.find_key(&cursor.reference_key()?)
+        };
+        assert_eq!(follow(".users.u1.friend"), parse_path(".users.u2", &jsons));
+        // Numbers refer to keys too
+        assert_eq!(
+            follow(".users.u2.friend"),
+            parse_path(".nums.\"7\"", &jsons)
+        );
+        assert_eq!(follow(".nums.\"7\""), None);
+        assert_eq!(cursor(".users").find_key("missing"), None);
+        // Only the current top level value is searched
+        let other = LeafCursor::at_indices(jsons.clone(), 1, vec![]).unwrap();
+        assert_eq!(other.find_key("u1"), None);
+        assert_eq!(
+            other.find_key("u2").map(|path| path.strip_position()),
+            Some((1, vec![0]))
+        );
+    }
+    #[test]
+    fn unit_sibling_keys() {
+        let jsons: Vec<JV> = vec![(&json!({"a": {"x": 1, "y": [2]}, "b": [3, 4]})).into()];
+        let jsons: Rc<[JV]> = jsons.into();
+        let cursor =
+            |path: &str| LeafCursor::from_path(jsons.clone(), &parse_path(path, &jsons).unwrap());
+        let (keys, index) = cursor(".a.y").sibling_keys().unwrap();
+        assert_eq!(keys[index], "y");
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec!["x", "y"]);
+        let other = 1 - index;
+        assert_eq!(
+            cursor(".a.y").sibling(other).unwrap().to_path(),
+            parse_path(".a.x", &jsons).unwrap()
+        );
+        assert!(cursor(".a.y").sibling(2).is_none());
+        // Array elements and the top level value aren't in an object
+        assert_eq!(cursor(".b[0]").sibling_keys(), None);
+        assert_eq!(cursor(".").sibling_keys(), None);
+    }
+    #[test]
+    fn unit_resolve_pointer() {
+        assert_eq!(
+            parse_json_pointer("#/a~1b/c~0d/"),
+            Some(vec!["a/b".to_owned(), "c~d".to_owned(), "".to_owned()])
+        );
+        assert_eq!(parse_json_pointer("#"), Some(vec![]));
+        assert_eq!(parse_json_pointer("/a"), None);
+        let jsons: Vec<JV> = vec![
+            (&json!({"defs": {"Foo": [1, {"x/y": 2}]}, "ref": "#/defs/Foo/1/x~1y"})).into(),
+            (&json!({"defs": 3})).into(),
+        ];
+        let jsons: Rc<[JV]> = jsons.into();
+        let cursor = LeafCursor::from_path(jsons.clone(), &parse_path(".ref", &jsons).unwrap());
+        assert_eq!(
+            cursor.resolve_pointer("#/defs/Foo/1/x~1y"),
+            parse_path(".defs.Foo[1].\"x/y\"", &jsons)
+        );
+        assert_eq!(
+            cursor.resolve_pointer("#/defs/Foo"),
+            parse_path(".defs.Foo", &jsons)
+        );
+        assert_eq!(cursor.resolve_pointer("#/defs/Bar"), None);
+        assert_eq!(cursor.resolve_pointer("#/defs/Foo/2"), None);
+        assert_eq!(cursor.resolve_pointer("#/defs/Foo/01"), None);
+        assert_eq!(cursor.resolve_pointer("#/defs/Foo/+1"), None);
+        // Only the current top level value is searched
+        let other = LeafCursor::at_indices(jsons.clone(), 1, vec![]).unwrap();
+        assert_eq!(
+            other
+                .resolve_pointer("#/defs")
+                .map(|path| path.strip_position()),
+            Some((1, vec![0]))
+        );
+    }
+    #[test]
+    fn unit_append_element() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1], "b": []})).into()];
+        let jsons: Rc<[JV]> = jsons.into();
+        let path = parse_path(".a", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.clone(), &path);
+        let appended = cursor.append_element((&json!([2])).into()).unwrap();
+        assert_eq!(
+            Value::from(&appended.jsons[0]),
+            json!({"a": [1.0, [2.0]], "b": []})
+        );
+        assert_eq!(Value::from(&appended.focus), json!([2.0]));
+        assert_eq!(appended.focus_position, FocusPosition::Start);
+        // An empty array is drawn on one line, but takes children all the same
+        let path = parse_path(".b", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.clone(), &path)
+            .enclosing_container()
+            .unwrap();
+        let appended = cursor.append_element((&json!(3)).into()).unwrap();
+        assert_eq!(
+            Value::from(&appended.jsons[0]),
+            json!({"a": [1.0], "b": [3.0]})
+        );
+        assert_eq!(
+            appended.to_path(),
+            parse_path(".b[0]", &appended.jsons).unwrap()
+        );
+        let cursor = LeafCursor::new(jsons).unwrap();
+        assert!(cursor.append_element((&json!(4)).into()).is_none());
+    }
+    #[test]
+    fn unit_rename_key() {
+        let jsons: Vec<JV> = vec![(&json!([{"z": 1, "a": [2], "m": 3}])).into()];
+        let jsons: Rc<[JV]> = jsons.into();
+        let path = parse_path("[0].a[0]", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.clone(), &path);
+        // Only the innermost key can be renamed
+        assert!(cursor.rename_key("b").is_none());
+        let path = parse_path("[0].a", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.clone(), &path);
+        assert!(cursor.rename_key("m").is_none());
+        let (renamed, new_indices) = cursor.rename_key("b").unwrap();
+        assert_eq!(
+            Value::from(&renamed.jsons[0]),
+            json!([{"z": 1.0, "b": [2.0], "m": 3.0}])
+        );
+        assert_eq!(renamed.current_key().unwrap().value(), "b");
+        assert_eq!(Value::from(&renamed.focus), json!([2.0]));
+        // Every sibling can still be found at its new index
+        for (old_index, &new_index) in new_indices.iter().enumerate() {
+            let old = LeafCursor::at_indices(jsons.clone(), 0, vec![0, old_index]).unwrap();
+            let new = LeafCursor::at_indices(renamed.jsons.clone(), 0, vec![0, new_index]).unwrap();
+            assert_eq!(Value::from(&old.focus), Value::from(&new.focus));
+        }
+    }
+    #[test]
+    fn unit_path_after_reorder() {
+        let parent = (0, vec![1]);
+        let new_indices = [2, 0, 1];
+        let after = |top_index, frames: &[usize]| {
+            path_after_reorder((top_index, frames.to_vec()), &parent, &new_indices)
+        };
+        assert_eq!(after(0, &[1, 0, 5]), (0, vec![1, 2, 5]));
+        assert_eq!(after(0, &[1, 2]), (0, vec![1, 1]));
+        assert_eq!(after(0, &[1]), (0, vec![1]));
+        assert_eq!(after(0, &[0, 2]), (0, vec![0, 2]));
+        assert_eq!(after(1, &[1, 2]), (1, vec![1, 2]));
+    }
+    #[test]
+    fn unit_sort_keys() {
+        let unsorted = vec![JV::parse_native(r#"{"b": 1, "a": [{"d": 2, "c": 3}]}"#).unwrap()];
+        let sorted = vec![sort_keys(&unsorted[0])];
+        assert_eq!(sorted[0], unsorted[0]);
+        let keys = |json: &JV| match json {
+            JV::Object(obj) => obj.iter().map(|(k, _)| k.to_owned()).collect::<Vec<_>>(),
+            _ => panic!("Expected an object"),
+        };
+        assert_eq!(keys(&sorted[0]), ["a", "b"]);
+        let inner = LeafCursor::at_indices(sorted.clone().into(), 0, vec![0, 0])
+            .unwrap()
+            .focus;
+        assert_eq!(keys(&inner), ["c", "d"]);
+        let after =
+            |frames: &[usize]| path_after_key_reorder((0, frames.to_vec()), &unsorted, &sorted);
+        assert_eq!(after(&[0]), Some((0, vec![1])));
+        assert_eq!(after(&[1, 0, 1]), Some((0, vec![0, 0, 0])));
+        assert_eq!(after(&[2]), None);
+        assert_eq!(
+            path_after_key_reorder((0, vec![0, 0, 0]), &sorted, &unsorted),
+            Some((0, vec![1, 0, 1]))
+        );
+    }
+    #[test]
+    fn unit_path_after_deletion() {
+        let deleted = (0, vec![1, 2]);
+        let after = |top_index, frames: &[usize]| {
+            path_after_deletion((top_index, frames.to_vec()), &deleted)
+        };
+        assert_eq!(after(0, &[1, 1, 5]), Some((0, vec![1, 1, 5])));
+        assert_eq!(after(0, &[1, 2]), None);
+        assert_eq!(after(0, &[1, 2, 0]), None);
+        assert_eq!(after(0, &[1, 3, 0]), Some((0, vec![1, 2, 0])));
+        assert_eq!(after(0, &[2, 3]), Some((0, vec![2, 3])));
+        assert_eq!(after(0, &[1]), Some((0, vec![1])));
+        assert_eq!(after(1, &[1, 3]), Some((1, vec![1, 3])));
+        let deleted = (1, vec![]);
+        assert_eq!(path_after_deletion((1, vec![0]), &deleted), None);
+        assert_eq!(
+            path_after_deletion((2, vec![0]), &deleted),
+            Some((1, vec![0]))
+        );
+    }
+    #[test]
+    fn unit_descendant_count() {
+        let json: JV = (&json!({"a": [1, [2, 3]], "b": {}, "c": null})).into();
+        assert_eq!(descendant_count(&json), 7);
+        assert_eq!(descendant_count(&(&json!(1)).into()), 0);
+    }
+    #[test]
+    fn unit_inline_width() {
+        let tests = vec![
+            json!([]),
+            json!({}),
+            json!([1, 2.5, null, true]),
+            json!({"a": [1, "x\ty\u{1b}"], "日本": {}}),
+        ];
+        let all_on = DisplayOptions {
+            control_pictures: true,
+            literal_glyphs: true,
+            literal_newlines: true,
+        };
+        for display in vec![DisplayOptions::default(), all_on] {
+            for test in tests.iter() {
+                let json: JV = test.into();
+                let leaf = Leaf {
+                    content: LeafContent::Inline(json.clone()),
+                    key: None,
+                    indent: 0,
+                    comma: false,
+                };
+                let rendered = LineCursor::new_at_start(leaf.render(display), 10000)
+                    .current()
+                    .unwrap();
+                let width = rendered.width() as usize;
+                assert_eq!(inline_width(&json, 1000, display), Some(width), "{}", test);
+                assert_eq!(inline_width(&json, width, display), Some(width), "{}", test);
+                assert_eq!(inline_width(&json, width - 1, display), None, "{}", test);
+            }
+        }
+    }
+    #[test]
+    fn unit_describe_focus() {
+        let jsons: Vec<JV> =
+            vec![(&json!({"a": [1], "b": "tab\there", "c": "日本", "d": null})).into()];
+        let jsons: Rc<[JV]> = jsons.into();
+        let describe = |path: &str| {
+            let path = parse_path(path, &jsons).unwrap();
+            LeafCursor::from_path(jsons.clone(), &path).describe_focus(DisplayOptions::default())
+        };
+        assert_eq!(describe(".a"), "array, 1 item");
+        assert_eq!(describe(".a[0]"), "number");
+        assert_eq!(describe(".b"), "string, 8 chars, 9 columns");
+        assert_eq!(describe(".c"), "string, 2 chars, 4 columns");
+        assert_eq!(describe(".d"), "null");
+        let cursor = LeafCursor::new(jsons.clone()).unwrap();
+        assert_eq!(
+            cursor.describe_focus(DisplayOptions::default()),
+            "object, 4 keys"
+        );
+    }
+    #[test]
+    fn unit_parse_path() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"a": 1})).into(),
+            (&json!({"results": [0, {"items": [], "odd key": null}]})).into(),
+        ];
+        let check = |path: &str, expected: Option<Value>| {
+            let actual = parse_path(path, &jsons)
+                .map(|path| Value::from(&LeafCursor::from_path(jsons.clone().into(), &path).focus));
+            assert_eq!(actual, expected, "path: {}", path);
+        };
+        check(".", Some(json!({"a": 1.0})));
+        check(".a", Some(json!(1.0)));
+        check(".results[1].items", Some(json!([])));
+        check(".results[-1][\"odd key\"]", Some(json!(null)));
+        check(".results.[1].\"odd key\"", Some(json!(null)));
+        check(".results[2]", None);
+        check(".results.items", None);
+        check(".missing", None);
+        let path = parse_path(".results[1].items", &jsons).unwrap();
+        assert_eq!(path.top_index, 1);
+        assert_eq!(path.frames, vec![0, 1, 0]);
+        assert_eq!(path.focus_position, FocusPosition::Start);
+    }
+    #[test]
+    fn unit_siblings() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"a": [1, 2, 3], "b": 4})).into(),
+            (&json!(5)).into(),
+        ];
+        let folds = Folds::new();
+        let mut cursor = LeafCursor::new(jsons.clone().into()).unwrap();
+        // Put the cursor on the `2`
+        for _ in 0..3 {
+            cursor.advance(&folds);
+        }
+        let mut first = cursor.clone();
+        first.first_sibling();
+        assert_eq!(Value::from(&first.focus), json!(1.0));
+        let mut last = cursor.clone();
+        last.last_sibling();
+        assert_eq!(Value::from(&last.focus), json!(3.0));
+        // Siblings in an object
+        last.regress(&folds);
+        last.regress(&folds);
+        last.regress(&folds);
+        assert_eq!(last.focus_position, FocusPosition::Start);
+        last.last_sibling();
+        assert_eq!(Value::from(&last.focus), json!(4.0));
+        last.first_sibling();
+        assert_eq!(Value::from(&last.focus), json!([1.0, 2.0, 3.0]));
+        assert_eq!(last.focus_position, FocusPosition::Start);
+        // Top level values
+        let mut top = LeafCursor::new(jsons.into()).unwrap();
+        top.last_sibling();
+        assert_eq!(top.top_index, 1);
+        top.first_sibling();
+        assert_eq!(top.top_index, 0);
+        assert_eq!(top.focus_position, FocusPosition::Start);
+    }
+    #[test]
+    fn unit_focused_value() {
+        let value = json!({"a": [1, 2]});
+        let jsons: Vec<JV> = vec![(&value).into()];
+        let folds = Folds::new();
+        let mut cursor = LeafCursor::new(jsons.into()).unwrap();
+        cursor.advance(&folds);
+        let start_value = cursor.focused_value();
+        for _ in 0..3 {
+            cursor.advance(&folds);
+        }
+        assert_eq!(cursor.focus_position, FocusPosition::End);
+        assert_eq!(cursor.focused_value(), start_value);
+        assert_eq!(Value::from(&start_value), json!([1.0, 2.0]));
+    }
+    #[test]
+    fn unit_format_jq_key() {
+        assert_eq!(format_jq_key("foo"), ".foo");
+        assert_eq!(format_jq_key("_foo_1"), "._foo_1");
+        assert_eq!(format_jq_key("1foo"), r#"."1foo""#);
+        assert_eq!(format_jq_key("foo bar"), r#"."foo bar""#);
+        assert_eq!(format_jq_key(""), r#"."""#);
+        assert_eq!(format_jq_key("🦀"), r#"."🦀""#);
+        assert_eq!(format_jq_key("if"), r#"."if""#);
+        assert_eq!(format_jq_key("reduce"), r#"."reduce""#);
+        // Not a keyword, just a builtin
+        assert_eq!(format_jq_key("not"), ".not");
+        assert_eq!(format_jq_key("a\"b\\c\n"), r#"."a\"b\\c\n""#);
+        // Each of them finds its way back
+        let keys = ["foo", "foo bar", "", "🦀", "if", "a\"b\\c\n", "e\u{301}"];
+        let obj: JVObject = keys
+            .iter()
+            .map(|key| (key.to_string(), JV::from(&json!(null))))
+            .collect();
+        let jsons = vec![JV::from(obj)];
+        for (i, key) in keys.iter().enumerate() {
+            let path = parse_path(&format_jq_key(key), &jsons).unwrap();
+            assert_eq!(path.frames, vec![i]);
+        }
+    }
+    #[test]
+    fn unit_to_jq_path() {
+        let jsons: Vec<JV> = vec![(&json!([{"foo": {"bar baz": [null]}}])).into()];
+        let mut cursor = LeafCursor::new(jsons.into()).unwrap();
+        let folds = Folds::new();
+        let mut paths = vec![cursor.to_jq_path()];
+        while let Some(()) = cursor.advance(&folds) {
+            paths.push(cursor.to_jq_path());
+        }
+        let expected = vec![
+            ".",
+            ".[0]",
+            ".[0].foo",
+            r#".[0].foo."bar baz""#,
+            r#".[0].foo."bar baz"[0]"#,
+            r#".[0].foo."bar baz""#,
+            ".[0].foo",
+            ".[0]",
+            ".",
+        ];
+        assert_eq!(paths, expected);
+    }
+    #[test]
+    fn unit_to_path_assignment() {
+        let jsons: Vec<JV> =
+            vec![(&json!({"users": [{"email": "a@b.com", "tags": [1, 2]}]})).into()];
+        let path = parse_path(".users[0].email", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.clone().into(), &path);
+        assert_eq!(
+            cursor.to_path_assignment(),
+            r#".users[0].email = "a@b.com""#
+        );
+        let path = parse_path(".users[0].tags", &jsons).unwrap();
+        let cursor = LeafCursor::from_path(jsons.into(), &path);
+        assert_eq!(cursor.to_path_assignment(), ".users[0].tags = [1,2]");
+    }
     proptest! {
         #[test]
         fn prop_path_ordering(values in proptest::collection::vec(arb_json(), 1..10)) {
             let jsons : Vec<JV> = values.iter().map(|v| v.into()).collect();
             let jsons : Rc<[JV]> = jsons.into();
-            let folds = HashSet::new();
+            let folds = Folds::new();
             if let Some(mut cursor) = LeafCursor::new(jsons) {
                 let mut prior_path = cursor.to_path();
                 while let Some(()) = cursor.advance(&folds) {
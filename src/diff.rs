@@ -1,5 +1,9 @@
-use crate::jq::jv::{JVBool, JVNull, JVNumber, JVString, JV};
-use similar::{capture_diff, Algorithm, DiffOp};
+use crate::{
+    cursor::{FocusPosition, ValuePath},
+    jq::jv::{JVBool, JVNull, JVNumber, JVString, JV},
+};
+use similar::{capture_diff, Algorithm, DiffOp, DiffTag};
+use std::collections::HashSet;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum DiffElem {
@@ -13,55 +17,119 @@ enum DiffElem {
     ArrayEnd,
 }
 
-fn to_diffable(jv: JV) -> Vec<DiffElem> {
-    let mut out = Vec::new();
-    write_diffable(jv, &mut out);
-    out
+// The elements of jsons in order, along with the path of the line each one is drawn on. Object
+// keys are sorted so that the same object always diffs the same way.
+fn to_diffable(jsons: &[JV]) -> (Vec<DiffElem>, Vec<ValuePath>) {
+    let mut elems = Vec::new();
+    let mut paths = Vec::new();
+    for (top_index, jv) in jsons.iter().enumerate() {
+        let mut out = DiffableWriter {
+            top_index,
+            frames: Vec::new(),
+            elems: &mut elems,
+            paths: &mut paths,
+        };
+        out.write(jv.clone());
+    }
+    (elems, paths)
+}
+
+struct DiffableWriter<'a> {
+    top_index: usize,
+    frames: Vec<usize>,
+    elems: &'a mut Vec<DiffElem>,
+    paths: &'a mut Vec<ValuePath>,
 }
 
-fn write_diffable(jv: JV, out: &mut Vec<DiffElem>) {
-    match jv {
-        JV::Null(x) => out.push(DiffElem::Null(x)),
-        JV::Bool(x) => out.push(DiffElem::Bool(x)),
-        JV::Number(x) => out.push(DiffElem::Number(x)),
-        JV::String(x) => out.push(DiffElem::String(x)),
-        JV::Object(obj) => {
-            out.push(DiffElem::ObjectStart);
-            let mut kvs: Vec<(JVString, JV)> = obj.into_iter().map(|(k, v)| (k, v)).collect();
-            kvs.sort_by(|x, y| x.0.cmp(&y.0));
-            for (k, v) in kvs {
-                write_diffable(k.into(), out);
-                write_diffable(v, out);
+impl<'a> DiffableWriter<'a> {
+    fn push(&mut self, elem: DiffElem, focus_position: FocusPosition) {
+        self.elems.push(elem);
+        self.paths.push(ValuePath::new(
+            self.top_index,
+            self.frames.clone(),
+            focus_position,
+        ));
+    }
+    fn write(&mut self, jv: JV) {
+        match jv {
+            JV::Null(x) => self.push(DiffElem::Null(x), FocusPosition::Value),
+            JV::Bool(x) => self.push(DiffElem::Bool(x), FocusPosition::Value),
+            JV::Number(x) => self.push(DiffElem::Number(x), FocusPosition::Value),
+            JV::String(x) => self.push(DiffElem::String(x), FocusPosition::Value),
+            JV::Object(obj) => {
+                self.push(DiffElem::ObjectStart, FocusPosition::Start);
+                // Paths use the index the key is iterated at, not its sorted position
+                let mut kvs: Vec<(usize, (JVString, JV))> = obj.into_iter().enumerate().collect();
+                kvs.sort_by(|x, y| (x.1).0.cmp(&(y.1).0));
+                for (i, (k, v)) in kvs {
+                    self.frames.push(i);
+                    // The key is drawn on the same line as the start of its value
+                    let focus_position = match v {
+                        JV::Object(_) | JV::Array(_) => FocusPosition::Start,
+                        _ => FocusPosition::Value,
+                    };
+                    self.push(DiffElem::String(k), focus_position);
+                    self.write(v);
+                    self.frames.pop();
+                }
+                self.push(DiffElem::ObjectEnd, FocusPosition::End);
             }
-            out.push(DiffElem::ObjectEnd);
-        }
-        JV::Array(arr) => {
-            out.push(DiffElem::ArrayStart);
-            for child in arr.iter() {
-                write_diffable(child, out);
+            JV::Array(arr) => {
+                self.push(DiffElem::ArrayStart, FocusPosition::Start);
+                for (i, child) in arr.iter().enumerate() {
+                    self.frames.push(i);
+                    self.write(child);
+                    self.frames.pop();
+                }
+                self.push(DiffElem::ArrayEnd, FocusPosition::End);
             }
-            out.push(DiffElem::ArrayEnd);
         }
     }
 }
 
-fn diff(a: JV, b: JV) -> Vec<DiffOp> {
-    let diffable_a = to_diffable(a);
-    let diffable_b = to_diffable(b);
-    capture_diff(
-        Algorithm::Patience,
-        &diffable_a,
-        0..diffable_a.len(),
-        &diffable_b,
-        0..diffable_b.len(),
-    )
+pub fn diff(a: JV, b: JV) -> Vec<DiffOp> {
+    let (diffable_a, _) = to_diffable(&[a]);
+    let (diffable_b, _) = to_diffable(&[b]);
+    diff_elems(&diffable_a, &diffable_b)
+}
+
+fn diff_elems(a: &[DiffElem], b: &[DiffElem]) -> Vec<DiffOp> {
+    capture_diff(Algorithm::Patience, a, 0..a.len(), b, 0..b.len())
+}
+
+// The lines that differ between two sets of values, by the paths of the leaves drawing them.
+#[derive(Debug, Default, PartialEq)]
+pub struct DiffLines {
+    // Lines of the old values that aren't in the new ones
+    pub removed: HashSet<ValuePath>,
+    // Lines of the new values that aren't in the old ones
+    pub added: HashSet<ValuePath>,
+}
+
+pub fn diff_lines(old: &[JV], new: &[JV]) -> DiffLines {
+    let (old_elems, old_paths) = to_diffable(old);
+    let (new_elems, new_paths) = to_diffable(new);
+    let mut lines = DiffLines::default();
+    for op in diff_elems(&old_elems, &new_elems) {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        if tag == DiffTag::Equal {
+            continue;
+        }
+        lines.removed.extend(old_paths[old_range].iter().cloned());
+        lines.added.extend(new_paths[new_range].iter().cloned());
+    }
+    lines
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{diff, to_diffable};
-    use crate::jq::jv::JV;
+    use super::{diff, diff_lines, to_diffable};
+    use crate::{
+        cursor::{parse_path, ValuePath},
+        jq::jv::JV,
+    };
     use serde_json::json;
+    use std::collections::HashSet;
     #[test]
     fn unit_diff() {
         let a: JV = (&json!({
@@ -74,8 +142,28 @@ mod tests {
             "A" : {"Foo":"Bar"},
         }))
             .into();
-        dbg!(to_diffable(a.clone()));
-        dbg!(to_diffable(b.clone()));
+        dbg!(to_diffable(&[a.clone()]));
+        dbg!(to_diffable(&[b.clone()]));
         dbg!(diff(a, b));
     }
+    #[test]
+    fn unit_diff_lines() {
+        let old: Vec<JV> = vec![(&json!({"a": 1, "b": [1, 2], "c": true})).into()];
+        let new: Vec<JV> = vec![(&json!({"a": 1, "b": [1, 3, 4], "c": true})).into()];
+        let paths = |values: &[JV], paths: &[&str]| -> HashSet<ValuePath> {
+            paths
+                .iter()
+                .map(|path| parse_path(path, values).unwrap())
+                .collect()
+        };
+        let lines = diff_lines(&old, &new);
+        assert_eq!(lines.removed, paths(&old, &[".b[1]"]));
+        assert_eq!(lines.added, paths(&new, &[".b[1]", ".b[2]"]));
+        // A changed key marks the line it's on
+        let new: Vec<JV> = vec![(&json!({"a": 1, "d": [1, 2], "c": true})).into()];
+        let lines = diff_lines(&old, &new);
+        assert!(lines.removed.contains(&parse_path(".b", &old).unwrap()));
+        assert!(lines.added.contains(&parse_path(".d", &new).unwrap()));
+        assert!(!lines.added.contains(&parse_path(".a", &new).unwrap()));
+    }
 }
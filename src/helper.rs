@@ -1,3 +1,4 @@
+use crate::{cursor::format_jq_key, jq::jv::JV};
 use rustyline::{
     completion::{Completer, FilenameCompleter},
     highlight::Highlighter,
@@ -35,3 +36,151 @@ impl Highlighter for Helper {}
 impl Hinter for Helper {}
 impl Validator for Helper {}
 impl HelperTrait for Helper {}
+
+const JQ_BUILTINS: &[&str] = &[
+    "add",
+    "all",
+    "any",
+    "ascii_downcase",
+    "ascii_upcase",
+    "contains",
+    "del",
+    "empty",
+    "endswith",
+    "error",
+    "first",
+    "flatten",
+    "from_entries",
+    "fromjson",
+    "group_by",
+    "has",
+    "in",
+    "index",
+    "indices",
+    "inside",
+    "join",
+    "keys",
+    "keys_unsorted",
+    "last",
+    "length",
+    "limit",
+    "ltrimstr",
+    "map",
+    "map_values",
+    "max",
+    "max_by",
+    "min",
+    "min_by",
+    "not",
+    "path",
+    "paths",
+    "range",
+    "recurse",
+    "reduce",
+    "reverse",
+    "rtrimstr",
+    "select",
+    "sort",
+    "sort_by",
+    "split",
+    "startswith",
+    "test",
+    "to_entries",
+    "tojson",
+    "tonumber",
+    "tostring",
+    "type",
+    "unique",
+    "unique_by",
+    "values",
+    "with_entries",
+];
+
+// Completes jq builtins, and (after a `.`) the keys of the top-level objects being queried.
+pub struct JqCompleter {
+    keys: Vec<String>,
+}
+impl JqCompleter {
+    pub fn new(values: &[JV]) -> Self {
+        let mut keys: Vec<String> = values
+            .iter()
+            .filter_map(|value| match value {
+                JV::Object(obj) => Some(obj.iter().map(|(k, _)| k.to_owned())),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        JqCompleter { keys }
+    }
+    fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '"'))
+            .map_or(0, |i| i + 1);
+        let prefix = &before[start..];
+        let candidates = if before[..start].ends_with('.') {
+            self.keys
+                .iter()
+                .map(|key| format_jq_key(key)[1..].to_owned())
+                .filter(|key| key.starts_with(prefix))
+                .collect()
+        } else {
+            JQ_BUILTINS
+                .iter()
+                .filter(|builtin| builtin.starts_with(prefix))
+                .map(|&builtin| builtin.to_owned())
+                .collect()
+        };
+        (start, candidates)
+    }
+}
+impl Completer for JqCompleter {
+    type Candidate = String;
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> Result<(usize, Vec<Self::Candidate>)> {
+        Ok(self.completions(line, pos))
+    }
+}
+impl Highlighter for JqCompleter {}
+impl Hinter for JqCompleter {}
+impl Validator for JqCompleter {}
+impl HelperTrait for JqCompleter {}
+
+#[cfg(test)]
+mod tests {
+    use super::JqCompleter;
+    use crate::jq::jv::JV;
+    use serde_json::json;
+
+    #[test]
+    fn unit_jq_completions() {
+        let values: Vec<JV> = vec![
+            (&json!({"name": 1.0, "nested": {"inner": 2.0}})).into(),
+            (&json!({"first name": 3.0})).into(),
+            (&json!([4.0])).into(),
+        ];
+        let completer = JqCompleter::new(&values);
+        assert_eq!(
+            completer.completions(".n", 2),
+            (1, vec!["name".to_owned(), "nested".to_owned()])
+        );
+        assert_eq!(
+            completer.completions(".[] | .\"fi", 10),
+            (7, vec!["\"first name\"".to_owned()])
+        );
+        assert_eq!(
+            completer.completions(".name | to_e", 12),
+            (8, vec!["to_entries".to_owned()])
+        );
+        assert_eq!(
+            completer.completions("map(sel", 7),
+            (4, vec!["select".to_owned()])
+        );
+    }
+}
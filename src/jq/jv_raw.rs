@@ -45,6 +45,9 @@ impl Clone for JVRaw {
 
 impl PartialEq for JVRaw {
     fn eq(&self, other: &Self) -> bool {
+        if self.is_identical(other) {
+            return true;
+        }
         let self_ptr = self.clone().unwrap_without_drop();
         let other_ptr = other.clone().unwrap_without_drop();
         let res = unsafe { jv_equal(self_ptr, other_ptr) };
@@ -138,6 +141,19 @@ impl JVRaw {
         }
     }
 
+    // Whether both are the same view of the same allocation, so they must be equal without looking
+    // inside. Only strings, arrays and objects are allocated; other kinds are never identical.
+    pub fn is_identical(&self, other: &Self) -> bool {
+        match self.get_kind() {
+            JVKind::String | JVKind::Array | JVKind::Object => {
+                self.ptr.kind_flags == other.ptr.kind_flags
+                    && self.ptr.offset == other.ptr.offset
+                    && self.ptr.size == other.ptr.size
+                    && unsafe { self.ptr.u.ptr == other.ptr.u.ptr }
+            }
+            _ => false,
+        }
+    }
     pub fn get_kind(&self) -> JVKind {
         let raw_kind = unsafe { jv_get_kind(self.ptr) };
         #[allow(non_upper_case_globals)]
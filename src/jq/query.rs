@@ -2,20 +2,136 @@ use super::{
     jv::JV,
     jv_raw::{JVKind, JVRaw},
 };
-use jq_sys::{jq_compile, jq_init, jq_next, jq_set_error_cb, jq_start, jq_state, jq_teardown};
-use std::{convert::TryInto, ffi::CString, os::raw::c_void};
+use jq_sys::{
+    jq_compile, jq_halt, jq_halted, jq_init, jq_next, jq_set_error_cb, jq_start, jq_state,
+    jq_teardown, jv_invalid,
+};
+use std::{
+    convert::TryInto,
+    ffi::CString,
+    fmt,
+    ops::Range,
+    os::raw::c_void,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Queries run on the UI thread (JVs aren't Send, and the values are shared with the views), so a
+// runaway query like `repeat(1)` is cut off at the result limit, or halted by a Watchdog once it's
+// been running this long.
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(10);
+// How many results a query keeps before the rest are dropped, until the user changes it
+pub const DEFAULT_RESULT_LIMIT: usize = 100_000;
+
+#[derive(Debug)]
+pub struct QueryResults {
+    pub values: Vec<JV>,
+    // Whether the query had more results than the limit, which were dropped
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JqError {
+    // jq's messages, as returned by JQ::compile
+    Compile(Vec<String>),
+    // input_index is which of the values the query was run on raised the error
+    Runtime { input_index: usize, message: String },
+    // The query was stopped, and whatever it had produced thrown away
+    TimedOut(Duration),
+}
+
+impl JqError {
+    // The top level value the query failed on, if it got far enough to run
+    pub fn input_index(&self) -> Option<usize> {
+        match self {
+            JqError::Runtime { input_index, .. } => Some(*input_index),
+            JqError::Compile(_) | JqError::TimedOut(_) => None,
+        }
+    }
+    pub fn messages(&self) -> Vec<String> {
+        match self {
+            JqError::Compile(errors) => errors.clone(),
+            JqError::Runtime {
+                input_index,
+                message,
+            } => vec![format!("jq: error (at input {}): {}", input_index, message)],
+            JqError::TimedOut(timeout) => vec![format!(
+                "jq: query aborted: still running after {} seconds",
+                timeout.as_secs()
+            )],
+        }
+    }
+}
+
+impl fmt::Display for JqError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.messages().join("\n"))
+    }
+}
+
+// The part of an error message saying where the problem is: "line N" in the program for compile
+// errors (jq doesn't give a column), or "input N" for the ones from run_jq_query.
+pub fn error_location(message: &str) -> Option<Range<usize>> {
+    ["line ", "input "].iter().find_map(|keyword| {
+        message.match_indices(keyword).find_map(|(start, _)| {
+            let digits_start = start + keyword.len();
+            let rest = &message[digits_start..];
+            let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+            let terminated = rest[digits..].starts_with(':') || rest[digits..].starts_with(')');
+            if digits > 0 && terminated {
+                Some(start..digits_start + digits)
+            } else {
+                None
+            }
+        })
+    })
+}
+
+// A shell command running the query, for pasting into a script. Single quotes can't be escaped
+// inside single quotes, so they close the quoting, add an escaped quote, and reopen it.
+pub fn jq_command(query: &str) -> String {
+    format!("jq '{}'", query.replace('\'', r"'\''"))
+}
 
 pub fn run_jq_query<'a, I: IntoIterator<Item = &'a JV>>(
     content: I,
     prog: &mut JQ,
-) -> Result<Vec<JV>, String> {
-    let mut results: Vec<JV> = Vec::new();
-    for value in content {
+    max_results: usize,
+) -> Result<QueryResults, JqError> {
+    run_jq_query_limited(content, prog, max_results, QUERY_TIMEOUT)
+}
+
+fn run_jq_query_limited<'a, I: IntoIterator<Item = &'a JV>>(
+    content: I,
+    prog: &mut JQ,
+    max_results: usize,
+    timeout: Duration,
+) -> Result<QueryResults, JqError> {
+    prog.start_timer(timeout);
+    let mut values: Vec<JV> = Vec::new();
+    for (input_index, value) in content.into_iter().enumerate() {
         for res in prog.execute(value.clone().into()) {
-            results.push(res.try_into()?);
+            if values.len() == max_results {
+                return Ok(QueryResults {
+                    values,
+                    truncated: true,
+                });
+            }
+            let res = res.try_into().map_err(|message| JqError::Runtime {
+                input_index,
+                message,
+            })?;
+            values.push(res);
+        }
+        if prog.timed_out() {
+            return Err(JqError::TimedOut(timeout));
         }
     }
-    Ok(results)
+    Ok(QueryResults {
+        values,
+        truncated: false,
+    })
 }
 
 #[derive(Debug)]
@@ -24,6 +140,100 @@ pub struct JQ {
     // We want to make sure the vec pointer doesn't move, so we can keep pushing to it.
     #[allow(clippy::box_vec)]
     errors: Box<Vec<JVRaw>>,
+    // Set by start_timer: runs are halted once it passes
+    deadline: Option<Instant>,
+    timed_out: bool,
+    watchdog: Watchdog,
+}
+
+// A jq_state, for halting it from the watchdog thread. That only sets a flag jq checks before
+// every instruction, so unlike the rest of jq it's fine to do from another thread.
+#[derive(Debug, Clone, Copy)]
+struct HaltHandle(*mut jq_state);
+unsafe impl Send for HaltHandle {}
+
+#[derive(Debug, Default)]
+struct WatchdogState {
+    // The program that's running, and when to halt it
+    armed: Option<(HaltHandle, Instant)>,
+    // Whether the last program armed was halted
+    fired: bool,
+    // Set when the JQ is dropped, to end the thread
+    closed: bool,
+}
+
+// Halts a running program once its deadline passes, from a thread of its own, since jq_next
+// doesn't come back until there's a result. That stops programs that never produce one, like
+// `def f: f; f` or `last(range(1e12))`, not just ones that produce too many.
+#[derive(Debug, Default)]
+struct Watchdog {
+    shared: Arc<(Mutex<WatchdogState>, Condvar)>,
+    // The thread is only started the first time a deadline is set
+    started: bool,
+}
+
+impl Watchdog {
+    fn arm(&mut self, ptr: *mut jq_state, deadline: Instant) {
+        if !self.started {
+            let shared = self.shared.clone();
+            thread::spawn(move || watch(&shared));
+            self.started = true;
+        }
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().expect("watchdog lock poisoned");
+        state.armed = Some((HaltHandle(ptr), deadline));
+        state.fired = false;
+        changed.notify_one();
+    }
+    // Returns whether the program was halted
+    fn disarm(&self) -> bool {
+        let (state, changed) = &*self.shared;
+        let mut state = state.lock().expect("watchdog lock poisoned");
+        state.armed = None;
+        changed.notify_one();
+        state.fired
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let (state, changed) = &*self.shared;
+        if let Ok(mut state) = state.lock() {
+            state.closed = true;
+            changed.notify_one();
+        }
+    }
+}
+
+fn watch(shared: &(Mutex<WatchdogState>, Condvar)) {
+    let (state, changed) = shared;
+    let mut state = state.lock().expect("watchdog lock poisoned");
+    while !state.closed {
+        let armed = state.armed;
+        state = match armed {
+            None => changed.wait(state).expect("watchdog lock poisoned"),
+            Some((HaltHandle(ptr), deadline)) => {
+                let now = Instant::now();
+                if now < deadline {
+                    let (state, _) = changed
+                        .wait_timeout(state, deadline - now)
+                        .expect("watchdog lock poisoned");
+                    state
+                } else {
+                    // The program is still running, since disarming takes the lock. It might
+                    // have halted itself with `halt` though, and jq asserts that only happens once.
+                    unsafe {
+                        if jq_halted(ptr) == 0 {
+                            jq_halt(ptr, jv_invalid(), jv_invalid());
+                        }
+                    }
+                    state.armed = None;
+                    state.fired = true;
+                    state
+                }
+            }
+        };
+    }
 }
 
 impl Drop for JQ {
@@ -38,7 +248,21 @@ impl JQ {
         let mut errors = Box::new(Vec::new());
         let err_ptr = (errors.as_mut() as *mut Vec<JVRaw>) as *mut c_void;
         unsafe { jq_set_error_cb(ptr, Some(jq_error_callback), err_ptr) };
-        JQ { ptr, errors }
+        JQ {
+            ptr,
+            errors,
+            deadline: None,
+            timed_out: false,
+            watchdog: Watchdog::default(),
+        }
+    }
+    // Halts anything run from now on once timeout has passed. timed_out says whether it had to.
+    pub fn start_timer(&mut self, timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout);
+        self.timed_out = false;
+    }
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
     }
     fn take_errors(&mut self) -> impl Iterator<Item = JVRaw> + '_ {
         self.errors.as_mut().drain(..)
@@ -57,8 +281,27 @@ impl JQ {
             Err(strings)
         }
     }
+    // Whether any output for the input is truthy (anything but false, null or an error). Once the
+    // timer runs out, nothing is.
+    pub fn any_truthy(&mut self, input: &JV) -> bool {
+        self.execute(input.clone().into()).any(|res| {
+            !matches!(
+                res.get_kind(),
+                JVKind::Invalid | JVKind::Null | JVKind::False
+            )
+        })
+    }
     fn execute(&mut self, input: JVRaw) -> impl Iterator<Item = JVRaw> + '_ {
         unsafe { jq_start(self.ptr, input.unwrap_without_drop(), 0) };
+        match self.deadline {
+            Some(deadline) if deadline <= Instant::now() => {
+                // Out of time already, so halt it before it starts
+                unsafe { jq_halt(self.ptr, jv_invalid(), jv_invalid()) };
+                self.timed_out = true;
+            }
+            Some(deadline) => self.watchdog.arm(self.ptr, deadline),
+            None => {}
+        }
         JQResults { jq: self }
     }
 }
@@ -99,16 +342,22 @@ impl<'a> Drop for JQResults<'a> {
         // not guaranteed to be called, but if it isn't called, then error won't be freed anyway,
         // so that's not an issue.
         unsafe { jq_set_error_cb(self.jq.ptr, None, std::ptr::null_mut()) };
+        if self.jq.deadline.is_some() && self.jq.watchdog.disarm() {
+            self.jq.timed_out = true;
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{run_jq_query, JQ};
+    use super::{
+        error_location, jq_command, run_jq_query, run_jq_query_limited, JqError,
+        DEFAULT_RESULT_LIMIT, JQ,
+    };
     use crate::{jq::jv::JV, testing::arb_json};
     use proptest::proptest;
     use serde_json::{json, value::Value};
-    use std::{cell::RefCell, convert::TryInto};
+    use std::{cell::RefCell, convert::TryInto, time::Duration};
     fn sample_json() -> JV {
         let val = json!({
             "hello": "world",
@@ -133,15 +382,19 @@ mod tests {
     #[test]
     fn unit_jq_simple() {
         let mut prog = JQ::compile(".array").unwrap();
-        let res = run_jq_query(&[sample_json()], &mut prog).unwrap();
-        assert_eq!(res, vec![(&json!(["a", "b", "c", 1.0, 2.0, 3.0])).into()]);
+        let res = run_jq_query(&[sample_json()], &mut prog, DEFAULT_RESULT_LIMIT).unwrap();
+        assert_eq!(
+            res.values,
+            vec![(&json!(["a", "b", "c", 1.0, 2.0, 3.0])).into()]
+        );
     }
     #[test]
     fn unit_jq_spread() {
         let mut prog = JQ::compile(".array | .[]").unwrap();
-        let res = run_jq_query(&[sample_json()], &mut prog).unwrap();
+        let res = run_jq_query(&[sample_json()], &mut prog, DEFAULT_RESULT_LIMIT).unwrap();
+        assert!(!res.truncated);
         assert_eq!(
-            res,
+            res.values,
             vec![
                 (&json!("a")).into(),
                 (&json!("b")).into(),
@@ -165,7 +418,74 @@ mod tests {
     #[test]
     fn unit_jq_runtime_error() {
         let mut prog = JQ::compile(".[1]").unwrap();
-        let res = run_jq_query(&[sample_json()], &mut prog);
-        assert_eq!(res.unwrap_err(), "Cannot index object with number");
+        let res = run_jq_query(
+            &[(&json!([1.0])).into(), sample_json()],
+            &mut prog,
+            DEFAULT_RESULT_LIMIT,
+        );
+        let err = res.unwrap_err();
+        assert_eq!(err.input_index(), Some(1));
+        assert_eq!(
+            err,
+            JqError::Runtime {
+                input_index: 1,
+                message: "Cannot index object with number".to_owned()
+            }
+        );
+        let message = err.to_string();
+        assert_eq!(
+            message,
+            "jq: error (at input 1): Cannot index object with number"
+        );
+        assert_eq!(&message[error_location(&message).unwrap()], "input 1");
+    }
+    #[test]
+    fn unit_jq_runaway_query() {
+        let mut prog = JQ::compile("repeat(1)").unwrap();
+        let res = run_jq_query_limited(&[sample_json()], &mut prog, 100, Duration::from_secs(60));
+        let res = res.unwrap();
+        assert!(res.truncated);
+        assert_eq!(res.values.len(), 100);
+        let res = run_jq_query_limited(&[sample_json()], &mut prog, usize::MAX, Duration::ZERO);
+        assert_eq!(res.unwrap_err(), JqError::TimedOut(Duration::ZERO));
+        // Exactly max_results results isn't truncated
+        let mut prog = JQ::compile(".hello").unwrap();
+        let res = run_jq_query_limited(&[sample_json()], &mut prog, 1, Duration::from_secs(60));
+        let res = res.unwrap();
+        assert!(!res.truncated);
+        assert_eq!(res.values, vec![(&json!("world")).into()]);
+    }
+    #[test]
+    fn unit_jq_query_without_output() {
+        // Neither of these ever gets as far as producing a result
+        for query in ["def f: f; f", "last(range(1e12))"] {
+            let mut prog = JQ::compile(query).unwrap();
+            let timeout = Duration::from_millis(200);
+            let res = run_jq_query_limited(&[sample_json()], &mut prog, usize::MAX, timeout);
+            assert_eq!(res.unwrap_err(), JqError::TimedOut(timeout));
+        }
+        let mut prog = JQ::compile("def f: f; f").unwrap();
+        prog.start_timer(Duration::from_millis(200));
+        assert!(!prog.any_truthy(&sample_json()));
+        assert!(prog.timed_out());
+        let mut prog = JQ::compile(".hello").unwrap();
+        prog.start_timer(Duration::from_secs(60));
+        assert!(prog.any_truthy(&sample_json()));
+        assert!(!prog.timed_out());
+    }
+    #[test]
+    fn unit_error_location() {
+        let message = "jq: error: lol/0 is not defined at <top-level>, line 12:\nlol";
+        assert_eq!(&message[error_location(message).unwrap()], "line 12");
+        assert_eq!(
+            error_location("Cannot iterate over a multiline string"),
+            None
+        );
+        assert_eq!(error_location("jq: 1 compile error"), None);
+    }
+    #[test]
+    fn unit_jq_command() {
+        assert_eq!(jq_command(".[] | .name"), "jq '.[] | .name'");
+        assert_eq!(jq_command(r#"."it's""#), r#"jq '."it'\''s"'"#);
     }
 }
@@ -1,46 +1,110 @@
 use tui::layout::{Constraint, Direction, Layout, Rect};
+
+pub const DEFAULT_SPLIT_PERCENT: u16 = 50;
+// Narrowest (or shortest, when stacked) a pane gets: room for 7 columns of json, plus borders
+const MIN_PANE_SIZE: u16 = 7 + 2;
+
+// How the two views share the screen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LayoutMode {
+    #[default]
+    SideBySide,
+    // One above the other, for tall narrow terminals
+    Stacked,
+}
+
+impl LayoutMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            LayoutMode::SideBySide => LayoutMode::Stacked,
+            LayoutMode::Stacked => LayoutMode::SideBySide,
+        }
+    }
+}
 #[derive(Clone, Copy, Debug)]
 pub struct JexLayout {
     pub tree: Option<Rect>,
+    pub outline: Option<Rect>,
     pub left: Rect,
     pub right: Rect,
+    pub breadcrumb: Rect,
     pub query: Rect,
 }
 
 impl JexLayout {
-    pub fn new(size: Rect, show_tree: bool) -> JexLayout {
+    // split_percent is how much of the room for the views goes to the left (or top) one
+    pub fn new(
+        size: Rect,
+        show_tree: bool,
+        show_outline: bool,
+        mode: LayoutMode,
+        split_percent: u16,
+    ) -> JexLayout {
         let vchunks = Layout::default()
             .direction(Direction::Vertical)
-            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .constraints(
+                [
+                    Constraint::Min(0),
+                    Constraint::Length(1),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
             .split(size);
+        // The tree goes on the left of the views and the outline on the right
+        let mut constraints = vec![Constraint::Ratio(1, 1)];
         if show_tree {
-            let tree_split = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Length(20), Constraint::Ratio(1, 1)].as_ref())
-                .split(vchunks[0]);
-            let views = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)].as_ref())
-                .split(tree_split[1]);
-            JexLayout {
-                tree: Some(tree_split[0]),
-                left: views[0],
-                right: views[1],
-                query: vchunks[1],
-            }
+            constraints.insert(0, Constraint::Length(20));
+        }
+        if show_outline {
+            constraints.push(Constraint::Length(24));
+        }
+        let hchunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(constraints)
+            .split(vchunks[0]);
+        let views_rect = if show_tree { hchunks[1] } else { hchunks[0] };
+        let (views_direction, room) = match mode {
+            LayoutMode::SideBySide => (Direction::Horizontal, views_rect.width),
+            LayoutMode::Stacked => (Direction::Vertical, views_rect.height),
+        };
+        let first = (room as u32 * split_percent.min(100) as u32 / 100) as u16;
+        let first = if room >= 2 * MIN_PANE_SIZE {
+            first.clamp(MIN_PANE_SIZE, room - MIN_PANE_SIZE)
         } else {
-            let views = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)].as_ref())
-                .split(vchunks[0]);
-            JexLayout {
-                tree: None,
-                left: views[0],
-                right: views[1],
-                query: vchunks[1],
-            }
+            room / 2
+        };
+        let views = Layout::default()
+            .direction(views_direction)
+            .constraints([Constraint::Length(first), Constraint::Min(0)].as_ref())
+            .split(views_rect);
+        JexLayout {
+            tree: if show_tree { Some(hchunks[0]) } else { None },
+            outline: if show_outline {
+                hchunks.last().copied()
+            } else {
+                None
+            },
+            left: views[0],
+            right: views[1],
+            breadcrumb: vchunks[1],
+            query: vchunks[2],
         }
     }
+    // The split percents that still move the divider, since past them new holds a pane at its
+    // minimum size. None if there isn't room for both panes to get their minimum.
+    pub fn split_percent_range(&self, mode: LayoutMode) -> Option<(u16, u16)> {
+        let room = match mode {
+            LayoutMode::SideBySide => self.left.width + self.right.width,
+            LayoutMode::Stacked => self.left.height + self.right.height,
+        };
+        if room < 2 * MIN_PANE_SIZE {
+            return None;
+        }
+        // The smallest percent that gives the left pane this size
+        let percent = |size: u16| ((size as u32 * 100 + room as u32 - 1) / room as u32) as u16;
+        Some((percent(MIN_PANE_SIZE), percent(room - MIN_PANE_SIZE)))
+    }
 }
 
 pub fn flash(size: Rect) -> Rect {
@@ -62,3 +126,91 @@ pub fn flash(size: Rect) -> Rect {
         .split(v_layout[1]);
     h_layout[1]
 }
+
+impl JexLayout {
+    // Gives the left (or right) view the room for both, and leaves the other one an empty rect.
+    // Unless show_query, the query line is hidden too, and the breadcrumbs move down into its place.
+    pub fn single_pane(self, left: bool, show_query: bool) -> Self {
+        let mut full = self.left.union(self.right);
+        let mut breadcrumb = self.breadcrumb;
+        let mut query = self.query;
+        if !show_query {
+            full.height += query.height;
+            breadcrumb.y += query.height;
+            query = Rect {
+                y: query.bottom(),
+                height: 0,
+                ..query
+            };
+        }
+        let (left, right) = if left {
+            (full, Rect::default())
+        } else {
+            (Rect::default(), full)
+        };
+        JexLayout {
+            left,
+            right,
+            breadcrumb,
+            query,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JexLayout, LayoutMode, MIN_PANE_SIZE};
+    use tui::layout::Rect;
+
+    #[test]
+    fn unit_split_percent() {
+        let size = Rect::new(0, 0, 100, 40);
+        let layout = JexLayout::new(size, false, false, LayoutMode::SideBySide, 30);
+        assert_eq!((layout.left.width, layout.right.width), (30, 70));
+        // Neither pane gets squeezed below the minimum
+        let layout = JexLayout::new(size, false, false, LayoutMode::SideBySide, 100);
+        assert_eq!(layout.right.width, MIN_PANE_SIZE);
+        let layout = JexLayout::new(size, false, false, LayoutMode::Stacked, 0);
+        assert_eq!(layout.left.height, MIN_PANE_SIZE);
+        assert_eq!(layout.left.height + layout.right.height, 38);
+    }
+    #[test]
+    fn unit_split_percent_range() {
+        let size = Rect::new(0, 0, 100, 40);
+        let layout = JexLayout::new(size, false, false, LayoutMode::SideBySide, 50);
+        assert_eq!(
+            layout.split_percent_range(LayoutMode::SideBySide),
+            Some((MIN_PANE_SIZE, 100 - MIN_PANE_SIZE))
+        );
+        // The ends of the range give each pane its minimum size
+        let layout = JexLayout::new(size, false, false, LayoutMode::Stacked, 50);
+        let (min, max) = layout.split_percent_range(LayoutMode::Stacked).unwrap();
+        let at = |percent| JexLayout::new(size, false, false, LayoutMode::Stacked, percent);
+        assert_eq!(at(min).left.height, MIN_PANE_SIZE);
+        assert_eq!(at(max).right.height, MIN_PANE_SIZE);
+        // Too small for both panes to get their minimum
+        let layout = JexLayout::new(
+            Rect::new(0, 0, 15, 40),
+            false,
+            false,
+            LayoutMode::SideBySide,
+            50,
+        );
+        assert_eq!(layout.split_percent_range(LayoutMode::SideBySide), None);
+    }
+    #[test]
+    fn unit_single_pane() {
+        let size = Rect::new(0, 0, 100, 40);
+        let layout = JexLayout::new(size, false, false, LayoutMode::SideBySide, 50);
+        let single = layout.single_pane(false, false);
+        assert_eq!(single.left.area(), 0);
+        assert_eq!(single.right, Rect::new(0, 0, 100, 39));
+        assert_eq!(single.breadcrumb.y, 39);
+        assert_eq!(single.query.height, 0);
+        // Editing a query brings the query line back
+        let single = layout.single_pane(true, true);
+        assert_eq!(single.left, Rect::new(0, 0, 100, 38));
+        assert_eq!(single.query, layout.query);
+    }
+}
@@ -1,10 +1,13 @@
 pub mod app;
 pub mod cursor;
-mod diff;
+pub mod diff;
 pub mod helper;
 pub mod jq;
 pub mod layout;
 pub mod lines;
+pub mod session;
 #[cfg(test)]
 mod testing;
+pub mod theme;
 pub mod view_tree;
+pub mod watch;
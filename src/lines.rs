@@ -1,11 +1,14 @@
-use crate::jq::jv::JVString;
-use std::{cell::RefCell, matches, ops::Range, rc::Rc};
+use crate::{
+    jq::jv::{JVString, JV},
+    theme::Theme,
+};
+use std::{cell::RefCell, fmt::Write, matches, ops::Range, rc::Rc};
 use tui::{
-    style::{Color, Modifier, Style},
+    style::Style,
     text::{Span, Spans},
 };
 use unicode_general_category::{get_general_category, GeneralCategory};
-use unicode_width::UnicodeWidthChar;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Leaf {
@@ -21,47 +24,79 @@ pub enum LeafContent {
     Bool(bool),
     Number(f64),
     String(JVString),
-    FoldedArray(usize),
+    FoldedArray(FoldedSize),
     ArrayStart,
     ArrayEnd,
-    FoldedObject(usize),
+    FoldedObject(FoldedSize),
     ObjectStart,
     ObjectEnd,
+    // A whole container drawn on one line
+    Inline(JV),
+}
+
+// What's shown after a folded container
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FoldedSize {
+    Children(usize),
+    Descendants(usize),
+}
+
+impl std::fmt::Display for FoldedSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FoldedSize::Children(n) => write!(f, "({} children)", n),
+            FoldedSize::Descendants(n) => write!(f, "({} descendants)", n),
+        }
+    }
 }
 
 use std::fmt::Debug;
 impl Leaf {
-    pub fn render(self) -> LineFragments {
+    pub fn render(self, display: DisplayOptions) -> LineFragments {
         let indent = LineFragment::new_unstyled(" ".repeat(self.indent as usize), false);
+        // On the line a container starts, the cursor covers its key along with the bracket, so
+        // it's clear which entry of a dense object is selected.
+        let key_style = match self.content {
+            LeafContent::ArrayStart
+            | LeafContent::ObjectStart
+            | LeafContent::FoldedArray(_)
+            | LeafContent::FoldedObject(_)
+            | LeafContent::Inline(_) => StyleType::Highlightable,
+            _ => StyleType::Unhighlightable,
+        };
         let mut out = match self.key {
             Some(key) => vec![
                 indent,
-                LineFragment::new_unstyled("\"", false),
-                LineFragment::new_unstyled(key, true),
-                LineFragment::new_unstyled("\" : ", false),
+                LineFragment::new("\"", false, key_style),
+                LineFragment::new(key, true, key_style),
+                LineFragment::new("\" : ", false, key_style),
             ],
             _ => vec![indent],
         };
         match self.content {
             LeafContent::Null => {
-                out.push(LineFragment::new("null", false, StyleType::Highlightable));
+                out.push(LineFragment::new(
+                    display.null_str(),
+                    false,
+                    StyleType::Null,
+                ));
                 if self.comma {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
             }
             LeafContent::String(string) => {
-                out.push(LineFragment::new("\"", false, StyleType::Highlightable));
-                out.push(LineFragment::new(string, true, StyleType::Highlightable));
-                out.push(LineFragment::new("\"", false, StyleType::Highlightable));
+                out.push(LineFragment::new("\"", false, StyleType::String));
+                out.push(LineFragment::new(string, true, StyleType::String));
+                out.push(LineFragment::new("\"", false, StyleType::String));
                 if self.comma {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
             }
             LeafContent::Bool(b) => {
                 out.push(LineFragment::new(
-                    b.to_string(),
+                    display.bool_str(b),
                     false,
-                    StyleType::Highlightable,
+                    StyleType::Bool,
                 ));
                 if self.comma {
                     out.push(LineFragment::new_unstyled(",", false));
@@ -69,21 +104,21 @@ impl Leaf {
             }
             LeafContent::Number(x) => {
                 out.push(LineFragment::new(
-                    x.to_string(),
+                    format_number(x),
                     false,
-                    StyleType::Highlightable,
+                    StyleType::Number,
                 ));
                 if self.comma {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
             }
-            LeafContent::FoldedArray(children) => {
+            LeafContent::FoldedArray(size) => {
                 out.push(LineFragment::new("[...]", false, StyleType::Highlightable));
                 if self.comma {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
                 out.push(LineFragment::new(
-                    format!(" ({} children)", children),
+                    format!(" {}", size),
                     false,
                     StyleType::Background,
                 ));
@@ -97,13 +132,13 @@ impl Leaf {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
             }
-            LeafContent::FoldedObject(children) => {
+            LeafContent::FoldedObject(size) => {
                 out.push(LineFragment::new("{...}", false, StyleType::Highlightable));
                 if self.comma {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
                 out.push(LineFragment::new(
-                    format!(" ({} children)", children),
+                    format!(" {}", size),
                     false,
                     StyleType::Background,
                 ));
@@ -117,11 +152,114 @@ impl Leaf {
                     out.push(LineFragment::new_unstyled(",", false));
                 }
             }
+            LeafContent::Inline(json) => {
+                push_inline(&json, &mut out, display);
+                if self.comma {
+                    out.push(LineFragment::new_unstyled(",", false));
+                }
+            }
         };
-        LineFragments::new(out)
+        LineFragments::new(out, display)
     }
 }
 
+// Draws json on a single line, like [1, 2, {"a": null}]. Must agree with cursor::inline_width.
+fn push_inline(json: &JV, out: &mut Vec<LineFragment>, display: DisplayOptions) {
+    match json {
+        JV::Null(_) => out.push(LineFragment::new(
+            display.null_str(),
+            false,
+            StyleType::Null,
+        )),
+        JV::Bool(b) => out.push(LineFragment::new(
+            display.bool_str(b.value()),
+            false,
+            StyleType::Bool,
+        )),
+        JV::Number(x) => out.push(LineFragment::new(
+            format_number(x.value()),
+            false,
+            StyleType::Number,
+        )),
+        JV::String(string) => {
+            out.push(LineFragment::new("\"", false, StyleType::String));
+            out.push(LineFragment::new(string.clone(), true, StyleType::String));
+            out.push(LineFragment::new("\"", false, StyleType::String));
+        }
+        JV::Array(arr) => {
+            out.push(LineFragment::new("[", false, StyleType::Highlightable));
+            for (i, child) in arr.iter().enumerate() {
+                if i != 0 {
+                    out.push(LineFragment::new(", ", false, StyleType::Highlightable));
+                }
+                push_inline(&child, out, display);
+            }
+            out.push(LineFragment::new("]", false, StyleType::Highlightable));
+        }
+        JV::Object(obj) => {
+            out.push(LineFragment::new("{", false, StyleType::Highlightable));
+            for (i, (key, child)) in obj.clone().into_iter().enumerate() {
+                if i != 0 {
+                    out.push(LineFragment::new(", ", false, StyleType::Highlightable));
+                }
+                out.push(LineFragment::new("\"", false, StyleType::Highlightable));
+                out.push(LineFragment::new(key, true, StyleType::Highlightable));
+                out.push(LineFragment::new("\": ", false, StyleType::Highlightable));
+                push_inline(&child, out, display);
+            }
+            out.push(LineFragment::new("}", false, StyleType::Highlightable));
+        }
+    }
+}
+
+// Formats x the way jq prints numbers: the shortest digits that round trip, with an exponent
+// for very large or small numbers, and no decimal point on integers.
+pub fn format_number(x: f64) -> String {
+    if x.is_nan() {
+        return "null".to_owned();
+    }
+    // jq clamps infinities to the largest finite number
+    let x = x.clamp(f64::MIN, f64::MAX);
+    // Rust's {:e} gives us the shortest round-tripping digits, like -1.25e-7
+    let formatted = format!("{:e}", x);
+    let (mantissa, exponent) = formatted
+        .split_once('e')
+        .expect("{:e} should always have an exponent");
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(|&c| c != '.').collect();
+    // Where the decimal point goes, relative to the start of digits
+    let decpt = exponent
+        .parse::<i32>()
+        .expect("{:e} exponent should be an integer")
+        + 1;
+    let mut out = sign.to_owned();
+    if decpt <= -4 || decpt > digits.len() as i32 + 15 {
+        out.push_str(&digits[..1]);
+        if digits.len() > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+        let exponent = decpt - 1;
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        write!(out, "e{}{:02}", exponent_sign, exponent.abs()).expect("Writing to a string");
+    } else if decpt <= 0 {
+        out.push_str("0.");
+        out.push_str(&"0".repeat(-decpt as usize));
+        out.push_str(&digits);
+    } else if decpt as usize >= digits.len() {
+        out.push_str(&digits);
+        out.push_str(&"0".repeat(decpt as usize - digits.len()));
+    } else {
+        out.push_str(&digits[..decpt as usize]);
+        out.push('.');
+        out.push_str(&digits[decpt as usize..]);
+    }
+    out
+}
+
 fn is_unicode_escaped(c: char) -> bool {
     match get_general_category(c) {
         GeneralCategory::Control
@@ -139,14 +277,90 @@ fn is_unicode_escaped(c: char) -> bool {
     }
 }
 
+// How values are drawn on screen. None of these change what's saved or copied: escaped_str is
+// still valid json either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayOptions {
+    // Whether control characters are drawn as control pictures (like ␀) instead of \u escapes
+    pub control_pictures: bool,
+    // Whether true, false and null are drawn as ✓, ✗ and ∅, which are easier to pick out of a
+    // crowd
+    pub literal_glyphs: bool,
+    // Whether newlines in strings end the line they're on instead of being drawn as \n, for
+    // reading multi-line log messages
+    pub literal_newlines: bool,
+}
+
+impl DisplayOptions {
+    pub fn null_str(self) -> &'static str {
+        if self.literal_glyphs {
+            "∅"
+        } else {
+            "null"
+        }
+    }
+    pub fn bool_str(self, b: bool) -> &'static str {
+        match (self.literal_glyphs, b) {
+            (true, true) => "✓",
+            (true, false) => "✗",
+            (false, true) => "true",
+            (false, false) => "false",
+        }
+    }
+    // What's drawn in place of c when control pictures are on, if anything. Characters with short
+    // escapes like \n keep them.
+    fn control_picture(self, c: char) -> Option<char> {
+        if !self.control_pictures {
+            return None;
+        }
+        match c {
+            '\u{08}' | '\u{0C}' | '\n' | '\r' | '\t' => None,
+            '\u{00}'..='\u{1F}' => char::from_u32(0x2400 + c as u32),
+            '\u{7F}' => Some('\u{2421}'), // ␡
+            '\u{80}'..='\u{9F}' => Some('·'),
+            _ => None,
+        }
+    }
+    // Like escaped_str, but with control pictures if they're on
+    pub fn display_str(self, s: &str) -> String {
+        escape_with(s, |c, w| match self.control_picture(c) {
+            Some(picture) => w.push(picture),
+            None => write_escaped_char(c, w),
+        })
+    }
+    // Columns s takes up once escaped
+    pub fn escaped_width(self, s: &str) -> usize {
+        s.chars().map(|c| self.display_width(c) as usize).sum()
+    }
+    fn display_width(self, c: char) -> u8 {
+        match c {
+            '\"' | '\\' | '\u{08}' | '\u{0C}' | '\n' | '\r' | '\t' => 2,
+            _ if self.control_picture(c).is_some() => 1,
+            _ if is_unicode_escaped(c) => 6 * c.len_utf16() as u8, // \u1234
+            // TODO: It kind of sucks to have this huge table that get_general_category uses and
+            // not even get the width from it. Probably we should make our own table at some point,
+            // with values Escaped | HalfWidth | FullWidth | Special. 2 bits, you could pack that
+            // in pretty nicely.
+            _ => c
+                .width()
+                .expect("control characters should have been filtered out above")
+                as u8,
+        }
+    }
+}
+
 pub fn escaped_str(s: &str) -> String {
+    escape_with(s, write_escaped_char)
+}
+
+fn escape_with<F: Fn(char, &mut String)>(s: &str, write_escaped: F) -> String {
     let mut out = String::new();
     let mut range_start = 0;
     for (i, c) in s.char_indices() {
         if is_escaped(c) {
             out.push_str(&s[range_start..i]);
             range_start = i + c.len_utf8();
-            write_escaped_char(c, &mut out);
+            write_escaped(c, &mut out);
         }
     }
     out.push_str(&s[range_start..]);
@@ -180,32 +394,90 @@ fn is_escaped(c: char) -> bool {
     }
 }
 
-fn display_width(c: char) -> u8 {
-    match c {
-        '\"' | '\\' | '\u{08}' | '\u{0C}' | '\n' | '\r' | '\t' => 2,
-        _ if is_unicode_escaped(c) => 6 * c.len_utf16() as u8, // \u1234
-        // TODO: It kind of sucks to have this huge table that get_general_category uses and
-        // not even get the width from it. Probably we should make our own table at some point,
-        // with values Escaped | HalfWidth | FullWidth | Special. 2 bits, you could pack that in
-        // pretty nicely.
-        _ => c
-            .width()
-            .expect("control characters should have been filtered out above") as u8,
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UnstyledSpans {
     pub content: Vec<UnstyledSpan>,
 }
 
 impl UnstyledSpans {
-    pub fn to_spans(self, is_cursor: bool) -> Spans<'static> {
+    pub fn width(&self) -> u16 {
+        self.content
+            .iter()
+            .map(|span| span.text.width() as u16)
+            .sum()
+    }
+    // Drops the first n columns. A wide character straddling the cut is replaced by spaces.
+    pub fn skip_columns(self, n: u16) -> Self {
+        let mut to_skip = n as usize;
+        let mut content = Vec::with_capacity(self.content.len());
+        for span in self.content {
+            if to_skip == 0 {
+                content.push(span);
+                continue;
+            }
+            let mut text = String::new();
+            for c in span.text.chars() {
+                let width = c.width().unwrap_or(0);
+                if to_skip == 0 {
+                    text.push(c);
+                } else if width > to_skip {
+                    text.push_str(&" ".repeat(width - to_skip));
+                    to_skip = 0;
+                } else {
+                    to_skip -= width;
+                }
+            }
+            if !text.is_empty() {
+                content.push(UnstyledSpan { text, ..span });
+            }
+        }
+        UnstyledSpans { content }
+    }
+    // Cuts the line down to n columns. If anything was cut, the last column is a dim ellipsis
+    // instead, so it's clear there's more.
+    pub fn truncate_columns(self, n: u16) -> Self {
+        if self.width() <= n {
+            return self;
+        }
+        if n == 0 {
+            return UnstyledSpans {
+                content: Vec::new(),
+            };
+        }
+        let mut remaining = n as usize - 1;
+        let mut content = Vec::with_capacity(self.content.len() + 1);
+        for span in self.content {
+            let mut text = String::new();
+            let mut cut = false;
+            for c in span.text.chars() {
+                let width = c.width().unwrap_or(0);
+                if width > remaining {
+                    cut = true;
+                    break;
+                }
+                remaining -= width;
+                text.push(c);
+            }
+            if !text.is_empty() {
+                content.push(UnstyledSpan { text, ..span });
+            }
+            if cut {
+                break;
+            }
+        }
+        // A wide character that didn't fit leaves a gap before the ellipsis
+        content.push(UnstyledSpan {
+            style_type: StyleType::Background,
+            text: format!("{}…", " ".repeat(remaining)),
+        });
+        UnstyledSpans { content }
+    }
+    pub fn to_spans(self, is_cursor: bool, theme: &Theme) -> Spans<'static> {
         let v: Vec<Span> = self
             .content
             .into_iter()
             .map(|unstyled| {
-                let style = unstyled.style_type.to_style(is_cursor);
+                let style = unstyled.style_type.to_style(is_cursor, theme);
                 Span::styled(unstyled.text, style)
             })
             .collect();
@@ -224,14 +496,27 @@ pub enum StyleType {
     Unhighlightable,
     Highlightable,
     Background,
+    // Highlightable, but with a color of their own
+    Null,
+    Bool,
+    Number,
+    String,
 }
 
 impl StyleType {
-    fn to_style(self, is_cursor: bool) -> Style {
-        match self {
-            StyleType::Highlightable if is_cursor => Style::default().bg(Color::Blue),
+    fn to_style(self, is_cursor: bool, theme: &Theme) -> Style {
+        let style = match self {
             StyleType::Unhighlightable | StyleType::Highlightable => Style::default(),
-            StyleType::Background => Style::default().add_modifier(Modifier::DIM),
+            StyleType::Background => theme.background,
+            StyleType::Null => theme.null,
+            StyleType::Bool => theme.bool,
+            StyleType::Number => theme.number,
+            StyleType::String => theme.string,
+        };
+        match self {
+            StyleType::Unhighlightable | StyleType::Background => style,
+            _ if is_cursor => style.patch(theme.cursor),
+            _ => style,
         }
     }
 }
@@ -289,6 +574,11 @@ pub struct LineFragment {
     string: StringLike,
     is_escaped: bool,
     style: StyleType,
+    // Whether the newlines in this fragment end lines, taking up no room themselves. Set by
+    // LineFragments::new, so only strings that actually have newlines pay for it.
+    breaks_lines: bool,
+    // Also set by LineFragments::new
+    display: DisplayOptions,
 }
 
 impl LineFragment {
@@ -297,6 +587,8 @@ impl LineFragment {
             string: s.into(),
             is_escaped,
             style,
+            breaks_lines: false,
+            display: DisplayOptions::default(),
         }
     }
     fn new_unstyled<S: Into<StringLike>>(s: S, is_escaped: bool) -> Self {
@@ -304,13 +596,26 @@ impl LineFragment {
             string: s.into(),
             is_escaped,
             style: StyleType::Unhighlightable,
+            breaks_lines: false,
+            display: DisplayOptions::default(),
+        }
+    }
+    fn char_width(&self, c: char) -> u16 {
+        if self.breaks_lines && c == '\n' {
+            0
+        } else {
+            self.display.display_width(c) as u16
         }
     }
     fn take_width(&self, from: usize, target_width: u16) -> (Range<usize>, u16) {
         if self.is_escaped {
             let mut width = 0u16;
             for (i, c) in self.string.as_str()[from..].char_indices() {
-                let new_width = width + display_width(c) as u16;
+                if self.breaks_lines && c == '\n' {
+                    // The newline goes at the end of the line it ends
+                    return (from..from + i + 1, width);
+                }
+                let new_width = width + self.char_width(c);
                 if new_width > target_width {
                     return (from..from + i, width);
                 }
@@ -322,9 +627,22 @@ impl LineFragment {
             (from..from + width, width as u16)
         }
     }
+    fn width(&self, range: Range<usize>) -> u16 {
+        if self.is_escaped {
+            self.string.as_str()[range]
+                .chars()
+                .map(|c| self.char_width(c))
+                .sum()
+        } else {
+            range.len() as u16
+        }
+    }
     fn span(&self, range: Range<usize>) -> UnstyledSpan {
-        let text = if self.is_escaped {
-            escaped_str(&self.string.as_str()[range])
+        let text = if self.breaks_lines {
+            self.display
+                .display_str(&self.string.as_str()[range].replace('\n', ""))
+        } else if self.is_escaped {
+            self.display.display_str(&self.string.as_str()[range])
         } else {
             self.string.as_str()[range].to_string()
         };
@@ -345,9 +663,14 @@ pub struct LineFragmentsIndex {
 }
 
 impl LineFragments {
-    fn new(mut v: Vec<LineFragment>) -> Self {
+    fn new(mut v: Vec<LineFragment>, display: DisplayOptions) -> Self {
         v.retain(|f| f.string.len() != 0);
         assert_ne!(v.len(), 0);
+        for f in v.iter_mut() {
+            f.breaks_lines =
+                display.literal_newlines && f.is_escaped && f.string.as_str().contains('\n');
+            f.display = display;
+        }
         Self(v)
     }
     fn take_width(
@@ -363,6 +686,9 @@ impl LineFragments {
                 fragment.take_width(current.byte_index, target_width - width);
             width += fragment_width;
             current.byte_index = fragment_range.end;
+            if self.ends_line(current) {
+                return (from..current, width);
+            }
             if fragment_range.end != fragment.string.len() {
                 // Didn't consume the whole fragment
                 break;
@@ -378,7 +704,104 @@ impl LineFragments {
             current.fragment_index += 1;
             current.byte_index = 0;
         }
-        (from..current, width)
+        match self.word_break(from, current) {
+            Some(end) => {
+                let width = width - self.width(end..current);
+                (from..end, width)
+            }
+            None => (from..current, width),
+        }
+    }
+    // Whether ix is just after a newline that ends the line
+    fn ends_line(&self, ix: LineFragmentsIndex) -> bool {
+        let fragment = &self.0[ix.fragment_index];
+        fragment.breaks_lines && fragment.string.as_str()[..ix.byte_index].ends_with('\n')
+    }
+    // Lines that start partway through a string with newlines in it are indented to line up with
+    // the start of the string, so a multi-line message reads as a block. Like the indent of the
+    // value itself, this is capped so some room is left for the text.
+    fn continuation_indent(&self, start: LineFragmentsIndex, width: u16) -> u16 {
+        let fragment_index = if start.byte_index > 0 {
+            start.fragment_index
+        } else {
+            match start.fragment_index.checked_sub(1) {
+                Some(fragment_index) => fragment_index,
+                None => return 0,
+            }
+        };
+        if !self.0[fragment_index].breaks_lines {
+            return 0;
+        }
+        let indent: u16 = self.0[..fragment_index]
+            .iter()
+            .map(|fragment| fragment.width(0..fragment.string.len()))
+            .sum();
+        std::cmp::min(indent, width - 7)
+    }
+    // If a line running from..end would end in the middle of a word, returns an earlier end just
+    // after the last space on the line. If there's no space (a single long word), it gets broken
+    // wherever it hits the edge. Only spaces inside strings count: breaking after indentation
+    // would leave lines with nothing on them.
+    fn word_break(
+        &self,
+        from: LineFragmentsIndex,
+        end: LineFragmentsIndex,
+    ) -> Option<LineFragmentsIndex> {
+        match self.char_at(end) {
+            // The line runs to the end of the content, or stops at a word boundary anyway
+            None | Some(' ') => return None,
+            Some(_) => {}
+        }
+        for fragment_index in (from.fragment_index..=end.fragment_index).rev() {
+            let fragment = &self.0[fragment_index];
+            if !fragment.is_escaped {
+                continue;
+            }
+            let start = if fragment_index == from.fragment_index {
+                from.byte_index
+            } else {
+                0
+            };
+            let stop = if fragment_index == end.fragment_index {
+                end.byte_index
+            } else {
+                fragment.string.len()
+            };
+            if let Some(i) = fragment.string.as_str()[start..stop].rfind(' ') {
+                return Some(LineFragmentsIndex {
+                    fragment_index,
+                    byte_index: start + i + 1,
+                });
+            }
+        }
+        None
+    }
+    fn width(&self, range: Range<LineFragmentsIndex>) -> u16 {
+        (range.start.fragment_index..=range.end.fragment_index)
+            .map(|fragment_index| {
+                let fragment = &self.0[fragment_index];
+                let start = if fragment_index == range.start.fragment_index {
+                    range.start.byte_index
+                } else {
+                    0
+                };
+                let end = if fragment_index == range.end.fragment_index {
+                    range.end.byte_index
+                } else {
+                    fragment.string.len()
+                };
+                fragment.width(start..end)
+            })
+            .sum()
+    }
+    fn char_at(&self, mut ix: LineFragmentsIndex) -> Option<char> {
+        if ix.byte_index == self.0[ix.fragment_index].string.len() {
+            ix.fragment_index += 1;
+            ix.byte_index = 0;
+        }
+        self.0.get(ix.fragment_index)?.string.as_str()[ix.byte_index..]
+            .chars()
+            .next()
     }
     fn spans(&self, range: Range<LineFragmentsIndex>) -> Vec<UnstyledSpan> {
         self.0[range.start.fragment_index..=range.end.fragment_index]
@@ -471,7 +894,17 @@ impl LineCursor {
                 let end = self
                     .content
                     .add_byte_offset(start, line_widths[current_line] as usize);
-                let content = self.content.spans(start..end);
+                let mut content = self.content.spans(start..end);
+                let indent = self.content.continuation_indent(start, self.width);
+                if indent > 0 {
+                    content.insert(
+                        0,
+                        UnstyledSpan {
+                            style_type: StyleType::Unhighlightable,
+                            text: " ".repeat(indent as usize),
+                        },
+                    );
+                }
                 Some(UnstyledSpans { content })
             }
         }
@@ -512,7 +945,8 @@ impl LineCursor {
                 start,
             } => {
                 if current_line == line_widths.len() {
-                    let (range, _) = self.content.take_width(start, self.width);
+                    let indent = self.content.continuation_indent(start, self.width);
+                    let (range, _) = self.content.take_width(start, self.width - indent);
                     line_widths.push(
                         (self.content.to_global_byte_offset(range.end)
                             - self.content.to_global_byte_offset(start))
@@ -605,9 +1039,21 @@ impl LineCursor {
         out
     }
     pub fn set_width(&mut self, width: u16) {
-        if self.width == width {
-            return;
+        if self.width != width {
+            self.rewrap_at(width);
         }
+    }
+    // Works out where the lines break again, for when characters are drawn wider or narrower than
+    // they were
+    pub fn rewrap(&mut self) {
+        self.rewrap_at(self.width);
+    }
+    // Swaps in a redrawn copy of the line, staying about where it was
+    pub fn set_content(&mut self, content: LineFragments) {
+        self.content = content;
+        self.rewrap();
+    }
+    fn rewrap_at(&mut self, width: u16) {
         match self.position {
             LineCursorPosition::Start => {
                 *self = LineCursor::new_at_start(self.content.clone(), width);
@@ -641,15 +1087,25 @@ impl LineCursor {
 
 #[cfg(test)]
 mod tests {
-    use super::{display_width, escaped_str, LineCursor, LineFragment, LineFragments};
+    use super::{
+        escaped_str, format_number, DisplayOptions, Leaf, LeafContent, LineCursor, LineFragment,
+        LineFragments, UnstyledSpans,
+    };
+    use crate::{
+        cursor::inline_width,
+        jq::jv::{JVString, JV},
+        theme::Theme,
+    };
     use proptest::prelude::*;
+    use serde_json::json;
+    use tui::style::{Color, Modifier, Style};
     use unicode_width::UnicodeWidthStr;
     proptest! {
         #[test]
         fn prop_display_width(string in any::<String>()) {
             let escaped = escaped_str(&string);
             let expected_width = escaped.width();
-            let actual_inner_width: usize = string.chars().map(|c| display_width(c) as usize).sum();
+            let actual_inner_width = DisplayOptions::default().escaped_width(&string);
             assert_eq!(expected_width, actual_inner_width , "original: {:?}, escaped: {}", &string, &escaped);
         }
     }
@@ -675,7 +1131,10 @@ mod tests {
         out
     }
     fn check_lines(string: String, width: u16) {
-        let line_fragments = LineFragments::new(vec![LineFragment::new_unstyled(string, true)]);
+        let line_fragments = LineFragments::new(
+            vec![LineFragment::new_unstyled(string, true)],
+            DisplayOptions::default(),
+        );
         {
             let wide_cursor = LineCursor::new_at_start(line_fragments.clone(), u16::MAX);
             let actual_cursor = LineCursor::new_at_start(line_fragments.clone(), width);
@@ -698,6 +1157,91 @@ mod tests {
         }
     }
     #[test]
+    fn unit_theme_styles() {
+        let leaf = Leaf {
+            content: LeafContent::Number(1.0),
+            key: None,
+            indent: 0,
+            comma: true,
+        };
+        let line = LineCursor::new_at_start(leaf.render(DisplayOptions::default()), 80)
+            .current()
+            .unwrap();
+        let theme = Theme::dark();
+        let styles: Vec<Style> = line
+            .clone()
+            .to_spans(false, &theme)
+            .0
+            .into_iter()
+            .map(|span| span.style)
+            .collect();
+        assert_eq!(styles[0], Style::default().fg(Color::Cyan));
+        // The comma isn't part of the value, so it doesn't get colored
+        assert_eq!(styles[1], Style::default());
+        let cursor_styles: Vec<Style> = line
+            .clone()
+            .to_spans(true, &theme)
+            .0
+            .into_iter()
+            .map(|span| span.style)
+            .collect();
+        assert_eq!(
+            cursor_styles[0],
+            Style::default().fg(Color::Cyan).bg(Color::Blue)
+        );
+        // Without color, the cursor is picked out with reverse video instead
+        let theme = Theme::dark().without_color();
+        let cursor_styles: Vec<Style> = line
+            .to_spans(true, &theme)
+            .0
+            .into_iter()
+            .map(|span| span.style)
+            .collect();
+        assert_eq!(
+            cursor_styles[0],
+            Style::default().add_modifier(Modifier::REVERSED)
+        );
+    }
+    #[test]
+    fn unit_container_key_highlight() {
+        let theme = Theme::dark();
+        // The style of the key on the line for content, with and without the cursor on it
+        let key_styles = |content| {
+            let leaf = Leaf {
+                content,
+                key: Some(JVString::new("k")),
+                indent: 2,
+                comma: false,
+            };
+            let line = LineCursor::new_at_start(leaf.render(DisplayOptions::default()), 80)
+                .current()
+                .unwrap();
+            let key_style = |is_cursor| {
+                line.clone()
+                    .to_spans(is_cursor, &theme)
+                    .0
+                    .into_iter()
+                    .find(|span| span.content == "k")
+                    .unwrap()
+                    .style
+            };
+            (key_style(true), key_style(false))
+        };
+        assert_eq!(
+            key_styles(LeafContent::ObjectStart),
+            (theme.cursor, Style::default())
+        );
+        assert_eq!(
+            key_styles(LeafContent::ArrayStart),
+            (theme.cursor, Style::default())
+        );
+        // Scalars already highlight their value, so their keys stay as they were
+        assert_eq!(
+            key_styles(LeafContent::Number(1.0)),
+            (Style::default(), Style::default())
+        );
+    }
+    #[test]
     fn unit_display_lines() {
         let tests = vec![
             ("a", 7),
@@ -710,25 +1254,250 @@ mod tests {
         }
     }
     #[test]
+    fn unit_format_number() {
+        let tests = vec![
+            (42.0, "42"),
+            (-0.0, "-0"),
+            (0.0, "0"),
+            (1e21, "1e+21"),
+            (0.1, "0.1"),
+            (-2.5, "-2.5"),
+            (123456789012.0, "123456789012"),
+            (1e17, "1e+17"),
+            (0.0001, "0.0001"),
+            (0.00001, "1e-05"),
+            (1.5e300, "1.5e+300"),
+            (f64::INFINITY, "1.7976931348623157e+308"),
+        ];
+        for (x, expected) in tests {
+            assert_eq!(format_number(x), expected, "Test failure for {:?}", x);
+        }
+    }
+    #[test]
+    fn unit_word_wrap() {
+        let lines = |fragments: Vec<LineFragment>, width: u16| -> Vec<String> {
+            let mut cursor = LineCursor::new_at_start(
+                LineFragments::new(fragments, DisplayOptions::default()),
+                width,
+            );
+            let mut out = Vec::new();
+            while let Some(line) = cursor.current() {
+                let text: String = line.content.iter().map(|span| span.text.as_str()).collect();
+                assert!(text.width() <= width as usize);
+                out.push(text);
+                cursor.move_next();
+            }
+            out
+        };
+        let fragments = vec![
+            LineFragment::new_unstyled("\"", false),
+            LineFragment::new_unstyled("the quick brown fox", true),
+            LineFragment::new_unstyled("\",", false),
+        ];
+        assert_eq!(
+            lines(fragments, 10),
+            vec!["\"the quick", " brown ", "fox\","]
+        );
+        // A word too long for a line still gets broken
+        let fragments = vec![LineFragment::new_unstyled("a abcdefghijkl", true)];
+        assert_eq!(lines(fragments, 7), vec!["a ", "abcdefg", "hijkl"]);
+        // Spaces outside of strings aren't broken at
+        let fragments = vec![
+            LineFragment::new_unstyled("    ", false),
+            LineFragment::new_unstyled("abcdefghij", true),
+        ];
+        assert_eq!(lines(fragments, 8), vec!["    abcd", "efghij"]);
+    }
+    #[test]
+    fn unit_skip_columns() {
+        let line_fragments = LineFragments::new(
+            vec![
+                LineFragment::new_unstyled("ab", false),
+                LineFragment::new_unstyled("c\u{4e00}d", false),
+            ],
+            DisplayOptions::default(),
+        );
+        let line = LineCursor::new_at_start(line_fragments, 10000)
+            .current()
+            .unwrap();
+        assert_eq!(line.width(), 6);
+        let text = |line: UnstyledSpans| -> Vec<String> {
+            line.content.into_iter().map(|span| span.text).collect()
+        };
+        assert_eq!(text(line.clone().skip_columns(0)), vec!["ab", "c\u{4e00}d"]);
+        assert_eq!(text(line.clone().skip_columns(3)), vec!["\u{4e00}d"]);
+        // Half of a wide character is left as a space
+        assert_eq!(text(line.clone().skip_columns(4)), vec![" d"]);
+        assert_eq!(text(line.skip_columns(10)), Vec::<String>::new());
+    }
+    #[test]
+    fn unit_truncate_columns() {
+        let line_fragments = LineFragments::new(
+            vec![
+                LineFragment::new_unstyled("ab", false),
+                LineFragment::new_unstyled("c\u{4e00}d", false),
+            ],
+            DisplayOptions::default(),
+        );
+        let line = LineCursor::new_at_start(line_fragments, 10000)
+            .current()
+            .unwrap();
+        let text = |line: UnstyledSpans| -> Vec<String> {
+            line.content.into_iter().map(|span| span.text).collect()
+        };
+        assert_eq!(
+            text(line.clone().truncate_columns(6)),
+            vec!["ab", "c\u{4e00}d"]
+        );
+        assert_eq!(text(line.clone().truncate_columns(4)), vec!["ab", "c", "…"]);
+        // Half of a wide character doesn't fit, so it leaves a gap
+        assert_eq!(
+            text(line.clone().truncate_columns(5)),
+            vec!["ab", "c", " …"]
+        );
+        assert_eq!(line.clone().truncate_columns(5).width(), 5);
+        assert_eq!(text(line.clone().truncate_columns(3)), vec!["ab", "…"]);
+        assert_eq!(text(line.clone().truncate_columns(1)), vec!["…"]);
+        assert_eq!(text(line.truncate_columns(0)), Vec::<String>::new());
+    }
+    #[test]
     fn unit_to_string() {
         let tests = vec![
             ("Hello world!", r#"Hello world!"#),
             ("Hello\nworld!", r#"Hello\nworld!"#),
         ];
         for (string, expected) in tests {
-            let line_fragments = LineFragments::new(vec![LineFragment::new_unstyled(string, true)]);
+            let line_fragments = LineFragments::new(
+                vec![LineFragment::new_unstyled(string, true)],
+                DisplayOptions::default(),
+            );
             let actual_cursor = LineCursor::new_at_start(line_fragments, 10000);
             let line = actual_cursor.current().unwrap();
             let actual: String = line.content.iter().map(|span| span.text.as_str()).collect();
             assert_eq!(actual, expected, "Test failure for {:?}", string);
         }
     }
+    #[test]
+    fn unit_control_pictures() {
+        let string = "a\u{0}b\u{1b}\n\u{7f}\u{85}é\u{301}";
+        let pictures = DisplayOptions {
+            control_pictures: true,
+            ..DisplayOptions::default()
+        };
+        let shown = pictures.display_str(string);
+        assert_eq!(shown, "a␀b␛\\n␡·é\\u0301");
+        assert_eq!(shown.width(), pictures.escaped_width(string));
+        let fragments = || vec![LineFragment::new_unstyled(string, true)];
+        let mut cursor = LineCursor::new_at_start(LineFragments::new(fragments(), pictures), 8);
+        let line = cursor.current().unwrap();
+        let first_line: String = line.content.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(first_line, "a␀b␛\\n␡·");
+        // The json escapes never change
+        assert_eq!(
+            escaped_str(string),
+            r#"a\u0000b\u001b\n\u007f\u0085é\u0301"#
+        );
+        cursor.set_content(LineFragments::new(fragments(), DisplayOptions::default()));
+        let line = cursor.current().unwrap();
+        let first_line: String = line.content.iter().map(|span| span.text.as_str()).collect();
+        assert_eq!(first_line, r#"a\u0000b"#);
+    }
+    #[test]
+    fn unit_literal_glyphs() {
+        let render = |content: LeafContent, display: DisplayOptions| {
+            let leaf = Leaf {
+                content,
+                key: None,
+                indent: 0,
+                comma: true,
+            };
+            let line = LineCursor::new_at_start(leaf.render(display), 80)
+                .current()
+                .unwrap();
+            line.content
+                .iter()
+                .map(|span| span.text.as_str())
+                .collect::<String>()
+        };
+        let glyphs = DisplayOptions {
+            literal_glyphs: true,
+            ..DisplayOptions::default()
+        };
+        let inline: JV = (&json!([true, false, null])).into();
+        assert_eq!(render(LeafContent::Bool(true), glyphs), "✓,");
+        assert_eq!(render(LeafContent::Null, glyphs), "∅,");
+        let shown = render(LeafContent::Inline(inline.clone()), glyphs);
+        assert_eq!(shown, "[✓, ✗, ∅],");
+        // Compact mode has to agree on how wide that is, minus the comma
+        assert_eq!(inline_width(&inline, 80, glyphs), Some(shown.width() - 1));
+        // Only the screen changes
+        assert_eq!(inline.to_compact_string(), "[true,false,null]");
+        let words = DisplayOptions::default();
+        assert_eq!(
+            render(LeafContent::Inline(inline.clone()), words),
+            "[true, false, null],"
+        );
+        assert_eq!(inline_width(&inline, 80, words), Some(19));
+    }
+    #[test]
+    fn unit_literal_newlines() {
+        let lines = |display: DisplayOptions, width: u16| -> Vec<String> {
+            let fragments = LineFragments::new(newline_fragments(), display);
+            let mut cursor = LineCursor::new_at_start(fragments, width);
+            let mut out = Vec::new();
+            while let Some(line) = cursor.current() {
+                let text: String = line.content.iter().map(|span| span.text.as_str()).collect();
+                assert!(text.width() <= width as usize);
+                out.push(text);
+                cursor.move_next();
+            }
+            out
+        };
+        let newlines = DisplayOptions {
+            literal_newlines: true,
+            ..DisplayOptions::default()
+        };
+        // Lines after the first line up with the start of the string, and wrap within it
+        assert_eq!(
+            lines(newlines, 12),
+            vec!["  \"one", "   ", "   three ", "   four\","]
+        );
+        // Going backwards agrees
+        let mut cursor =
+            LineCursor::new_at_end(LineFragments::new(newline_fragments(), newlines), 12);
+        let mut backwards = Vec::new();
+        while let Some(line) = cursor.current() {
+            backwards.push(
+                line.content
+                    .iter()
+                    .map(|span| span.text.as_str())
+                    .collect::<String>(),
+            );
+            cursor.move_prev();
+        }
+        backwards.reverse();
+        assert_eq!(backwards, lines(newlines, 12));
+        // The json escapes never change
+        assert_eq!(escaped_str("one\ntwo"), r#"one\ntwo"#);
+        assert_eq!(
+            lines(DisplayOptions::default(), 80),
+            vec![r#"  "one\n\nthree four","#]
+        );
+    }
+    fn newline_fragments() -> Vec<LineFragment> {
+        vec![
+            LineFragment::new_unstyled("  ", false),
+            LineFragment::new_unstyled("\"", false),
+            LineFragment::new_unstyled("one\n\nthree four", true),
+            LineFragment::new_unstyled("\",", false),
+        ]
+    }
     fn strings_to_fragments(strings: Vec<String>) -> LineFragments {
         let content = strings
             .into_iter()
             .map(|s| LineFragment::new_unstyled(s, true))
             .collect();
-        LineFragments::new(content)
+        LineFragments::new(content, DisplayOptions::default())
     }
     fn arb_fragments() -> impl Strategy<Value = LineFragments> {
         proptest::collection::vec("..*", 1..10).prop_map(strings_to_fragments)
@@ -1,30 +1,40 @@
 use argh::FromArgs;
 use crossterm::{
     event,
-    event::KeyCode,
+    event::{
+        DisableMouseCapture, EnableMouseCapture, KeyCode, KeyModifiers, MouseButton, MouseEvent,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use directories;
 use jex::{
-    app::{App, AppRenderMode, Focus},
+    app::{App, AppRenderMode, Focus, SearchQuery, SearchResult, DEFAULT_INLINE_WIDTH},
     cursor::GlobalCursor,
-    helper::Helper,
-    layout::JexLayout,
-    view_tree::View,
+    helper::{Helper, JqCompleter},
+    jq::{
+        jv::{CountedJV, JVString, JV},
+        query::{JqError, JQ, QUERY_TIMEOUT},
+    },
+    layout::{JexLayout, LayoutMode, DEFAULT_SPLIT_PERCENT},
+    theme::{ColorChoice, CursorStyle, Theme},
+    view_tree::{is_ndjson, View, ViewWithParent},
+    watch::FileWatcher,
 };
 use log::{debug, warn};
 use regex::Regex;
+use rustyline::history::History;
 use simplelog::WriteLogger;
 use std::{
     default::Default,
     error::Error,
-    fs,
     fs::{create_dir_all, File},
     io,
     io::Write,
+    os::unix::io::{AsRawFd, FromRawFd},
     panic,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 use tui::{
     backend::CrosstermBackend,
@@ -52,8 +62,36 @@ struct Args {
     #[argh(option)]
     #[argh(description = "logging output file")]
     log_path: Option<String>,
+    #[argh(option)]
+    #[argh(description = "color theme: plain, dark or light")]
+    #[argh(default = "Theme::plain()")]
+    theme: Theme,
+    #[argh(option)]
+    #[argh(description = "when to use color: auto (unless NO_COLOR is set), always or never")]
+    #[argh(default = "ColorChoice::Auto")]
+    color: ColorChoice,
+    #[argh(option)]
+    #[argh(
+        description = "how to highlight the cursor: a background color and/or bold or reverse, like yellow,bold"
+    )]
+    cursor_style: Option<CursorStyle>,
+    #[argh(option)]
+    #[argh(
+        description = "widest a container can be and still be drawn on one line in compact mode"
+    )]
+    #[argh(default = "DEFAULT_INLINE_WIDTH")]
+    inline_width: usize,
+    #[argh(switch)]
+    #[argh(description = "reload files whenever they change on disk")]
+    watch: bool,
+    #[argh(option)]
+    #[argh(description = "restore a session saved with ctrl-s")]
+    session: Option<String>,
     #[argh(positional)]
-    json_path: String,
+    #[argh(
+        description = "json files to view, each in a tree of its own. - (or no files) reads stdin"
+    )]
+    json_paths: Vec<String>,
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -110,9 +148,6 @@ struct BenchMode {}
 //   * Children can be modified if they have no children
 //   * Allow copying descendents onto another root, so you if you want to modify a tree's root you
 // can do so by making a new root and then copying over the descendents
-// * Lightweight error messages (no search results, can't fold a leaf, can't edit a non-leaf)
-//   * Probably requires timers, which requires us to be able to inject stuff into the event
-//   stream. Async? That would also let us show a loading message.
 // * Diffs
 //   * UI
 //     * Need to make left and right pane independent
@@ -145,19 +180,68 @@ struct BenchMode {}
 fn main() -> Result<(), Box<dyn Error>> {
     use coredump;
     coredump::register_panic_handler();
-    let args: Args = argh::from_env();
+    let args = parse_args();
     init_logging(&args);
     match args.mode {
-        Mode::Normal(_) => run(args.json_path),
-        Mode::Bench(_) => bench(args.json_path),
+        Mode::Normal(_) => run(
+            args.json_paths,
+            args.session,
+            args.theme,
+            args.inline_width,
+            args.watch,
+        ),
+        Mode::Bench(_) => bench(args.json_paths.into_iter().next().unwrap_or_default()),
     }
 }
 
 #[cfg(not(feature = "dev-tools"))]
 fn main() -> Result<(), Box<dyn Error>> {
-    let args: Args = argh::from_env();
+    let args = parse_args();
     init_logging(&args);
-    run(args.json_path)
+    run(
+        args.json_paths,
+        args.session,
+        args.theme,
+        args.inline_width,
+        args.watch,
+    )
+}
+
+// argh takes anything starting with - for an option, so a lone - (meaning stdin) is swapped for a
+// placeholder while parsing. No real argument can hold a nul.
+const STDIN_PLACEHOLDER: &str = "\0-";
+
+// Like argh::from_env, but lets - through as a path.
+fn parse_args() -> Args {
+    let strings: Vec<String> = std::env::args()
+        .map(|arg| {
+            if arg == "-" {
+                STDIN_PLACEHOLDER.to_owned()
+            } else {
+                arg
+            }
+        })
+        .collect();
+    let strs: Vec<&str> = strings.iter().map(|s| s.as_str()).collect();
+    let mut args = Args::from_args(&[strs[0]], &strs[1..]).unwrap_or_else(|early_exit| {
+        println!("{}", early_exit.output);
+        std::process::exit(match early_exit.status {
+            Ok(()) => 0,
+            Err(()) => 1,
+        })
+    });
+    for path in args.json_paths.iter_mut() {
+        if path == STDIN_PLACEHOLDER {
+            *path = "-".to_owned();
+        }
+    }
+    if !args.color.use_color() {
+        args.theme = args.theme.clone().without_color();
+    }
+    if let Some(cursor_style) = args.cursor_style {
+        args.theme = args.theme.clone().with_cursor(cursor_style);
+    }
+    args
 }
 
 fn init_logging(args: &Args) {
@@ -197,21 +281,163 @@ fn force_draw<B: tui::backend::Backend, F: FnMut(&mut Frame<B>)>(
     terminal.backend_mut().draw(updates.into_iter())
 }
 
+// Asks before saving over an existing file, returning whether to go ahead.
+fn confirm_overwrite<B: tui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    path: &str,
+) -> Result<bool, io::Error> {
+    if !Path::new(path).exists() {
+        return Ok(true);
+    }
+    app.set_flash(format!("{} already exists. Overwrite it? (y/n)", path));
+    force_draw(terminal, app.render(AppRenderMode::Normal))?;
+    let confirmed = loop {
+        if let event::Event::Key(c) = event::read().expect("Error getting next event") {
+            break c.code == KeyCode::Char('y');
+        }
+    };
+    app.flash = None;
+    Ok(confirmed)
+}
+
+// Reads a line of input on the query line, calling on_edit after every change so the view can follow
+// along as it's typed, which rustyline has no way to do. Up and down step through history like they
+// do in rustyline. Returns None if it's cancelled with Esc.
+fn read_live_input<B: tui::backend::Backend, F: FnMut(&mut App, &str)>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    prompt: &str,
+    history: &History,
+    mut on_edit: F,
+) -> Result<Option<String>, io::Error> {
+    let mut input = String::new();
+    // The history entry being shown, and what had been typed before going back to it
+    let mut recalled: Option<usize> = None;
+    let mut draft = String::new();
+    loop {
+        let line = format!("{}{}", prompt, input);
+        terminal.draw(app.render(AppRenderMode::Prompt(line)))?;
+        let key = match event::read().expect("Error getting next event") {
+            event::Event::Key(key) => key,
+            event::Event::Resize(..) => {
+                let layout = app.layout(terminal.get_frame().size());
+                app.resize(layout);
+                continue;
+            }
+            event::Event::Mouse(_) => continue,
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => return Ok(Some(input)),
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if control => return Ok(None),
+            KeyCode::Char('u') if control => input.clear(),
+            KeyCode::Backspace => {
+                if input.pop().is_none() {
+                    continue;
+                }
+            }
+            KeyCode::Char(c) if !control => input.push(c),
+            KeyCode::Up => {
+                let index = match recalled {
+                    Some(0) => continue,
+                    Some(index) => index - 1,
+                    None if history.is_empty() => continue,
+                    None => {
+                        draft = std::mem::take(&mut input);
+                        history.len() - 1
+                    }
+                };
+                input = history[index].clone();
+                recalled = Some(index);
+            }
+            KeyCode::Down => match recalled {
+                None => continue,
+                Some(index) if index + 1 < history.len() => {
+                    input = history[index + 1].clone();
+                    recalled = Some(index + 1);
+                }
+                Some(_) => {
+                    input = std::mem::take(&mut draft);
+                    recalled = None;
+                }
+            },
+            _ => continue,
+        }
+        on_edit(app, &input);
+    }
+}
+
+// Splits a trailing " -c" (for compact output) off a path typed into the save prompt. Files with an
+// ndjson extension are saved compact either way, so they stay one value per line.
+fn parse_save_path(input: &str) -> (String, bool) {
+    let (path, compact) = match input.strip_suffix(" -c") {
+        Some(path) => (path.trim_end(), true),
+        None => (input, false),
+    };
+    (path.to_owned(), !compact && !is_ndjson(path))
+}
+
+// A value typed into a prompt, and how many integers in it had to be rounded. Anything that isn't
+// valid json is taken as a string.
+fn parse_typed_value(text: &str) -> (JV, usize) {
+    match serde_json::from_str::<CountedJV>(text) {
+        Ok(counted) => (counted.value, counted.lossy_integers),
+        Err(_) => (JVString::new(text).into(), 0),
+    }
+}
+
+// How long to wait for more of a file to be parsed before checking for input again
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Columns Left and Right scroll by when line wrapping is off
+const H_SCROLL_STEP: u16 = 8;
+
+// Jumps to the times'th next (or previous) search hit, stopping early if there are no more.
+fn search(app: &mut App, reverse: bool, times: usize) {
+    for _ in 0..times {
+        match app.search(reverse) {
+            SearchResult::Wrapped if !app.search_wrap => {
+                let end = if reverse { "top" } else { "bottom" };
+                app.set_flash(format!("Search hit {}", end));
+                return;
+            }
+            SearchResult::Found | SearchResult::Wrapped => {}
+            SearchResult::NotFound(pattern) => {
+                app.set_flash(format!("Pattern not found: {}", pattern));
+                return;
+            }
+            SearchResult::NoSearch => return,
+            SearchResult::TimedOut(pattern) => {
+                let message = JqError::TimedOut(QUERY_TIMEOUT).to_string();
+                app.set_flash(format!("Search for {} stopped:\n{}", pattern, message));
+                return;
+            }
+        }
+    }
+}
+
+enum MarkAction {
+    Set,
+    Jump,
+}
+
 struct DeferRestoreTerminal {}
 
 impl Drop for DeferRestoreTerminal {
     fn drop(&mut self) {
         disable_raw_mode().expect("Failed to disable raw mode");
-        execute!(io::stdout(), LeaveAlternateScreen).expect("Failed to leave alternate screen");
+        execute!(io::stdout(), DisableMouseCapture, LeaveAlternateScreen)
+            .expect("Failed to leave alternate screen");
     }
 }
 
-struct RustylineWrapper {
+struct RustylineWrapper<H: rustyline::Helper = Helper> {
     history_path: PathBuf,
-    editor: rustyline::Editor<Helper>,
+    editor: rustyline::Editor<H>,
 }
 
-impl RustylineWrapper {
+impl<H: rustyline::Helper> RustylineWrapper<H> {
     fn new(history_path: PathBuf) -> Result<Self, Box<dyn Error>> {
         let config = rustyline::Config::builder().auto_add_history(true).build();
         let mut editor = rustyline::Editor::with_config(config);
@@ -222,9 +448,7 @@ impl RustylineWrapper {
             editor,
         })
     }
-}
-impl Drop for RustylineWrapper {
-    fn drop(&mut self) {
+    fn save_history(&self) {
         let res = create_dir_all(self.history_path.parent().unwrap());
         if let Err(err) = res {
             warn!("Error creating directory: {:?}", err);
@@ -235,44 +459,176 @@ impl Drop for RustylineWrapper {
         }
     }
 }
+impl<H: rustyline::Helper> Drop for RustylineWrapper<H> {
+    fn drop(&mut self) {
+        self.save_history();
+    }
+}
 
-fn run(json_path: String) -> Result<(), Box<dyn Error>> {
+// Returns what was piped into stdin, and points stdin back at the terminal, since the line editor
+// reads from it.
+fn take_stdin() -> io::Result<File> {
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No json to view: pass a path, or pipe it into stdin",
+        ));
+    }
+    let tty = File::open("/dev/tty")?;
+    unsafe {
+        let piped = libc::dup(libc::STDIN_FILENO);
+        if piped < 0 || libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(piped))
+    }
+}
+
+fn run(
+    json_paths: Vec<String>,
+    session: Option<String>,
+    theme: Theme,
+    inline_width: usize,
+    watch: bool,
+) -> Result<(), Box<dyn Error>> {
+    // A path of - reads the json from stdin
+    let json_paths = if json_paths.is_empty() && session.is_none() {
+        vec!["-".to_owned()]
+    } else {
+        json_paths
+    };
+    let mut stdin = match json_paths.iter().filter(|path| *path == "-").count() {
+        0 => None,
+        1 => Some(take_stdin()?),
+        _ => return Err("Can only read stdin once".into()),
+    };
     enable_raw_mode().expect("Failed to enter raw mode");
 
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen).expect("Failed to enter alternate screen");
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)
+        .expect("Failed to enter alternate screen");
     let default_panic_handler = panic::take_hook();
     panic::set_hook(Box::new(move |p| {
         disable_raw_mode().expect("Failed to disable raw mode");
-        execute!(io::stdout(), LeaveAlternateScreen).expect("Failed to leave alternate screen");
+        execute!(io::stdout(), DisableMouseCapture, LeaveAlternateScreen)
+            .expect("Failed to leave alternate screen");
         default_panic_handler(p);
     }));
     let _defer = DeferRestoreTerminal {};
-    let f = fs::File::open(&json_path)?;
-    let r = io::BufReader::new(f);
     let stdout = io::stdout();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let initial_layout = JexLayout::new(terminal.get_frame().size(), false);
-    let mut app = App::new(r, json_path, initial_layout)?;
+    let initial_layout = JexLayout::new(
+        terminal.get_frame().size(),
+        false,
+        false,
+        LayoutMode::SideBySide,
+        DEFAULT_SPLIT_PERCENT,
+    );
+    let mut json_paths = json_paths.into_iter();
+    let mut app = match &session {
+        Some(session_path) => App::new_from_session(session_path, initial_layout)?,
+        None => {
+            let first_path = json_paths.next().expect("There's always at least one path");
+            if first_path == "-" {
+                let stdin = stdin.take().expect("Stdin should have been taken");
+                App::new(stdin, "stdin".to_owned(), initial_layout)?
+            } else {
+                App::new_from_file(first_path, initial_layout)?
+            }
+        }
+    };
+    // Files opened alongside a session don't take over the panes it was showing
+    let session_panes =
+        session.map(|_| (app.left_index.clone(), app.right_index.clone(), app.focus));
+    for path in json_paths {
+        if path == "-" {
+            let stdin = stdin.take().expect("Stdin should have been taken");
+            app.open_reader(stdin, "stdin".to_owned(), initial_layout)?;
+        } else {
+            app.open_file(path, initial_layout)?;
+        }
+    }
+    match session_panes {
+        Some((left, right, focus)) => app.restore_panes(Some(left), Some(right), focus),
+        None => app.focus_tree(0),
+    }
+    let mut file_watcher = if watch {
+        Some(FileWatcher::new()?)
+    } else {
+        None
+    };
+    app.theme = theme;
+    app.inline_width = inline_width;
     terminal.draw(app.render(AppRenderMode::Normal))?;
     let project_dirs =
         directories::ProjectDirs::from("", "", "jex").ok_or("Error getting project dirs")?;
     let cache_dir = project_dirs.cache_dir();
-    let mut query_rl = RustylineWrapper::new(cache_dir.join("query_history"))?;
-    let mut search_rl = RustylineWrapper::new(cache_dir.join("search_history"))?;
-    let mut open_rl = RustylineWrapper::new(cache_dir.join("open_history"))?;
-    let mut rename_rl = RustylineWrapper::new(cache_dir.join("rename_history"))?;
-    let mut save_rl = RustylineWrapper::new(cache_dir.join("save_history"))?;
+    // Queries are worth keeping around, so they go in the data dir rather than the cache.
+    let mut query_rl: RustylineWrapper<JqCompleter> =
+        RustylineWrapper::new(project_dirs.data_dir().join("query_history"))?;
+    let mut search_rl: RustylineWrapper = RustylineWrapper::new(cache_dir.join("search_history"))?;
+    let mut open_rl: RustylineWrapper = RustylineWrapper::new(cache_dir.join("open_history"))?;
+    let mut rename_rl: RustylineWrapper = RustylineWrapper::new(cache_dir.join("rename_history"))?;
+    let mut save_rl: RustylineWrapper = RustylineWrapper::new(cache_dir.join("save_history"))?;
+    let mut jump_rl: RustylineWrapper = RustylineWrapper::new(cache_dir.join("jump_history"))?;
+    let mut edit_rl: RustylineWrapper = RustylineWrapper::new(cache_dir.join("edit_history"))?;
+    let mut rename_key_rl: RustylineWrapper =
+        RustylineWrapper::new(cache_dir.join("rename_key_history"))?;
+    let mut jq_search_rl: RustylineWrapper =
+        RustylineWrapper::new(cache_dir.join("jq_search_history"))?;
 
     open_rl.editor.set_helper(Some(Helper::new()));
     save_rl.editor.set_helper(Some(Helper::new()));
+    // Numeric prefix typed before a command, like vim's counts
+    let mut pending_count: Option<usize> = None;
+    // Set by m and `, which take the mark name from the next key
+    let mut pending_mark: Option<MarkAction> = None;
     loop {
+        while !event::poll(Duration::from_millis(0))? {
+            let layout = app.layout(terminal.get_frame().size());
+            if let Some(file_watcher) = file_watcher.as_mut() {
+                // Trees opened or saved since the last pass need watching too
+                for path in app.sources() {
+                    if let Err(err) = file_watcher.watch(path) {
+                        warn!("Couldn't watch {}: {}", path, err);
+                    }
+                }
+                if app.reload_changed(&file_watcher.changed(), layout) {
+                    terminal.draw(app.render(AppRenderMode::Normal))?;
+                }
+            }
+            if app.has_pending_loads() {
+                if app.poll_pending_loads(LOAD_POLL_INTERVAL, layout) {
+                    terminal.draw(app.render(AppRenderMode::Normal))?;
+                }
+            } else if file_watcher.is_some() {
+                event::poll(LOAD_POLL_INTERVAL)?;
+            } else {
+                break;
+            }
+        }
         let event = event::read().expect("Error getting next event");
         debug!("Event: {:?}", event);
         let c = match event {
             event::Event::Key(c) => c,
-            event::Event::Mouse(_) => panic!("Mouse events aren't enabled!"),
+            event::Event::Mouse(mouse) => {
+                let layout = app.layout(terminal.get_frame().size());
+                match mouse {
+                    MouseEvent::ScrollDown(column, row, _) => {
+                        app.scroll_pane_at(column, row, layout, true);
+                    }
+                    MouseEvent::ScrollUp(column, row, _) => {
+                        app.scroll_pane_at(column, row, layout, false);
+                    }
+                    MouseEvent::Down(MouseButton::Left, column, row, _) => {
+                        app.click_pane_at(column, row, layout);
+                    }
+                    _ => {}
+                }
+                terminal.draw(app.render(AppRenderMode::Normal))?;
+                continue;
+            }
             event::Event::Resize(width, height) => {
                 let rect = Rect {
                     x: 0,
@@ -280,13 +636,13 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
                     width,
                     height,
                 };
-                let layout = JexLayout::new(rect, app.show_tree);
+                let layout = app.layout(rect);
                 app.resize(layout);
                 terminal.draw(app.render(AppRenderMode::Normal))?;
                 continue;
             }
         };
-        let layout = JexLayout::new(terminal.get_frame().size(), app.show_tree);
+        let layout = app.layout(terminal.get_frame().size());
         if let Some(flash) = app.flash.as_mut() {
             match c.code {
                 KeyCode::Esc => {
@@ -303,18 +659,108 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
             terminal.draw(app.render(AppRenderMode::Normal))?;
             continue;
         }
+        if app.outline_selection.is_some() {
+            match c.code {
+                KeyCode::Char('j') | KeyCode::Down => app.move_outline_selection(true),
+                KeyCode::Char('k') | KeyCode::Up => app.move_outline_selection(false),
+                KeyCode::Enter => app.select_outline_key(),
+                KeyCode::Char('l') => app.toggle_outline(),
+                KeyCode::Esc => app.outline_selection = None,
+                _ => {}
+            }
+            terminal.draw(app.render(AppRenderMode::Normal))?;
+            continue;
+        }
+        let count = pending_count.take();
+        // How many times to repeat a motion
+        let times = count.unwrap_or(1);
+        if let Some(action) = pending_mark.take() {
+            if let KeyCode::Char(mark) = c.code {
+                match action {
+                    MarkAction::Set => app.set_mark(mark),
+                    MarkAction::Jump => app.jump_to_mark(mark),
+                }
+            }
+            terminal.draw(app.render(AppRenderMode::Normal))?;
+            continue;
+        }
         match c.code {
             KeyCode::Esc => break,
             KeyCode::Char('t') => {
                 app.show_tree = !app.show_tree;
             }
+            KeyCode::Char('l') => {
+                app.toggle_outline();
+            }
+            KeyCode::Char('V') => {
+                app.toggle_layout_mode(terminal.get_frame().size());
+            }
+            KeyCode::Char('Q') => {
+                app.copy_query(false);
+            }
+            KeyCode::Char('q') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                app.copy_query(true);
+            }
+            KeyCode::Char('M') => {
+                app.toggle_single_pane(terminal.get_frame().size());
+            }
+            KeyCode::Char('>') => {
+                app.resize_focused_pane(true, terminal.get_frame().size());
+            }
+            KeyCode::Char('<') => {
+                app.resize_focused_pane(false, terminal.get_frame().size());
+            }
+            KeyCode::Char('X') => {
+                app.remove_focused_view();
+            }
+            KeyCode::Char('R') => {
+                app.reload_focused(layout);
+            }
+            KeyCode::Char('I') => {
+                app.toggle_control_pictures();
+            }
+            KeyCode::Char('B') => {
+                app.toggle_literal_glyphs();
+            }
+            KeyCode::Char('H') => {
+                app.toggle_literal_newlines();
+            }
+            #[cfg(feature = "dev-tools")]
+            KeyCode::Char('i') => {
+                app.show_debug_info();
+            }
+            KeyCode::Char('L') => {
+                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                let limit = app.result_limit.to_string();
+                if let Ok(limit) = jump_rl
+                    .editor
+                    .readline_with_initial("Result limit:", (&limit, ""))
+                {
+                    // Just in case rustyline messed stuff up
+                    force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                    app.set_result_limit(&limit, layout);
+                }
+            }
+            KeyCode::Char('#') => {
+                app.show_line_numbers = !app.show_line_numbers;
+            }
+            KeyCode::Char('x') => {
+                app.toggle_diff();
+            }
             KeyCode::Char('q') => {
-                if app.focused_query_mut().is_some() {
+                if let ViewWithParent::Child { parent, .. } = app.focused_view() {
+                    let completer = match &parent.view {
+                        View::Json(Some(parent)) => JqCompleter::new(&parent.values),
+                        _ => JqCompleter::new(&[]),
+                    };
+                    query_rl.editor.set_helper(Some(completer));
                     terminal.draw(app.render(AppRenderMode::InputEditor))?;
                     let query = app.focused_query_mut().unwrap();
                     match query_rl.editor.readline_with_initial("", (&*query, "")) {
                         Ok(new_query) => {
                             *query = new_query;
+                            // Save now, so the query survives even if we crash later.
+                            query_rl.save_history();
                             // Just in case rustyline messed stuff up
                             force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
                             app.recompute_focused_view(layout.right);
@@ -323,6 +769,20 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
                     }
                 }
             }
+            KeyCode::Char('|') => {
+                if let View::Json(Some(view)) = &app.focused_view().frame().view {
+                    query_rl
+                        .editor
+                        .set_helper(Some(JqCompleter::new(&view.values)));
+                    terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                    if let Ok(query) = query_rl.editor.readline("") {
+                        query_rl.save_history();
+                        // Just in case rustyline messed stuff up
+                        force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                        app.push_child_with_query(query, layout);
+                    }
+                }
+            }
             KeyCode::Tab => {
                 app.focus = app.focus.swap();
                 debug!("Swapped focus to {:?}", app.focus);
@@ -337,23 +797,32 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
                     .expect("App index invalidated");
                 tree.push_trivial_child(rect);
             }
-            KeyCode::Char('j') => match app.focus {
-                Focus::Left => {
-                    app.left_index.advance(&app.views);
-                }
-                Focus::Right => {
-                    app.right_index.advance(&app.views);
-                }
-            },
-            KeyCode::Char('k') => match app.focus {
-                Focus::Left => {
-                    app.left_index.regress(&app.views);
+            KeyCode::Char('j') => {
+                for _ in 0..times {
+                    match app.focus {
+                        Focus::Left => {
+                            app.left_index.advance(&app.views);
+                        }
+                        Focus::Right => {
+                            app.right_index.advance(&app.views);
+                        }
+                    }
                 }
-                Focus::Right => {
-                    app.right_index.regress(&app.views);
+            }
+            KeyCode::Char('k') => {
+                for _ in 0..times {
+                    match app.focus {
+                        Focus::Left => {
+                            app.left_index.regress(&app.views);
+                        }
+                        Focus::Right => {
+                            app.right_index.regress(&app.views);
+                        }
+                    }
                 }
-            },
-            KeyCode::Char('r') => {
+            }
+            // Ctrl-R is redo
+            KeyCode::Char('r') if !c.modifiers.contains(KeyModifiers::CONTROL) => {
                 terminal.draw(app.render(AppRenderMode::InputEditor))?;
                 let mut view_with_parent = app.focused_view_mut();
                 let frame = view_with_parent.frame();
@@ -368,37 +837,135 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
                 }
                 force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
             }
+            KeyCode::Char('s') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                if let Ok(path) = save_rl.editor.readline("Save session:") {
+                    // Just in case rustyline messed stuff up
+                    force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                    if confirm_overwrite(&mut terminal, &mut app, &path)? {
+                        let flash = match app.save_session(&path) {
+                            Ok(()) => format!("Saved session to {}", path),
+                            Err(err) => format!("Couldn't save session to {}:\n{}", path, err),
+                        };
+                        app.set_flash(flash);
+                    }
+                }
+                force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+            }
             KeyCode::Char('s') => {
                 terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                let frame = app.focused_view().frame();
+                let path = match &frame.view {
+                    View::Json(Some(_)) => save_rl
+                        .editor
+                        .readline_with_initial("Save to:", (&frame.name, ""))
+                        .ok(),
+                    _ => None,
+                };
+                let (path, pretty) = match path.map(|path| parse_save_path(&path)) {
+                    Some((path, pretty)) => (Some(path), pretty),
+                    None => (None, true),
+                };
+                let path = match path {
+                    Some(path) if confirm_overwrite(&mut terminal, &mut app, &path)? => Some(path),
+                    _ => None,
+                };
                 let mut view_with_parent = app.focused_view_mut();
                 let frame = view_with_parent.frame();
-                let flash = {
-                    if let View::Json(Some(view)) = &frame.view {
-                        match save_rl
-                            .editor
-                            .readline_with_initial("Save to:", (&frame.name, ""))
-                        {
-                            Ok(path) => {
-                                if let Err(err) = view.save_to(&path) {
-                                    Some(format!("Error saving json:\n{:?}", err))
-                                } else {
-                                    frame.name = path;
-                                    let focused_index = app.focused_index().clone();
-                                    app.re_root(&focused_index);
-                                    None
+                let flash = match (&frame.view, path) {
+                    (View::Json(Some(view)), Some(path)) => {
+                        let is_yaml = path.ends_with(".yaml") || path.ends_with(".yml");
+                        let res = if is_yaml {
+                            view.save_to_yaml(&path)
+                        } else {
+                            view.save_to(&path, pretty)
+                        };
+                        match res {
+                            Err(err) => Some(format!("Couldn't save to {}:\n{}", path, err)),
+                            Ok(()) => {
+                                let flash = format!("Saved to {}", path);
+                                frame.name = path.clone();
+                                let focused_index = app.focused_index().clone();
+                                app.re_root(&focused_index);
+                                if let Some(file_watcher) = file_watcher.as_mut() {
+                                    file_watcher.ignore_own_write(&path);
+                                }
+                                // Reloading can only read json back in
+                                if !is_yaml {
+                                    let tree = app.focused_index().tree;
+                                    app.views.trees[tree].source = Some(path);
                                 }
+                                Some(flash)
                             }
-                            Err(_) => None,
                         }
-                    } else {
-                        None
                     }
+                    _ => None,
                 };
                 if let Some(flash) = flash {
                     app.set_flash(flash);
                 }
                 force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
             }
+            KeyCode::Char('S') => {
+                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                let path = match &app.focused_view().frame().view {
+                    View::Json(Some(_)) => save_rl.editor.readline("Save subtree to:").ok(),
+                    _ => None,
+                };
+                let (path, pretty) = match path.map(|path| parse_save_path(&path)) {
+                    Some((path, pretty)) => (Some(path), pretty),
+                    None => (None, true),
+                };
+                let path = match path {
+                    Some(path) if confirm_overwrite(&mut terminal, &mut app, &path)? => Some(path),
+                    _ => None,
+                };
+                let flash = match (&app.focused_view().frame().view, path) {
+                    (View::Json(Some(view)), Some(path)) => {
+                        match view.save_subtree_to(&path, pretty) {
+                            Err(err) => Some(format!("Couldn't save to {}:\n{}", path, err)),
+                            Ok(()) => Some(format!("Saved to {}", path)),
+                        }
+                    }
+                    _ => None,
+                };
+                if let Some(flash) = flash {
+                    app.set_flash(flash);
+                }
+                force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+            }
+            KeyCode::Char('E') => {
+                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                let path = match &app.focused_view().frame().view {
+                    View::Json(Some(_)) => save_rl.editor.readline("Export to:").ok(),
+                    _ => None,
+                };
+                let path = match path {
+                    Some(path) if confirm_overwrite(&mut terminal, &mut app, &path)? => Some(path),
+                    _ => None,
+                };
+                let flash = match (&app.focused_view().frame().view, path) {
+                    (View::Json(Some(view)), Some(path)) => match view.export_to(&path, &app.theme)
+                    {
+                        Err(err) => Some(format!("Couldn't export to {}:\n{}", path, err)),
+                        Ok(()) => Some(format!("Exported to {}", path)),
+                    },
+                    _ => None,
+                };
+                if let Some(flash) = flash {
+                    app.set_flash(flash);
+                }
+                force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+            }
+            KeyCode::Char('o') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                if let Ok(path) = open_rl.editor.readline("Load session:") {
+                    if let Err(err) = app.load_session(&path, layout) {
+                        app.set_flash(format!("Couldn't load session {}:\n{}", path, err));
+                    }
+                }
+                force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+            }
             KeyCode::Char('o') => {
                 terminal.draw(app.render(AppRenderMode::InputEditor))?;
                 let flash = {
@@ -417,10 +984,14 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
             }
             _ => {}
         }
+        // The commands above can change the layout, like t or V
+        let layout = app.layout(terminal.get_frame().size());
         let view_rect = match app.focus {
             Focus::Left => layout.left,
             Focus::Right => layout.right,
         };
+        let show_line_numbers = app.show_line_numbers;
+        let inline_width = app.inline_width;
         let mut view_with_parent = app.focused_view_mut();
         let view_frame = view_with_parent.frame();
         let json_rect = Block::default().borders(Borders::ALL).inner(view_rect);
@@ -428,52 +999,338 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
             View::Error(_) => {}
             View::Json(None) => {}
             View::Json(Some(view)) => {
-                view.resize_to(json_rect);
+                view.resize_to(view.text_rect(json_rect, show_line_numbers));
                 match c.code {
                     KeyCode::Down => {
-                        view.advance_cursor();
+                        for _ in 0..times {
+                            view.advance_cursor();
+                        }
                     }
                     KeyCode::Up => {
-                        view.regress_cursor();
+                        for _ in 0..times {
+                            view.regress_cursor();
+                        }
+                    }
+                    KeyCode::Char('f') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                        for _ in 0..times {
+                            view.page_down();
+                        }
+                    }
+                    KeyCode::Char('b') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                        for _ in 0..times {
+                            view.page_up();
+                        }
+                    }
+                    KeyCode::Char('d') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                        for _ in 0..times {
+                            view.half_page_down();
+                        }
+                    }
+                    KeyCode::Char('u') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                        for _ in 0..times {
+                            view.half_page_up();
+                        }
+                    }
+                    KeyCode::Char('u') => {
+                        app.undo(layout);
+                    }
+                    KeyCode::Char('r') if c.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.redo(layout);
                     }
                     KeyCode::PageDown => {
-                        view.page_down();
+                        for _ in 0..times {
+                            view.page_down();
+                        }
                     }
                     KeyCode::PageUp => {
-                        view.page_up();
+                        for _ in 0..times {
+                            view.page_up();
+                        }
+                    }
+                    // A leading 0 isn't a count
+                    KeyCode::Char('0') if count.is_none() => {}
+                    KeyCode::Char(d) if d.is_ascii_digit() => {
+                        let digit = d.to_digit(10).unwrap() as usize;
+                        pending_count = Some(count.unwrap_or(0) * 10 + digit);
                     }
-                    KeyCode::Char('z') => {
-                        view.toggle_fold();
+                    KeyCode::Char('z') if c.modifiers.contains(KeyModifiers::ALT) => {
+                        // Unfold everything below a folded container, or fold everything below an
+                        // open one
+                        let fold = !view.cursor_is_folded();
+                        if let Err(err) = view.fold_recursive(fold) {
+                            app.set_flash(err.to_string());
+                        }
+                    }
+                    KeyCode::Char('z') => match count {
+                        Some(depth) => view.fold_to_depth(depth),
+                        None => {
+                            if let Err(err) = view.toggle_fold() {
+                                app.set_flash(err.to_string());
+                            }
+                        }
+                    },
+                    KeyCode::Char('C') => {
+                        view.center_cursor();
+                    }
+                    KeyCode::Char('Z') => {
+                        view.unfold_all();
                     }
                     KeyCode::Char('/') => {
+                        // Jumps to the first match as the pattern is typed, going back to where it
+                        // started if the search is cancelled.
+                        let original = view.clone();
+                        let new_search = read_live_input(
+                            &mut terminal,
+                            &mut app,
+                            "Search:",
+                            search_rl.editor.history(),
+                            |app, pattern| app.preview_search(pattern, &original),
+                        )?;
+                        app.restore_focused_view(original);
+                        if let Some(new_search) = new_search {
+                            search_rl.editor.add_history_entry(new_search.as_str());
+                            match app.set_search_pattern(&new_search) {
+                                Ok(()) => search(&mut app, false, 1),
+                                Err(err) => app.set_flash(format!("Invalid regex:\n{}", err)),
+                            }
+                        }
+                    }
+                    KeyCode::Char('J') => {
+                        // Jumps to the best matching key as the query is typed, going back to
+                        // where it started if it's cancelled.
+                        let original = view.clone();
+                        let query = read_live_input(
+                            &mut terminal,
+                            &mut app,
+                            "Key:",
+                            &History::new(),
+                            |app, query| app.preview_fuzzy_key(query, &original),
+                        )?;
+                        match query {
+                            None => app.restore_focused_view(original),
+                            Some(query) => {
+                                if !query.is_empty() && original.fuzzy_find_key(&query).is_none() {
+                                    app.set_flash(format!("No key matches {}", query));
+                                }
+                            }
+                        }
+                    }
+                    KeyCode::Char('c') => {
                         terminal.draw(app.render(AppRenderMode::InputEditor))?;
-                        match search_rl.editor.readline_with_initial("Search:", ("", "")) {
-                            Ok(new_search) => {
+                        if let Ok(pattern) = search_rl.editor.readline("Count:") {
+                            // Just in case rustyline messed stuff up
+                            force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                            match Regex::new(&pattern) {
+                                Ok(re) => app.count_matches(&re),
+                                Err(err) => app.set_flash(format!("Invalid regex:\n{}", err)),
+                            }
+                        }
+                    }
+                    KeyCode::Char('G') if !view.ungrep() => {
+                        terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                        if let Ok(pattern) = search_rl.editor.readline("Grep:") {
+                            // Just in case rustyline messed stuff up
+                            force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                            match Regex::new(&pattern) {
+                                Ok(re) => app.grep(&re),
+                                Err(err) => app.set_flash(format!("Invalid regex:\n{}", err)),
+                            }
+                        }
+                    }
+                    KeyCode::Char('&') => {
+                        terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                        if let Ok(predicate) = jq_search_rl.editor.readline("Search (jq):") {
+                            // Just in case rustyline messed stuff up
+                            force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                            match JQ::compile(&predicate) {
+                                Ok(prog) => {
+                                    app.set_search(Some(SearchQuery::Jq {
+                                        prog,
+                                        source: predicate,
+                                    }));
+                                    search(&mut app, false, 1);
+                                }
+                                Err(errors) => app.set_flash(errors.join("\n")),
+                            }
+                        }
+                    }
+                    KeyCode::Char('v') => {
+                        app.cycle_search_scope();
+                    }
+                    KeyCode::Char('w') => {
+                        app.toggle_search_wrap();
+                    }
+                    KeyCode::Char('d') => {
+                        app.delete_at_cursor(layout);
+                    }
+                    KeyCode::Char('O') => {
+                        app.promote_cursor_to_root(layout);
+                    }
+                    KeyCode::Char('e') => match view.cursor.leaf_to_json() {
+                        None => app.set_flash("Can't edit a container".to_owned()),
+                        Some(text) => {
+                            terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                            if let Ok(new_text) =
+                                edit_rl.editor.readline_with_initial("Edit:", (&text, ""))
+                            {
                                 // Just in case rustyline messed stuff up
                                 force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
-                                app.search_re = Regex::new(new_search.as_ref()).ok();
-                                app.search(false);
+                                let (value, lossy_integers) = parse_typed_value(&new_text);
+                                app.replace_at_cursor(value, layout);
+                                app.flash_lossy_integers(lossy_integers);
                             }
-                            Err(_) => {}
                         }
+                    },
+                    KeyCode::Char('a') => {
+                        match view.cursor.enclosing_container().map(|c| c.focus) {
+                            Some(JV::Object(obj)) => {
+                                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                                if let Ok(key) = rename_key_rl.editor.readline("New key:") {
+                                    // Just in case rustyline messed stuff up
+                                    force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                                    if obj.contains_key(&key) {
+                                        app.set_flash(format!("Key {} already exists", key));
+                                    } else {
+                                        terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                                        if let Ok(text) = edit_rl.editor.readline("Value:") {
+                                            force_draw(
+                                                &mut terminal,
+                                                app.render(AppRenderMode::Normal),
+                                            )?;
+                                            let (value, lossy_integers) = parse_typed_value(&text);
+                                            app.insert_key(&key, value, layout);
+                                            app.flash_lossy_integers(lossy_integers);
+                                        }
+                                    }
+                                }
+                            }
+                            Some(JV::Array(_)) => {
+                                terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                                if let Ok(text) = edit_rl.editor.readline("Append:") {
+                                    // Just in case rustyline messed stuff up
+                                    force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                                    let (value, lossy_integers) = parse_typed_value(&text);
+                                    app.append_element(value, layout);
+                                    app.flash_lossy_integers(lossy_integers);
+                                }
+                            }
+                            _ => app.set_flash("Not in an object or array".to_owned()),
+                        }
+                    }
+                    KeyCode::Char('K') => match view.cursor.current_key() {
+                        None => app.set_flash("Not on an object key".to_owned()),
+                        Some(key) => {
+                            terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                            if let Ok(new_key) = rename_key_rl
+                                .editor
+                                .readline_with_initial("Rename key:", (key.value(), ""))
+                            {
+                                // Just in case rustyline messed stuff up
+                                force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                                app.rename_key(&new_key, layout);
+                            }
+                        }
+                    },
+                    KeyCode::Char('A') => {
+                        view.toggle_sort_keys();
+                    }
+                    KeyCode::Char('D') => {
+                        view.toggle_descendant_counts();
+                    }
+                    KeyCode::Char('=') => {
+                        view.toggle_inline(inline_width);
+                    }
+                    KeyCode::Char('W') => {
+                        view.toggle_wrap();
+                    }
+                    KeyCode::Left => {
+                        view.scroll_left(H_SCROLL_STEP);
+                    }
+                    KeyCode::Right => {
+                        view.scroll_right(H_SCROLL_STEP);
+                    }
+                    KeyCode::Char('F') => {
+                        view.fold_siblings();
+                    }
+                    KeyCode::Char('^') => {
+                        view.first_sibling();
+                    }
+                    KeyCode::Char('$') => {
+                        view.last_sibling();
+                    }
+                    KeyCode::Char('m') => {
+                        pending_mark = Some(MarkAction::Set);
+                    }
+                    KeyCode::Char('`') => {
+                        pending_mark = Some(MarkAction::Jump);
+                    }
+                    KeyCode::Char('g') => {
+                        let current_path = view.cursor.to_jq_path();
+                        terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                        if let Ok(path) = jump_rl
+                            .editor
+                            .readline_with_initial("Go to path:", (&current_path, ""))
+                        {
+                            // Just in case rustyline messed stuff up
+                            force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                            app.jump_to_path(&path);
+                        }
+                    }
+                    KeyCode::Char('U') => app.push_embedded_json_child(layout),
+                    KeyCode::Char('f') => app.follow_reference(),
+                    KeyCode::Enter => app.follow_pointer(),
+                    KeyCode::Char(':') => {
+                        terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                        if let Ok(line) = jump_rl.editor.readline("Go to line:") {
+                            // Just in case rustyline messed stuff up
+                            force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                            app.goto_line(&line);
+                        }
+                    }
+                    KeyCode::Char('T') => {
+                        terminal.draw(app.render(AppRenderMode::InputEditor))?;
+                        if let Ok(line) = jump_rl.editor.readline("Go to document:") {
+                            // Just in case rustyline messed stuff up
+                            force_draw(&mut terminal, app.render(AppRenderMode::Normal))?;
+                            app.goto_document(&line);
+                        }
+                    }
+                    KeyCode::Char(']') => app.step_document(false),
+                    KeyCode::Char('[') => app.step_document(true),
+                    KeyCode::Char('y') => {
+                        let text = view.cursor.focused_value().to_pretty_string();
+                        app.copy_to_clipboard(text);
+                    }
+                    KeyCode::Char('p') => {
+                        let path = view.cursor.to_jq_path();
+                        app.copy_to_clipboard(path);
+                    }
+                    KeyCode::Char('P') => {
+                        let text = view.cursor.to_path_assignment();
+                        app.copy_to_clipboard(text);
                     }
                     KeyCode::Char('n') => {
-                        app.search(false);
+                        search(&mut app, false, times);
                     }
                     KeyCode::Char('N') => {
-                        app.search(true);
+                        search(&mut app, true, times);
                     }
                     KeyCode::Home => {
-                        view.scroll =
-                            GlobalCursor::new(view.values.clone(), view.rect.width, &view.folds)
-                                .expect("values should still exist");
+                        view.scroll = GlobalCursor::new(
+                            view.values.clone(),
+                            view.wrap_width(),
+                            &view.folds,
+                            view.display,
+                        )
+                        .expect("values should still exist");
                         view.cursor = view.scroll.value_cursor.clone();
                     }
                     KeyCode::End => {
                         view.scroll = GlobalCursor::new_end(
                             view.values.clone(),
-                            view.rect.width,
+                            view.wrap_width(),
                             &view.folds,
+                            view.display,
                         )
                         .expect("values should still exist");
                         view.cursor = view.scroll.value_cursor.clone();
@@ -491,11 +1348,9 @@ fn run(json_path: String) -> Result<(), Box<dyn Error>> {
 }
 
 #[cfg(feature = "dev-tools")]
-fn bench(json_path: String) -> Result<(), io::Error> {
+fn bench(json_path: String) -> Result<(), Box<dyn Error>> {
     let mut profiler = PROFILER.lock().unwrap();
     profiler.start("profile").unwrap();
-    let f = fs::File::open(&json_path)?;
-    let r = io::BufReader::new(f);
     let initial_layout = JexLayout {
         left: Rect {
             x: 0,
@@ -509,15 +1364,25 @@ fn bench(json_path: String) -> Result<(), io::Error> {
             width: 100,
             height: 100,
         },
-        query: Rect {
+        breadcrumb: Rect {
             x: 0,
             y: 100,
             width: 100,
             height: 1,
         },
+        query: Rect {
+            x: 0,
+            y: 101,
+            width: 100,
+            height: 1,
+        },
         tree: None,
+        outline: None,
     };
-    let mut app = App::new(r, json_path, initial_layout)?;
+    let mut app = App::new_from_file(json_path, initial_layout)?;
+    while app.has_pending_loads() {
+        app.poll_pending_loads(LOAD_POLL_INTERVAL, initial_layout);
+    }
     std::mem::forget(app);
     profiler.stop().unwrap();
     Ok(())
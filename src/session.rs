@@ -0,0 +1,304 @@
+// Saving what you were looking at (the open files, the queries on them, and the cursor and folds in
+// each view) so an investigation can be picked up again later. The json itself isn't saved: it's
+// read back from the source files, so trees that didn't come from a file (like stdin) are left out.
+use crate::{
+    app::{App, Focus},
+    cursor::{Fold, ValuePath},
+    layout::JexLayout,
+    view_tree::{
+        read_values, NamedView, View, ViewError, ViewForestIndex, ViewTree, ViewTreeIndex,
+    },
+};
+use serde_json::{json, Value};
+use std::{error::Error, fs, io, io::Write};
+use tui::layout::Rect;
+
+pub struct Session {
+    pub trees: Vec<ViewTree>,
+    // These are None if the session didn't have them, or they no longer exist
+    pub left_index: Option<ViewForestIndex>,
+    pub right_index: Option<ViewForestIndex>,
+    pub focus: Focus,
+}
+
+pub fn save(app: &App, path: &str) -> io::Result<()> {
+    let session = to_json(app)?;
+    let mut w = io::BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer_pretty(&mut w, &session)?;
+    writeln!(w)?;
+    w.flush()
+}
+
+pub fn load(path: &str, layout: JexLayout, result_limit: usize) -> Result<Session, Box<dyn Error>> {
+    let r = io::BufReader::new(fs::File::open(path)?);
+    let session: Value = serde_json::from_reader(r)?;
+    from_json(&session, layout, result_limit)
+}
+
+pub fn to_json(app: &App) -> io::Result<Value> {
+    // Where each tree ends up in the session, if it's saved at all
+    let mut new_indices = Vec::new();
+    let mut trees = Vec::new();
+    for tree in app.views.trees.iter() {
+        match tree.source.as_deref() {
+            Some(source) => {
+                new_indices.push(Some(trees.len()));
+                let mut tree_json = tree_to_json(tree);
+                tree_json["source"] = source.into();
+                trees.push(tree_json);
+            }
+            None => new_indices.push(None),
+        }
+    }
+    if trees.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "None of the open trees were loaded from a file",
+        ));
+    }
+    let index_to_json = |index: &ViewForestIndex| match new_indices[index.tree] {
+        Some(tree) => json!({"tree": tree, "path": index.within_tree.path}),
+        None => Value::Null,
+    };
+    let focus = match app.focus {
+        Focus::Left => "left",
+        Focus::Right => "right",
+    };
+    Ok(json!({
+        "trees": trees,
+        "left": index_to_json(&app.left_index),
+        "right": index_to_json(&app.right_index),
+        "focus": focus,
+    }))
+}
+
+fn tree_to_json(tree: &ViewTree) -> Value {
+    let mut out = view_to_json(&tree.view_frame);
+    let children: Vec<Value> = tree
+        .children
+        .iter()
+        .map(|(query, child)| {
+            let mut child_json = tree_to_json(child);
+            child_json["query"] = query.as_str().into();
+            child_json
+        })
+        .collect();
+    out["children"] = children.into();
+    out
+}
+
+fn view_to_json(frame: &NamedView) -> Value {
+    let mut out = json!({ "name": frame.name });
+    if let View::Json(Some(view)) = &frame.view {
+        // Inline folds are left to compact mode to work out again
+        let mut folds: Vec<&(usize, Vec<usize>)> = view
+            .folds
+            .iter()
+            .filter(|(_, fold)| **fold != Fold::Inline)
+            .map(|(path, _)| path)
+            .collect();
+        folds.sort();
+        out["folds"] = json!(folds);
+        out["cursor"] = view.cursor.to_path().to_json();
+    }
+    out
+}
+
+pub fn from_json(
+    session: &Value,
+    layout: JexLayout,
+    result_limit: usize,
+) -> Result<Session, Box<dyn Error>> {
+    let trees = session["trees"]
+        .as_array()
+        .ok_or("Not a jex session: no trees")?
+        .iter()
+        .map(|tree| tree_from_json(tree, layout, result_limit))
+        .collect::<Result<Vec<ViewTree>, Box<dyn Error>>>()?;
+    if trees.is_empty() {
+        return Err("Not a jex session: no trees".into());
+    }
+    let focus = match session["focus"].as_str() {
+        Some("right") => Focus::Right,
+        _ => Focus::Left,
+    };
+    Ok(Session {
+        trees,
+        left_index: index_from_json(&session["left"]),
+        right_index: index_from_json(&session["right"]),
+        focus,
+    })
+}
+
+fn tree_from_json(
+    tree: &Value,
+    layout: JexLayout,
+    result_limit: usize,
+) -> Result<ViewTree, Box<dyn Error>> {
+    let source = tree["source"]
+        .as_str()
+        .ok_or("Not a jex session: tree with no source file")?;
+    // Like a reload, a file that's gone missing shows up as an error instead of sinking the session
+    let mut skipped_values = Vec::new();
+    let mut lossy_integers = 0;
+    let mut view = match read_values(source) {
+        Ok(loaded) => {
+            skipped_values = loaded.skipped;
+            lossy_integers = loaded.lossy_integers;
+            View::new(loaded.values, layout.left)
+        }
+        Err(err) => View::Error(ViewError::Load(vec![
+            format!("Couldn't load {}:", source),
+            err.to_string(),
+        ])),
+    };
+    restore_view(&mut view, tree);
+    let children = children_from_json(&view, tree, layout.right, result_limit)?;
+    Ok(ViewTree {
+        view_frame: NamedView {
+            view,
+            name: name_from_json(tree, source),
+        },
+        children,
+        source: Some(source.to_owned()),
+        skipped_values,
+        lossy_integers,
+    })
+}
+
+// Reruns each child's query on the parent, as ViewTree::recompute_children would
+fn children_from_json(
+    parent: &View,
+    tree: &Value,
+    target_view_rect: Rect,
+    result_limit: usize,
+) -> Result<Vec<(String, ViewTree)>, Box<dyn Error>> {
+    let children = match tree["children"].as_array() {
+        Some(children) => children,
+        None => return Ok(Vec::new()),
+    };
+    children
+        .iter()
+        .map(|child| {
+            let query = child["query"]
+                .as_str()
+                .ok_or("Not a jex session: view with no query")?;
+            let mut view = match parent {
+                View::Json(Some(view)) => view.apply_query(query, target_view_rect, result_limit),
+                View::Json(None) | View::Error(_) => View::Json(None),
+            };
+            restore_view(&mut view, child);
+            let children = children_from_json(&view, child, target_view_rect, result_limit)?;
+            let tree = ViewTree {
+                view_frame: NamedView {
+                    view,
+                    name: name_from_json(child, query),
+                },
+                children,
+                source: None,
+                skipped_values: Vec::new(),
+                lossy_integers: 0,
+            };
+            Ok((query.to_owned(), tree))
+        })
+        .collect()
+}
+
+fn name_from_json(view: &Value, default: &str) -> String {
+    view["name"].as_str().unwrap_or(default).to_owned()
+}
+
+fn restore_view(view: &mut View, saved: &Value) {
+    let view = match view {
+        View::Json(Some(view)) => view,
+        _ => return,
+    };
+    let folds: Vec<(usize, Vec<usize>)> = saved["folds"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|fold| serde_json::from_value(fold.clone()).ok())
+        .collect();
+    view.restore_state(folds, ValuePath::from_json(&saved["cursor"]));
+}
+
+fn index_from_json(index: &Value) -> Option<ViewForestIndex> {
+    Some(ViewForestIndex {
+        tree: index["tree"].as_u64()? as usize,
+        within_tree: ViewTreeIndex {
+            path: serde_json::from_value(index["path"].clone()).ok()?,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_json, to_json};
+    use crate::{
+        app::{App, Focus},
+        cursor::{parse_path, Fold, LeafCursor},
+        layout::{JexLayout, LayoutMode, DEFAULT_SPLIT_PERCENT},
+        view_tree::View,
+    };
+    use serde_json::json;
+    use std::fs;
+    use tui::layout::Rect;
+
+    #[test]
+    fn unit_session_round_trip() {
+        let dir = std::env::temp_dir().join(format!("jex_session_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        fs::write(&path, r#"{"a": [1, 2], "b": {"c": 3}}"#).unwrap();
+        let path = path.to_str().unwrap().to_owned();
+        let layout = JexLayout::new(
+            Rect::new(0, 0, 80, 24),
+            false,
+            false,
+            LayoutMode::SideBySide,
+            DEFAULT_SPLIT_PERCENT,
+        );
+        let mut app = App::new_from_file(path.clone(), layout).unwrap();
+        while app.has_pending_loads() {
+            app.poll_pending_loads(std::time::Duration::from_millis(10), layout);
+        }
+        // Fold .b and put the cursor on .a[1] on the left, and query .a on the right
+        if let View::Json(Some(view)) = &mut app.views.trees[0].view_frame.view {
+            let b = parse_path(".b", &view.values).unwrap().strip_position();
+            view.folds.insert(b, Fold::Children);
+            let a1 = parse_path(".a[1]", &view.values).unwrap();
+            view.cursor = LeafCursor::from_path(view.values.clone(), &a1);
+        }
+        app.views.trees[0].children[0].0 = ".a".to_owned();
+        app.views.trees[0].recompute_children(layout.right, app.result_limit);
+        app.focus = Focus::Right;
+        let saved = to_json(&app).unwrap();
+        // Round trips through text, like it would on disk
+        let saved: serde_json::Value = serde_json::from_str(&saved.to_string()).unwrap();
+        let session = from_json(&saved, layout, app.result_limit).unwrap();
+        assert_eq!(session.trees.len(), 1);
+        assert_eq!(session.trees[0].source.as_deref(), Some(path.as_str()));
+        match &session.trees[0].view_frame.view {
+            View::Json(Some(view)) => {
+                let b = parse_path(".b", &view.values).unwrap().strip_position();
+                assert_eq!(view.folds.keys().collect::<Vec<_>>(), vec![&b]);
+                assert_eq!(
+                    view.cursor.to_path(),
+                    parse_path(".a[1]", &view.values).unwrap()
+                );
+            }
+            _ => panic!("Expected a json view"),
+        }
+        let (query, child) = &session.trees[0].children[0];
+        assert_eq!(query, ".a");
+        match &child.view_frame.view {
+            View::Json(Some(view)) => {
+                assert_eq!(serde_json::Value::from(&view.values[0]), json!([1.0, 2.0]))
+            }
+            _ => panic!("Expected a json view"),
+        }
+        assert_eq!(session.right_index.unwrap().within_tree.path, vec![0]);
+        assert_eq!(session.focus, Focus::Right);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,326 @@
+use std::{fmt, str::FromStr};
+use tui::style::{Color, Modifier, Style};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    // Used for everything without a more specific style below
+    pub base: Style,
+    // Folded children counts and line numbers
+    pub background: Style,
+    // Patched over the style of the value under the cursor
+    pub cursor: Style,
+    pub null: Style,
+    pub bool: Style,
+    pub number: Style,
+    pub string: Style,
+    // Patched over lines only in the right pane when diffing the two panes
+    pub added: Style,
+    // Patched over lines only in the left pane when diffing the two panes
+    pub removed: Style,
+    // Queries that failed
+    pub error: Style,
+    // When false, nothing gets a color, and things are told apart with modifiers like reverse video
+    // instead. Styles that aren't part of the theme go through colored to respect it.
+    pub use_color: bool,
+}
+
+impl Theme {
+    // The original look: no distinction between value types
+    pub fn plain() -> Self {
+        Theme {
+            base: Style::default().fg(Color::White).bg(Color::Black),
+            background: Style::default().add_modifier(Modifier::DIM),
+            cursor: Style::default().bg(Color::Blue),
+            null: Style::default(),
+            bool: Style::default(),
+            number: Style::default(),
+            string: Style::default(),
+            added: Style::default().fg(Color::Black).bg(Color::Green),
+            removed: Style::default().fg(Color::Black).bg(Color::Red),
+            error: Style::default().fg(Color::White).bg(Color::Red),
+            use_color: true,
+        }
+    }
+    pub fn dark() -> Self {
+        Theme {
+            null: Style::default().fg(Color::Magenta),
+            bool: Style::default().fg(Color::Yellow),
+            number: Style::default().fg(Color::Cyan),
+            string: Style::default().fg(Color::Green),
+            ..Theme::plain()
+        }
+    }
+    pub fn light() -> Self {
+        Theme {
+            base: Style::default().fg(Color::Black).bg(Color::White),
+            background: Style::default().fg(Color::DarkGray),
+            cursor: Style::default().bg(Color::LightYellow),
+            null: Style::default().fg(Color::Magenta),
+            bool: Style::default().fg(Color::Red),
+            number: Style::default().fg(Color::Blue),
+            string: Style::default().fg(Color::Green),
+            added: Style::default().fg(Color::Black).bg(Color::LightGreen),
+            removed: Style::default().fg(Color::Black).bg(Color::LightRed),
+            ..Theme::plain()
+        }
+    }
+    // The same theme for terminals (or people) that can't or don't want to see color
+    pub fn without_color(self) -> Self {
+        Theme {
+            base: strip_color(self.base),
+            background: strip_color(self.background),
+            cursor: Style::default().add_modifier(Modifier::REVERSED),
+            null: strip_color(self.null),
+            bool: strip_color(self.bool),
+            number: strip_color(self.number),
+            string: strip_color(self.string),
+            added: Style::default().add_modifier(Modifier::UNDERLINED),
+            removed: Style::default().add_modifier(Modifier::CROSSED_OUT),
+            error: Style::default().add_modifier(Modifier::REVERSED),
+            use_color: false,
+        }
+    }
+    // Swaps in a different cursor highlight. Without color, a highlight that was only a color
+    // would be invisible, so the reverse video cursor is kept instead.
+    pub fn with_cursor(self, cursor: CursorStyle) -> Self {
+        let cursor = self.colored(cursor.0);
+        if cursor == Style::default() {
+            return self;
+        }
+        Theme { cursor, ..self }
+    }
+    pub fn colored(&self, style: Style) -> Style {
+        if self.use_color {
+            style
+        } else {
+            strip_color(style)
+        }
+    }
+}
+
+fn strip_color(style: Style) -> Style {
+    Style {
+        fg: None,
+        bg: None,
+        ..style
+    }
+}
+
+// Whether to draw in color: never, always, or unless the NO_COLOR environment variable is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // See https://no-color.org: set to anything but the empty string means no color
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownColorChoiceError(String);
+
+impl fmt::Display for UnknownColorChoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown color choice \"{}\" (expected auto, always or never)",
+            self.0
+        )
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = UnknownColorChoiceError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(UnknownColorChoiceError(s.to_owned())),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::plain()
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownThemeError(String);
+
+impl fmt::Display for UnknownThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown theme \"{}\" (expected plain, dark or light)",
+            self.0
+        )
+    }
+}
+
+impl FromStr for Theme {
+    type Err = UnknownThemeError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Theme::plain()),
+            "dark" => Ok(Theme::dark()),
+            "light" => Ok(Theme::light()),
+            _ => Err(UnknownThemeError(s.to_owned())),
+        }
+    }
+}
+
+// How to highlight the value under the cursor, like "blue", "yellow,bold" or "reverse": a
+// comma separated list of a background color and modifiers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CursorStyle(pub Style);
+
+#[derive(Debug)]
+pub struct UnknownCursorStyleError(String);
+
+impl fmt::Display for UnknownCursorStyleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown cursor style \"{}\" (expected a color like blue or #3a3a3a, bold or reverse)",
+            self.0
+        )
+    }
+}
+
+impl FromStr for CursorStyle {
+    type Err = UnknownCursorStyleError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        for part in s.split(',').map(str::trim) {
+            style = match part {
+                "bold" => style.add_modifier(Modifier::BOLD),
+                "reverse" => style.add_modifier(Modifier::REVERSED),
+                "underline" => style.add_modifier(Modifier::UNDERLINED),
+                _ => match parse_color(part) {
+                    Some(color) => style.bg(color),
+                    None => return Err(UnknownCursorStyleError(part.to_owned())),
+                },
+            };
+        }
+        Ok(CursorStyle(style))
+    }
+}
+
+// The named terminal colors, or a hex code like #3a3a3a
+fn parse_color(s: &str) -> Option<Color> {
+    let color = match s {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "darkgray" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => {
+            let hex = s.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+            Color::Rgb(channel(0)?, channel(2)?, channel(4)?)
+        }
+    };
+    Some(color)
+}
+
+// The CSS for style, for exporting to HTML
+pub fn css(style: Style) -> String {
+    let mut css = String::new();
+    if let Some(fg) = style.fg.and_then(css_color) {
+        css.push_str(&format!("color:{};", fg));
+    }
+    if let Some(bg) = style.bg.and_then(css_color) {
+        css.push_str(&format!("background-color:{};", bg));
+    }
+    if style.add_modifier.contains(Modifier::BOLD) {
+        css.push_str("font-weight:bold;");
+    }
+    if style.add_modifier.contains(Modifier::DIM) {
+        css.push_str("opacity:0.6;");
+    }
+    if style.add_modifier.contains(Modifier::ITALIC) {
+        css.push_str("font-style:italic;");
+    }
+    if style.add_modifier.contains(Modifier::UNDERLINED) {
+        css.push_str("text-decoration:underline;");
+    }
+    css
+}
+
+// Uses the xterm colors for the named ones
+fn css_color(color: Color) -> Option<String> {
+    let hex = match color {
+        Color::Reset => return None,
+        Color::Black => "#000000",
+        Color::Red => "#cd0000",
+        Color::Green => "#00cd00",
+        Color::Yellow => "#cdcd00",
+        Color::Blue => "#0000ee",
+        Color::Magenta => "#cd00cd",
+        Color::Cyan => "#00cdcd",
+        Color::Gray => "#e5e5e5",
+        Color::DarkGray => "#7f7f7f",
+        Color::LightRed => "#ff0000",
+        Color::LightGreen => "#00ff00",
+        Color::LightYellow => "#ffff00",
+        Color::LightBlue => "#5c5cff",
+        Color::LightMagenta => "#ff00ff",
+        Color::LightCyan => "#00ffff",
+        Color::White => "#ffffff",
+        Color::Rgb(r, g, b) => return Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        // Not worth carrying the whole 256 color palette around for
+        Color::Indexed(_) => return None,
+    };
+    Some(hex.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn unit_parse_cursor_style() {
+        let style: CursorStyle = "yellow,bold".parse().unwrap();
+        assert_eq!(
+            style.0,
+            Style::default()
+                .bg(Color::Yellow)
+                .add_modifier(Modifier::BOLD)
+        );
+        let style: CursorStyle = "#3a3a3a".parse().unwrap();
+        assert_eq!(style.0, Style::default().bg(Color::Rgb(0x3a, 0x3a, 0x3a)));
+        assert!("#3a3a".parse::<CursorStyle>().is_err());
+        assert!("purple".parse::<CursorStyle>().is_err());
+    }
+    #[test]
+    fn unit_with_cursor() {
+        let reverse = CursorStyle(Style::default().add_modifier(Modifier::REVERSED));
+        assert_eq!(Theme::plain().with_cursor(reverse).cursor, reverse.0);
+        // A color alone would vanish without color, so the reverse video cursor stays
+        let yellow = CursorStyle(Style::default().bg(Color::Yellow));
+        let theme = Theme::plain().without_color();
+        assert_eq!(theme.clone().with_cursor(yellow).cursor, theme.cursor);
+    }
+}
@@ -1,18 +1,42 @@
 use crate::{
-    cursor::{FocusPosition, GlobalCursor, GlobalPath, LeafCursor, ValuePath},
+    cursor::{
+        descendant_count, inline_width, json_line_count, path_after_deletion,
+        path_after_key_reorder, path_after_reorder, sort_keys, CursorFrame, FocusPosition, Fold,
+        Folds, GlobalCursor, GlobalPath, Highlights, LeafCursor, SearchScope, ValuePath,
+    },
     jq::{
-        jv::JV,
-        query::{run_jq_query, JQ},
+        jv::{CountedJV, JV},
+        query::{error_location, run_jq_query, JqError, JQ},
     },
     layout::JexLayout,
-    lines::LineCursor,
+    lines::{DisplayOptions, LineCursor},
+    theme::{css, Theme},
 };
 use log::trace;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Deserialize;
 use serde_json::Deserializer;
-use std::{cmp::Ordering, collections::HashSet, io, io::Write, ops::RangeInclusive, rc::Rc};
+use std::{
+    cell::Cell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    io::{BufRead, Write},
+    ops::{Range, RangeInclusive},
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{self, AtomicU64},
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
 use tui::{
     layout::{Alignment, Rect},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
     widgets::{Block, Borders, Paragraph},
 };
@@ -76,10 +100,27 @@ impl ViewForest {
         let tree = self.trees.get_mut(ix.tree)?;
         tree.index_mut(&ix.within_tree)
     }
+    // Removes the view at ix along with everything below it. Returns None (and does nothing) if that
+    // would leave no trees at all.
+    pub fn remove(&mut self, ix: &ViewForestIndex) -> Option<ViewTree> {
+        match ix.within_tree.path.split_last() {
+            None => {
+                if self.trees.len() == 1 {
+                    return None;
+                }
+                Some(self.trees.remove(ix.tree))
+            }
+            Some((&child_ix, parent_path)) => {
+                let parent = self.trees.get_mut(ix.tree)?.index_tree_mut(parent_path)?;
+                Some(parent.children.remove(child_ix).1)
+            }
+        }
+    }
     pub fn render_tree(
         &self,
         left_index: &ViewForestIndex,
         right_index: &ViewForestIndex,
+        theme: &Theme,
     ) -> Paragraph {
         trace!(
             "Rendering tree:\nleft:{:?}\nright:{:?}\ntrees:{:#?}",
@@ -105,10 +146,12 @@ impl ViewForest {
                 i == self.trees.len() - 1,
                 left_tree_index,
                 right_tree_index,
+                theme,
                 &mut spans,
             )
         }
-        Paragraph::new(spans).style(Style::default().fg(Color::White).bg(Color::Black))
+        Paragraph::new(spans)
+            .style(theme.colored(Style::default().fg(Color::White).bg(Color::Black)))
     }
 }
 
@@ -117,6 +160,12 @@ pub struct ViewTree {
     pub view_frame: NamedView,
     // (query, tree)
     pub children: Vec<(String, ViewTree)>,
+    // The file the root view was loaded from (or last saved to as json), for reloading it
+    pub source: Option<String>,
+    // Values that weren't valid json, and were left out, and how many integers were too big to
+    // load exactly. The app takes these to warn about them.
+    pub skipped_values: Vec<String>,
+    pub lossy_integers: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -125,19 +174,474 @@ pub struct NamedView {
     pub name: String,
 }
 
+impl NamedView {
+    // Swaps in a recomputed view, carrying over what state still makes sense.
+    pub fn replace_view(&mut self, mut view: View) {
+        if let (View::Json(Some(old)), View::Json(Some(new))) = (&mut self.view, &mut view) {
+            new.inherit_from(old);
+        }
+        self.view = view;
+    }
+}
+
+// JVs aren't Send, since their refcounts aren't atomic. A freshly deserialized value doesn't share
+// anything with any other value though, so it's fine to hand it off to another thread.
+struct SendJV(CountedJV);
+unsafe impl Send for SendJV {}
+
+impl<'de> Deserialize<'de> for SendJV {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        CountedJV::deserialize(deserializer).map(SendJV)
+    }
+}
+
+// Values read from a file, and what went wrong reading them
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub values: Vec<JV>,
+    // Descriptions of the values that weren't valid json, and were left out
+    pub skipped: Vec<String>,
+    // How many integers were too big to load exactly, and were rounded
+    pub lossy_integers: usize,
+}
+
+impl Loaded {
+    fn push(&mut self, SendJV(counted): SendJV) {
+        self.values.push(counted.value);
+        self.lossy_integers += counted.lossy_integers;
+    }
+}
+
+// What read_stream finds next: a value, or a description of one that wasn't valid json and was
+// left out.
+enum StreamItem {
+    Value(SendJV),
+    Skipped(String),
+}
+
+// The rest of a file that's still being parsed in the background.
+pub struct PendingLoad {
+    receiver: Receiver<serde_json::Result<StreamItem>>,
+    bytes_read: Arc<AtomicU64>,
+    // Only known when loading from a file
+    total_bytes: Option<u64>,
+}
+
+// How much of a PendingLoad's input the parser has read so far
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub bytes_read: u64,
+    pub total_bytes: Option<u64>,
+}
+
+impl fmt::Display for LoadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+        write!(f, "{:.1} MB", megabytes(self.bytes_read))?;
+        if let Some(total) = self.total_bytes {
+            write!(f, " of {:.1} MB", megabytes(total))?;
+        }
+        Ok(())
+    }
+}
+
+// Counts the bytes the background parser has read, for showing how far along it is.
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.fetch_add(read as u64, atomic::Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl PendingLoad {
+    pub fn progress(&self) -> LoadProgress {
+        LoadProgress {
+            bytes_read: self.bytes_read.load(atomic::Ordering::Relaxed),
+            total_bytes: self.total_bytes,
+        }
+    }
+    // Returns what's been parsed since the last poll, waiting up to timeout for the first value.
+    // The second element is None if there are more values to come.
+    pub fn poll(&self, timeout: Duration) -> (Loaded, Option<io::Result<()>>) {
+        let mut loaded = Loaded::default();
+        let mut received = self.receiver.recv_timeout(timeout);
+        loop {
+            match received {
+                Ok(Ok(StreamItem::Value(value))) => loaded.push(value),
+                Ok(Ok(StreamItem::Skipped(message))) => loaded.skipped.push(message),
+                Ok(Err(err)) => return (loaded, Some(Err(err.into()))),
+                Err(RecvTimeoutError::Timeout) => return (loaded, None),
+                Err(RecvTimeoutError::Disconnected) => return (loaded, Some(Ok(()))),
+            }
+            received = self.receiver.try_recv().map_err(|err| match err {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            });
+        }
+    }
+}
+
+// Empty containers are drawn on one line already, so there's nothing to fold
+fn is_foldable(json: &JV) -> bool {
+    match json {
+        JV::Array(arr) => !arr.is_empty(),
+        JV::Object(obj) => !obj.is_empty(),
+        _ => false,
+    }
+}
+
+pub fn is_ndjson(path: &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|ext| ext.to_str());
+    matches!(extension, Some("jsonl") | Some("ndjson"))
+}
+
+// Reads every value in a file at once, unlike ViewTree::new_from_file. Values that aren't valid
+// json are left out the same way.
+pub fn read_values(path: &str) -> io::Result<Loaded> {
+    if is_ndjson(path) {
+        let s = fs::read_to_string(path)?;
+        if let Some(loaded) = parse_ndjson_lossy(&s) {
+            return Ok(loaded);
+        }
+        return read_all_values(io::Cursor::new(s));
+    }
+    read_all_values(io::BufReader::new(fs::File::open(path)?))
+}
+
+fn read_all_values<R: BufRead>(r: R) -> io::Result<Loaded> {
+    let mut loaded = Loaded::default();
+    let mut result = Ok(());
+    read_stream(r, |item| match item {
+        Ok(StreamItem::Value(value)) => {
+            loaded.push(value);
+            true
+        }
+        Ok(StreamItem::Skipped(message)) => {
+            loaded.skipped.push(message);
+            true
+        }
+        Err(err) => {
+            result = Err(err);
+            false
+        }
+    });
+    result?;
+    Ok(loaded)
+}
+
+// Writes values one after another, separated by newlines, serializing each straight into w. Compact
+// values end up one per line, so the output can be read back as ndjson.
+pub fn write_values<W: Write>(values: &[JV], w: &mut W, pretty: bool) -> io::Result<()> {
+    for (i, v) in values.iter().enumerate() {
+        if i != 0 {
+            writeln!(w)?;
+        }
+        if pretty {
+            serde_json::to_writer_pretty(&mut *w, v)?;
+        } else {
+            serde_json::to_writer(&mut *w, v)?;
+        }
+    }
+    Ok(())
+}
+
+// How well query matches candidate as a subsequence, fzf style: higher is better, and None if it
+// doesn't match at all. Case is ignored. Runs of consecutive characters and matches at the start
+// of a word count for more, and every character skipped counts against it.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+    for q in query.chars() {
+        let skipped = candidate[position..]
+            .iter()
+            .position(|c| c.to_lowercase().eq(q.to_lowercase()))?;
+        let i = position + skipped;
+        score += 1 - skipped as i64;
+        if previous_match.is_some_and(|previous| previous + 1 == i) {
+            score += 5;
+        }
+        let word_start = i == 0
+            || !candidate[i - 1].is_alphanumeric()
+            || (candidate[i - 1].is_lowercase() && candidate[i].is_uppercase());
+        if word_start {
+            score += 3;
+        }
+        previous_match = Some(i);
+        position = i + 1;
+    }
+    Some(score)
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Lines parsed per rayon task: enough to amortize the overhead of handing the chunk off.
+const NDJSON_CHUNK_LINES: usize = 256;
+
+// Parses a file with one value per line, splitting the lines across threads.
+pub fn parse_ndjson(s: &str) -> serde_json::Result<Loaded> {
+    let lines: Vec<&str> = s.lines().filter(|line| !line.trim().is_empty()).collect();
+    let chunks = lines
+        .par_chunks(NDJSON_CHUNK_LINES)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|line| serde_json::from_str::<SendJV>(line))
+                .collect::<serde_json::Result<Vec<SendJV>>>()
+        })
+        .collect::<serde_json::Result<Vec<Vec<SendJV>>>>()?;
+    let mut loaded = Loaded::default();
+    for value in chunks.into_iter().flatten() {
+        loaded.push(value);
+    }
+    Ok(loaded)
+}
+
+// Reads values one after another, handing each to emit until it returns false. Once some values
+// have parsed, a malformed one is skipped along with the rest of its line, so one corrupt record
+// in a json lines file doesn't lose the others. It still gives up if more values fail than parse:
+// then it's probably not json at all.
+fn read_stream<R: BufRead>(mut r: R, mut emit: impl FnMut(serde_json::Result<StreamItem>) -> bool) {
+    let mut parsed = 0;
+    let mut skipped = 0;
+    // Each Deserializer counts lines from where it started
+    let mut line_offset = 0;
+    loop {
+        let mut stream = Deserializer::from_reader(&mut r).into_iter::<SendJV>();
+        let err = loop {
+            match stream.next() {
+                Some(Ok(value)) => {
+                    parsed += 1;
+                    if !emit(Ok(StreamItem::Value(value))) {
+                        return;
+                    }
+                }
+                Some(Err(err)) => break err,
+                None => return,
+            }
+        };
+        if err.is_io() || skipped >= parsed {
+            emit(Err(err));
+            return;
+        }
+        skipped += 1;
+        let message = skipped_value_message(line_offset + err.line(), &err);
+        if !emit(Ok(StreamItem::Skipped(message))) {
+            return;
+        }
+        // Column 0 means it gave up right after a newline, so the next line is still intact
+        if err.column() == 0 {
+            line_offset += err.line() - 1;
+        } else {
+            line_offset += err.line();
+            if let Err(err) = r.read_until(b'\n', &mut Vec::new()) {
+                emit(Err(serde_json::Error::io(err)));
+                return;
+            }
+        }
+    }
+}
+
+// Describes a value that was left out. serde_json's message ends with where it gave up, but
+// counting from wherever that parse started, so the line is swapped for one in the whole file.
+fn skipped_value_message(line: usize, err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    let message = message
+        .rsplit_once(" at line ")
+        .map_or(message.as_str(), |(message, _)| message);
+    format!("line {}, column {}: {}", line, err.column(), message)
+}
+
+// Why a file isn't valid json, for showing in a View::Error. serde_json's message ends with the
+// line and column it gave up at.
+fn parse_error_messages(name: &str, err: &serde_json::Error) -> Vec<String> {
+    vec![format!("Couldn't parse {}:", name), err.to_string()]
+}
+
+// Like parse_ndjson, but leaves out the lines that aren't valid json instead of giving up, returning
+// a description of each. Returns None if more lines fail than parse: then it's probably not one
+// value per line at all.
+pub fn parse_ndjson_lossy(s: &str) -> Option<Loaded> {
+    let lines: Vec<(usize, &str)> = s
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let chunks = lines
+        .par_chunks(NDJSON_CHUNK_LINES)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|&(i, line)| (i, serde_json::from_str::<SendJV>(line)))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut loaded = Loaded::default();
+    for (i, result) in chunks.into_iter().flatten() {
+        match result {
+            Ok(value) => loaded.push(value),
+            Err(err) => loaded.skipped.push(skipped_value_message(i + 1, &err)),
+        }
+    }
+    if loaded.skipped.len() > loaded.values.len() {
+        return None;
+    }
+    Some(loaded)
+}
+
 impl ViewTree {
-    pub fn new_from_reader<R: io::Read>(r: R, name: String, layout: JexLayout) -> io::Result<Self> {
-        let content: Vec<JV> = Deserializer::from_reader(r)
-            .into_iter::<JV>()
-            .collect::<Result<Vec<JV>, _>>()?;
-        let view = View::new(content, layout.left);
+    // Only parses enough values to fill the first screen: the rest are returned as a PendingLoad.
+    pub fn new_from_reader<R: io::Read + Send + 'static>(
+        r: R,
+        name: String,
+        layout: JexLayout,
+    ) -> io::Result<(Self, Option<PendingLoad>)> {
+        let (sender, receiver) = mpsc::channel();
+        let bytes_read = Arc::new(AtomicU64::new(0));
+        // serde_json reads a byte at a time, so buffer above the counter to keep it cheap
+        let r = io::BufReader::new(CountingReader {
+            inner: r,
+            count: bytes_read.clone(),
+        });
+        thread::spawn(move || read_stream(r, |item| sender.send(item).is_ok()));
+        // Every value takes up at least one line
+        let mut loaded = Loaded::default();
+        let mut done = false;
+        while loaded.values.len() < layout.left.height.max(1) as usize {
+            match receiver.recv() {
+                Ok(Ok(StreamItem::Value(value))) => loaded.push(value),
+                Ok(Ok(StreamItem::Skipped(message))) => loaded.skipped.push(message),
+                // Failing to read at all is still an io error, but malformed json is shown in
+                // place of the values, pointing at where it went wrong.
+                Ok(Err(err)) if err.is_io() => return Err(err.into()),
+                Ok(Err(err)) => {
+                    let view = View::Error(ViewError::Load(parse_error_messages(&name, &err)));
+                    return Ok((ViewTree::new_from_view(view, name, layout), None));
+                }
+                Err(_) => {
+                    done = true;
+                    break;
+                }
+            }
+        }
+        let mut tree = ViewTree::new_from_loaded(loaded, name, layout);
+        if let View::Json(Some(json_view)) = &mut tree.view_frame.view {
+            json_view.has_pending_load = !done;
+        }
+        let pending = if done {
+            None
+        } else {
+            Some(PendingLoad {
+                receiver,
+                bytes_read,
+                total_bytes: None,
+            })
+        };
+        Ok((tree, pending))
+    }
+    // Newline delimited json files are parsed in parallel, everything else is streamed.
+    pub fn new_from_file(
+        path: String,
+        layout: JexLayout,
+    ) -> io::Result<(Self, Option<PendingLoad>)> {
+        let source = Some(path.clone());
+        let total_bytes = fs::metadata(&path)?.len();
+        let (mut tree, mut pending) = if !is_ndjson(&path) {
+            let r = io::BufReader::new(fs::File::open(&path)?);
+            ViewTree::new_from_reader(r, path, layout)?
+        } else {
+            let s = fs::read_to_string(&path)?;
+            match parse_ndjson(&s) {
+                Ok(loaded) => (ViewTree::new_from_loaded(loaded, path, layout), None),
+                // Lines are parsed on their own here, so a corrupt one can't take the next with it
+                Err(_) => match parse_ndjson_lossy(&s) {
+                    Some(loaded) => (ViewTree::new_from_loaded(loaded, path, layout), None),
+                    // Not actually one value per line (maybe it's pretty printed), so stream it
+                    None => ViewTree::new_from_reader(io::Cursor::new(s), path, layout)?,
+                },
+            }
+        };
+        tree.source = source;
+        if let Some(pending) = &mut pending {
+            pending.total_bytes = Some(total_bytes);
+        }
+        Ok((tree, pending))
+    }
+    // Reads the source file again, swapping in its new values while keeping the cursor and folds
+    // where they make sense, and rerunning all the queries below it.
+    pub fn reload(&mut self, layout: JexLayout, result_limit: usize) {
+        let path = match &self.source {
+            Some(path) => path,
+            None => return,
+        };
+        let view = match read_values(path) {
+            Ok(loaded) => {
+                self.skipped_values = loaded.skipped;
+                self.lossy_integers = loaded.lossy_integers;
+                View::new(loaded.values, layout.left)
+            }
+            Err(err) => View::Error(ViewError::Load(vec![
+                format!("Couldn't reload {}:", path),
+                err.to_string(),
+            ])),
+        };
+        self.view_frame.replace_view(view);
+        self.recompute_children(layout.right, result_limit);
+    }
+    pub fn new_from_values(content: Vec<JV>, name: String, layout: JexLayout) -> Self {
+        ViewTree::new_from_view(View::new(content, layout.left), name, layout)
+    }
+    fn new_from_loaded(loaded: Loaded, name: String, layout: JexLayout) -> Self {
+        let mut tree = ViewTree::new_from_values(loaded.values, name, layout);
+        tree.skipped_values = loaded.skipped;
+        tree.lossy_integers = loaded.lossy_integers;
+        tree
+    }
+    fn new_from_view(view: View, name: String, layout: JexLayout) -> Self {
         let view_frame = NamedView { view, name };
         let mut tree = ViewTree {
             view_frame,
             children: Vec::new(),
+            source: None,
+            skipped_values: Vec::new(),
+            lossy_integers: 0,
         };
         tree.push_trivial_child(layout.right);
-        Ok(tree)
+        tree
+    }
+    pub fn recompute_children(&mut self, target_view_rect: Rect, result_limit: usize) {
+        for (query, child) in self.children.iter_mut() {
+            let new_view = match &self.view_frame.view {
+                View::Json(Some(view)) => view.apply_query(query, target_view_rect, result_limit),
+                View::Json(None) | View::Error(_) => View::Json(None),
+            };
+            child.view_frame.replace_view(new_view);
+            child.recompute_children(target_view_rect, result_limit);
+        }
+    }
+    // Changes how characters and values are drawn in this view and everything below it
+    pub fn set_display(&mut self, display: DisplayOptions) {
+        if let View::Json(Some(view)) = &mut self.view_frame.view {
+            view.set_display(display);
+        }
+        for (_, child) in self.children.iter_mut() {
+            child.set_display(display);
+        }
     }
     pub fn push_trivial_child(&mut self, target_view_rect: Rect) {
         if let View::Json(Some(view)) = &self.view_frame.view {
@@ -146,10 +650,14 @@ impl ViewTree {
                 view: View::new(view.values.clone(), target_view_rect),
                 name,
             };
-            let child = ViewTree {
+            let mut child = ViewTree {
                 view_frame,
                 children: Vec::new(),
+                source: None,
+                skipped_values: Vec::new(),
+                lossy_integers: 0,
             };
+            child.set_display(view.display);
             self.children.push((".".to_string(), child));
         }
     }
@@ -214,6 +722,7 @@ fn render_tree_inner<'a, 'b>(
     end: bool,
     left_index: Option<BorrowedViewTreeIndex>,
     right_index: Option<BorrowedViewTreeIndex>,
+    theme: &Theme,
     out: &mut Vec<Spans<'a>>,
 ) {
     let is_left = left_index.map_or(false, |index| index.parent.is_empty());
@@ -230,7 +739,7 @@ fn render_tree_inner<'a, 'b>(
         vec![
             prefix.to_owned().into(),
             mid.into(),
-            render_tree_entry(&tree.view_frame.name, is_left, is_right),
+            render_tree_entry(&tree.view_frame.name, is_left, is_right, theme),
         ]
         .into(),
     );
@@ -239,17 +748,24 @@ fn render_tree_inner<'a, 'b>(
         let end = i == tree.children.len() - 1;
         let left_index = left_index.and_then(|index| index.descend(i));
         let right_index = right_index.and_then(|index| index.descend(i));
-        render_tree_inner(child, &new_prefix, end, left_index, right_index, out);
+        render_tree_inner(child, &new_prefix, end, left_index, right_index, theme, out);
     }
 }
 
-fn render_tree_entry(name: &str, is_parent: bool, is_child: bool) -> Span {
-    match (is_parent, is_child) {
-        (false, false) => Span::raw(name),
-        (true, false) => Span::styled(format!("(L) {}", name), Style::default().fg(Color::Blue)),
-        (false, true) => Span::styled(format!("(R) {}", name), Style::default().fg(Color::Yellow)),
-        (true, true) => Span::styled(format!("(LR) {}", name), Style::default().fg(Color::Green)),
-    }
+fn render_tree_entry<'a>(
+    name: &'a str,
+    is_parent: bool,
+    is_child: bool,
+    theme: &Theme,
+) -> Span<'a> {
+    let (label, color) = match (is_parent, is_child) {
+        (false, false) => return Span::raw(name),
+        (true, false) => ("L", Color::Blue),
+        (false, true) => ("R", Color::Yellow),
+        (true, true) => ("LR", Color::Green),
+    };
+    let style = theme.colored(Style::default().fg(color));
+    Span::styled(format!("({}) {}", label, name), style)
 }
 
 #[derive(Debug, Clone)]
@@ -281,6 +797,35 @@ impl ViewForestIndex {
         self.within_tree = ViewTreeIndex::new_at_end(&forrest.trees[self.tree]);
         Some(())
     }
+    // Updates self to be consistent with the removal of the view at removed from forrest. If self
+    // was removed too, it moves to the removed view's parent, or to the tree that took its place.
+    pub fn update_after_removal(&mut self, removed: &Self, forrest: &ViewForest) {
+        if removed.within_tree.path.is_empty() {
+            match self.tree.cmp(&removed.tree) {
+                Ordering::Less => {}
+                Ordering::Equal => {
+                    self.tree = std::cmp::min(self.tree, forrest.trees.len() - 1);
+                    self.within_tree = ViewTreeIndex { path: Vec::new() };
+                }
+                Ordering::Greater => self.tree -= 1,
+            }
+            return;
+        }
+        if self.tree != removed.tree {
+            return;
+        }
+        let (&removed_ix, parent_path) = removed.within_tree.path.split_last().unwrap();
+        let path = &mut self.within_tree.path;
+        if path.len() <= parent_path.len() || path[..parent_path.len()] != *parent_path {
+            return;
+        }
+        let ix = &mut path[parent_path.len()];
+        match (*ix).cmp(&removed_ix) {
+            Ordering::Less => {}
+            Ordering::Equal => path.truncate(parent_path.len()),
+            Ordering::Greater => *ix -= 1,
+        }
+    }
     // updates self to be consistent with a move of origin to destination.
     pub fn update_after_move(&mut self, origin: &Self, destination: &Self) {
         if origin.tree != self.tree {
@@ -403,7 +948,23 @@ impl<'a> BorrowedViewTreeIndex<'a> {
 #[derive(Debug, Clone)]
 pub enum View {
     Json(Option<JsonView>),
-    Error(Vec<String>),
+    Error(ViewError),
+}
+
+#[derive(Debug, Clone)]
+pub enum ViewError {
+    Query(JqError),
+    // Messages for a file that couldn't be read or parsed
+    Load(Vec<String>),
+}
+
+impl ViewError {
+    pub fn messages(&self) -> Vec<String> {
+        match self {
+            ViewError::Query(err) => err.messages(),
+            ViewError::Load(messages) => messages.clone(),
+        }
+    }
 }
 
 impl View {
@@ -411,133 +972,886 @@ impl View {
         let json_rect = Block::default().borders(Borders::ALL).inner(view_rect);
         View::Json(JsonView::new(values, json_rect))
     }
-    pub fn render(&self, rect: Rect, has_focus: bool) -> Paragraph {
+    pub fn render(
+        &self,
+        rect: Rect,
+        has_focus: bool,
+        line_numbers: bool,
+        highlights: Highlights,
+        theme: &Theme,
+    ) -> Paragraph {
         match self {
-            View::Json(Some(json_view)) => json_view.render(rect, has_focus),
+            View::Json(Some(json_view)) => {
+                json_view.render(rect, has_focus, line_numbers, highlights, theme)
+            }
             View::Json(None) => Paragraph::new(Vec::new()),
             View::Error(err) => {
                 let err_text = err
+                    .messages()
                     .iter()
                     .flat_map(|e| e.split('\n'))
-                    .map(Spans::from)
+                    .map(|line| error_line(line.to_owned()))
                     .collect::<Vec<_>>();
                 Paragraph::new(err_text)
-                    .style(Style::default().fg(Color::White).bg(Color::Red))
+                    .style(theme.error)
                     .alignment(Alignment::Left)
             }
         }
     }
-    pub fn resize_to(&mut self, view_rect: Rect) {
+    pub fn resize_to(&mut self, view_rect: Rect, line_numbers: bool) {
         match self {
             View::Json(Some(v)) => {
                 let json_rect = Block::default().borders(Borders::ALL).inner(view_rect);
-                v.resize_to(json_rect);
+                v.resize_to(v.text_rect(json_rect, line_numbers));
             }
             _ => {}
         }
     }
 }
 
+// Picks out where the error is, if the message says
+fn error_line(line: String) -> Spans<'static> {
+    match error_location(&line) {
+        Some(location) => Spans::from(vec![
+            Span::raw(line[..location.start].to_owned()),
+            Span::styled(
+                line[location.clone()].to_owned(),
+                Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            ),
+            Span::raw(line[location.end..].to_owned()),
+        ]),
+        None => Spans::from(line),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FoldError {
+    Leaf,
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldError::Leaf => write!(f, "Cannot fold a scalar value"),
+        }
+    }
+}
+
+// Edits remembered for undo. Old values mostly share their subtrees with newer ones, so this is
+// more about bounding the bookkeeping than the memory.
+const UNDO_DEPTH: usize = 100;
+
+// What an edit changed, to go back to
+#[derive(Debug, Clone)]
+struct Snapshot {
+    values: Rc<[JV]>,
+    cursor: ValuePath,
+    folds: Folds,
+    sort_keys: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct JsonView {
     pub scroll: GlobalCursor,
     pub values: Rc<[JV]>,
     pub cursor: LeafCursor,
-    pub folds: HashSet<(usize, Vec<usize>)>,
+    pub folds: Folds,
+    // Whether folds show their total number of descendants instead of their number of children
+    pub show_descendants: bool,
+    // When set, containers that fit in this many columns are drawn on a single line
+    pub inline_width: Option<usize>,
+    // How characters and values are drawn. The app passes it down with set_display.
+    pub display: DisplayOptions,
+    // When false, lines run off the right edge and are scrolled horizontally instead of wrapping
+    pub wrap: bool,
+    // Columns hidden off the left edge when not wrapping
+    pub h_scroll: u16,
+    // Whether object keys are sorted, like jq -S
+    pub sort_keys: bool,
+    // The values as they were before their keys were sorted, to go back to. Dropped on edits, since
+    // those can't be carried back over to the original order.
+    unsorted_values: Option<Rc<[JV]>>,
+    // The folds from before grep mode, put back when it's turned off
+    grep_stash: Option<Folds>,
+    undo_stack: Vec<Snapshot>,
+    redo_stack: Vec<Snapshot>,
     pub rect: Rect,
+    // Whether more values are still being parsed in the background
+    pub has_pending_load: bool,
+    // Whether the query these values came from had more results than the result limit. Changing
+    // the limit reruns the queries, so it's always the current one.
+    pub truncated: bool,
+    // Lines in the unfolded document, computed when line numbers are first shown
+    line_count: Cell<Option<usize>>,
+    pub bookmarks: HashMap<char, ValuePath>,
 }
 
 impl JsonView {
     pub fn new<V: Into<Rc<[JV]>>>(values: V, rect: Rect) -> Option<Self> {
         let values: Rc<[JV]> = values.into();
         let cursor = LeafCursor::new(values.clone())?;
-        let folds = HashSet::new();
-        let scroll = GlobalCursor::new(values.clone(), rect.width, &folds)?;
+        let folds = Folds::new();
+        let display = DisplayOptions::default();
+        let scroll = GlobalCursor::new(values.clone(), rect.width, &folds, display)?;
         Some(JsonView {
             scroll,
             values,
             cursor,
             folds,
+            show_descendants: false,
+            inline_width: None,
+            display,
+            wrap: true,
+            h_scroll: 0,
+            sort_keys: false,
+            unsorted_values: None,
+            grep_stash: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
             rect,
+            has_pending_load: false,
+            truncated: false,
+            line_count: Cell::new(None),
+            bookmarks: HashMap::new(),
         })
     }
-    fn render(&self, rect: Rect, has_focus: bool) -> Paragraph {
+    // Takes the folds, cursor, scroll and bookmarks of the view this one replaced, as far as they
+    // still make sense for the new values. Anything whose path no longer exists is dropped.
+    pub fn inherit_from(&mut self, old: &mut JsonView) {
+        if old.sort_keys {
+            self.toggle_sort_keys();
+        }
+        self.inherit_bookmarks(std::mem::take(&mut old.bookmarks));
+        self.show_descendants = old.show_descendants;
+        // Before inline_width, so the inlines are only worked out once, below
+        self.set_display(old.display);
+        self.inline_width = old.inline_width;
+        self.wrap = old.wrap;
+        self.h_scroll = old.h_scroll;
+        for ((top_index, frames), fold) in old.folds.iter() {
+            let json = match LeafCursor::at_indices(self.values.clone(), *top_index, frames.clone())
+            {
+                Some(cursor) => cursor.focus,
+                None => continue,
+            };
+            let fold = match fold {
+                Fold::Inline => match self.inline_width {
+                    Some(limit) if inline_width(&json, limit, self.display).is_some() => {
+                        Fold::Inline
+                    }
+                    _ => continue,
+                },
+                _ => {
+                    if !is_foldable(&json) {
+                        continue;
+                    }
+                    self.fold_info(&json)
+                }
+            };
+            self.folds.insert((*top_index, frames.clone()), fold);
+        }
+        self.inline_containers(0..self.values.len());
+        if let Some(cursor) = LeafCursor::try_from_path(self.values.clone(), &old.cursor.to_path())
+        {
+            self.cursor = cursor;
+        }
+        let old_scroll =
+            LeafCursor::try_from_path(self.values.clone(), &old.scroll.value_cursor.to_path());
+        if let Some(scroll) = old_scroll {
+            self.scroll_to(scroll);
+        }
+        if self.cursor_is_folded() {
+            self.cursor.focus_position = FocusPosition::Start;
+        }
+        self.clamp_to_folds();
+        self.reveal_cursor();
+    }
+    // Puts back folds and a cursor saved in a session, skipping any that don't fit these values.
+    pub fn restore_state(&mut self, folds: Vec<(usize, Vec<usize>)>, cursor: Option<ValuePath>) {
+        for (top_index, frames) in folds {
+            let json = match LeafCursor::at_indices(self.values.clone(), top_index, frames.clone())
+            {
+                Some(cursor) => cursor.focus,
+                None => continue,
+            };
+            if is_foldable(&json) {
+                let fold = self.fold_info(&json);
+                self.folds.insert((top_index, frames), fold);
+            }
+        }
+        let cursor = cursor.and_then(|path| LeafCursor::try_from_path(self.values.clone(), &path));
+        if let Some(cursor) = cursor {
+            self.cursor = cursor;
+        }
+        if self.cursor_is_folded() {
+            self.cursor.focus_position = FocusPosition::Start;
+        }
+        self.clamp_to_folds();
+        self.reveal_cursor();
+    }
+    // Takes the bookmarks of the view this one replaced, dropping any that no longer exist.
+    pub fn inherit_bookmarks(&mut self, bookmarks: HashMap<char, ValuePath>) {
+        let values = &self.values;
+        self.bookmarks = bookmarks
+            .into_iter()
+            .filter(|(_, path)| LeafCursor::try_from_path(values.clone(), path).is_some())
+            .collect();
+    }
+    // (keys, values) matching re
+    pub fn count_matches(&self, re: &Regex) -> (usize, usize) {
+        LeafCursor::count_matches(self.values.clone(), re)
+    }
+    pub fn set_mark(&mut self, mark: char) {
+        self.bookmarks.insert(mark, self.cursor.to_path());
+    }
+    // The path to whichever key of the object around the cursor (or under it) best matches query,
+    // by fuzzy_score. The first key wins a tie.
+    pub fn fuzzy_find_key(&self, query: &str) -> Option<ValuePath> {
+        if query.is_empty() {
+            return None;
+        }
+        let container = self.cursor.enclosing_container()?;
+        let obj = match &container.focus {
+            JV::Object(obj) => obj,
+            _ => return None,
+        };
+        let mut best: Option<(i64, usize)> = None;
+        for (i, (key, _)) in obj.clone().into_iter().enumerate() {
+            if let Some(score) = fuzzy_score(query, key.value()) {
+                if best.is_none_or(|(best_score, _)| score > best_score) {
+                    best = Some((score, i));
+                }
+            }
+        }
+        let (_, index) = best?;
+        let (top_index, mut frames) = container.to_path().strip_position();
+        frames.push(index);
+        LeafCursor::at_indices(self.values.clone(), top_index, frames)
+            .map(|cursor| cursor.to_path())
+    }
+    // Returns false if there's no such mark
+    pub fn jump_to_mark(&mut self, mark: char) -> bool {
+        let cursor = self
+            .bookmarks
+            .get(&mark)
+            .and_then(|path| LeafCursor::try_from_path(self.values.clone(), path));
+        match cursor {
+            Some(cursor) => {
+                self.jump_to(cursor);
+                true
+            }
+            None => false,
+        }
+    }
+    // A query for the json inside the string under the cursor, like `.log | fromjson`, or why
+    // there isn't one.
+    pub fn embedded_json_query(&self) -> Result<String, String> {
+        let s = match self.cursor.focused_value() {
+            JV::String(s) => s,
+            _ => return Err("Not on a string".to_owned()),
+        };
+        if let Err(err) = JV::parse_native(s.value()) {
+            return Err(format!("Not valid json: {}", err));
+        }
+        Ok(format!("{} | fromjson", self.cursor.to_jq_path()))
+    }
+    // Jumps to the object key named by the string or number under the cursor. Returns false if
+    // there's no such key in the current top level value.
+    pub fn follow_reference(&mut self) -> bool {
+        let path = self
+            .cursor
+            .reference_key()
+            .and_then(|key| self.cursor.find_key(&key));
+        match path {
+            Some(path) => {
+                self.jump_to(LeafCursor::from_path(self.values.clone(), &path));
+                true
+            }
+            None => false,
+        }
+    }
+    // Returns false if the pointer doesn't resolve in the current top level value
+    pub fn jump_to_pointer(&mut self, pointer: &str) -> bool {
+        match self.cursor.resolve_pointer(pointer) {
+            Some(path) => {
+                self.jump_to(LeafCursor::from_path(self.values.clone(), &path));
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn append_values(&mut self, new_values: Vec<JV>) {
+        let old_len = self.values.len();
+        let new_values = if self.sort_keys {
+            if let Some(unsorted) = &self.unsorted_values {
+                self.unsorted_values = Some(unsorted.iter().chain(&new_values).cloned().collect());
+            }
+            new_values.iter().map(sort_keys).collect()
+        } else {
+            new_values
+        };
+        let values: Rc<[JV]> = self.values.iter().cloned().chain(new_values).collect();
+        // Undoing would lose the new values
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.cursor = LeafCursor::from_path(values.clone(), &self.cursor.to_path());
+        self.scroll.value_cursor =
+            LeafCursor::from_path(values.clone(), &self.scroll.value_cursor.to_path());
+        self.values = values;
+        self.line_count.set(None);
+        self.inline_containers(old_len..self.values.len());
+    }
+    fn line_count(&self) -> usize {
+        if let Some(line_count) = self.line_count.get() {
+            return line_count;
+        }
+        let line_count = self.values.iter().map(json_line_count).sum();
+        self.line_count.set(Some(line_count));
+        line_count
+    }
+    // The width lines are wrapped at: effectively unbounded when wrapping is off. LineCursor
+    // tracks line lengths in bytes as u16s, so this leaves room for multibyte characters.
+    pub fn wrap_width(&self) -> u16 {
+        if self.wrap {
+            self.rect.width
+        } else {
+            u16::MAX / 4
+        }
+    }
+    // Changes how characters and values are drawn, rendering the top line again and working out
+    // where it breaks
+    pub fn set_display(&mut self, display: DisplayOptions) {
+        if display == self.display {
+            return;
+        }
+        self.display = display;
+        // Containers can get wider or narrower, so what fits on one line needs working out again
+        if self.inline_width.is_some() {
+            self.folds.retain(|_, fold| *fold != Fold::Inline);
+            self.inline_containers(0..self.values.len());
+            self.clamp_to_folds();
+        }
+        self.scroll.redraw(&self.folds, self.wrap_width(), display);
+        self.resize_to(self.rect);
+    }
+    pub fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.h_scroll = 0;
+        self.resize_to(self.rect);
+    }
+    // Width of the widest line currently on screen
+    fn longest_visible_line(&self) -> u16 {
+        let mut scroll = self.scroll.clone();
+        let mut longest = scroll.current_line().width();
+        for _ in 1..self.rect.height {
+            if scroll.advance(&self.folds, self.wrap_width()).is_none() {
+                break;
+            }
+            longest = std::cmp::max(longest, scroll.current_line().width());
+        }
+        longest
+    }
+    pub fn scroll_left(&mut self, columns: u16) {
+        self.h_scroll = self.h_scroll.saturating_sub(columns);
+    }
+    // Scrolls right, but not so far that the widest visible line leaves the screen.
+    pub fn scroll_right(&mut self, columns: u16) {
+        if self.wrap {
+            return;
+        }
+        let max_scroll = self.longest_visible_line().saturating_sub(self.rect.width);
+        self.h_scroll = std::cmp::min(self.h_scroll.saturating_add(columns), max_scroll);
+    }
+    // The part of json_rect left for the json itself, after making room for any line numbers.
+    pub fn text_rect(&self, json_rect: Rect, line_numbers: bool) -> Rect {
+        if !line_numbers {
+            return json_rect;
+        }
+        // Digits of the last line number, plus a space
+        let gutter_width = self.line_count().to_string().len() as u16 + 1;
+        // LineCursor needs at least 7 columns to work with
+        if json_rect.width < gutter_width + 7 {
+            return json_rect;
+        }
+        Rect {
+            x: json_rect.x + gutter_width,
+            width: json_rect.width - gutter_width,
+            ..json_rect
+        }
+    }
+    fn render(
+        &self,
+        rect: Rect,
+        has_focus: bool,
+        line_numbers: bool,
+        highlights: Highlights,
+        theme: &Theme,
+    ) -> Paragraph {
         trace!("Rendering started: target rect {:?}", rect);
         let JsonView { cursor, scroll, .. } = self;
         let cursor = if has_focus { Some(cursor) } else { None };
-        let text = scroll.clone().render_lines(cursor, &self.folds, rect);
+        let text_rect = self.text_rect(rect, line_numbers);
+        let gutter_width = Some(text_rect.x - rect.x).filter(|&width| width > 0);
+        let lines_rect = Rect {
+            width: self.wrap_width(),
+            ..text_rect
+        };
+        let h_scroll = if self.wrap { 0 } else { self.h_scroll };
+        let text = scroll.clone().render_lines(
+            cursor,
+            &self.folds,
+            lines_rect,
+            gutter_width,
+            h_scroll,
+            text_rect.width,
+            highlights,
+            theme,
+        );
         trace!("Rendering complete");
         Paragraph::new(text)
-            .style(Style::default().fg(Color::White).bg(Color::Black))
+            .style(theme.base)
             .alignment(Alignment::Left)
         //.wrap(Wrap { trim: false })
     }
-    pub fn apply_query(&self, query: &str, target_view_rect: Rect) -> View {
+    // Keeps at most result_limit results, marking the view as truncated if there were more
+    pub fn apply_query(&self, query: &str, target_view_rect: Rect, result_limit: usize) -> View {
         let target_json_rect = Block::default()
             .borders(Borders::ALL)
             .inner(target_view_rect);
-        match JQ::compile(query) {
-            Ok(mut prog) => match run_jq_query(self.values.iter(), &mut prog) {
-                Ok(results) => View::Json(JsonView::new(results, target_json_rect)),
-                Err(err) => View::Error(vec![err]),
-            },
-            Err(err) => View::Error(err),
+        let results = JQ::compile(query)
+            .map_err(JqError::Compile)
+            .and_then(|mut prog| run_jq_query(self.values.iter(), &mut prog, result_limit));
+        match results {
+            Ok(results) => {
+                let mut view = JsonView::new(results.values, target_json_rect);
+                if let Some(view) = view.as_mut() {
+                    view.truncated = results.truncated;
+                    view.set_display(self.display);
+                }
+                View::Json(view)
+            }
+            Err(err) => View::Error(ViewError::Query(err)),
         }
     }
-    pub fn visible_range(&self, folds: &HashSet<(usize, Vec<usize>)>) -> GlobalPathRange {
+    pub fn visible_range(&self, folds: &Folds) -> GlobalPathRange {
         let mut scroll = self.scroll.clone();
         let start = scroll.to_path();
         let mut end_is_line_end = scroll.at_line_end();
+        let mut line_count = 1;
         for _ in 1..self.rect.height {
-            if let None = scroll.advance(folds, self.rect.width) {
+            if let None = scroll.advance(folds, self.wrap_width()) {
                 break;
             };
             end_is_line_end = scroll.at_line_end();
+            line_count += 1;
         }
         let end = scroll.to_path();
         GlobalPathRange {
             start,
             end,
             end_is_last_line: end_is_line_end,
+            line_count,
         }
     }
-    pub fn page_down(&mut self) {
-        for _ in 1..self.rect.height {
-            if let None = self.scroll.advance(&self.folds, self.rect.width) {
-                break;
-            };
-        }
-        for _ in 1..self.rect.height {
-            if let None = self.cursor.advance(&self.folds) {
-                break;
-            };
-        }
+    // The first and last values with a line on screen
+    pub fn visible_paths(&self) -> (ValuePath, ValuePath) {
+        let range = self.visible_range(&self.folds);
+        (range.start.value_path, range.end.value_path)
+    }
+    // Screen lines with something on them, which is less than the height at the end of the values
+    pub fn visible_line_count(&self) -> usize {
+        self.visible_range(&self.folds).line_count()
+    }
+    pub fn cursor_at_screen_row(&self, row: u16) -> Option<LeafCursor> {
+        if row >= self.rect.height {
+            return None;
+        }
+        let mut scroll = self.scroll.clone();
+        for _ in 0..row {
+            scroll.advance(&self.folds, self.wrap_width())?;
+        }
+        Some(scroll.value_cursor)
+    }
+    pub fn page_down(&mut self) {
+        self.scroll_down_by(self.rect.height.saturating_sub(1));
     }
     pub fn page_up(&mut self) {
-        for _ in 1..self.rect.height {
-            if let None = self.scroll.regress(&self.folds, self.rect.width) {
+        self.scroll_up_by(self.rect.height.saturating_sub(1));
+    }
+    pub fn half_page_down(&mut self) {
+        self.scroll_down_by(self.rect.height / 2);
+    }
+    pub fn half_page_up(&mut self) {
+        self.scroll_up_by(self.rect.height / 2);
+    }
+    fn scroll_down_by(&mut self, lines: u16) {
+        for _ in 0..lines {
+            if let None = self.scroll.advance(&self.folds, self.wrap_width()) {
                 break;
             };
         }
-        for _ in 1..self.rect.height {
+        for _ in 0..lines {
+            if let None = self.cursor.advance(&self.folds) {
+                break;
+            };
+        }
+        self.clamp_cursor_to_screen();
+    }
+    fn scroll_up_by(&mut self, lines: u16) {
+        for _ in 0..lines {
+            if let None = self.scroll.regress(&self.folds, self.wrap_width()) {
+                break;
+            };
+        }
+        for _ in 0..lines {
             if let None = self.cursor.regress(&self.folds) {
                 break;
             };
         }
+        self.clamp_cursor_to_screen();
+    }
+    // Paging moves the cursor by values but the scroll by lines, so multi-line values can leave
+    // the cursor off screen: pull it back onto the nearest visible value.
+    fn clamp_cursor_to_screen(&mut self) {
+        let visible_range = self.visible_range(&self.folds);
+        let (start, end) = visible_range.value_range().into_inner();
+        while self.cursor.to_path() > *end {
+            if self.cursor.regress(&self.folds).is_none() {
+                break;
+            }
+        }
+        while self.cursor.to_path() < *start {
+            if self.cursor.advance(&self.folds).is_none() {
+                break;
+            }
+        }
+    }
+    // Moves the cursor, unfolding and scrolling as needed to make it visible.
+    pub fn jump_to(&mut self, cursor: LeafCursor) {
+        self.cursor = cursor;
+        self.unfold_around_cursor();
+        self.reveal_cursor();
+    }
+    // Puts the cursor at the top of the screen if it's off screen
+    fn reveal_cursor(&mut self) {
+        if !self
+            .visible_range(&self.folds)
+            .contains_value(&self.cursor.to_path())
+        {
+            self.scroll_to(self.cursor.clone());
+        }
+    }
+    // Moves the cursor to line n as numbered in the gutter (counting from 1, as if nothing were
+    // folded), landing on the folded line if n is hidden inside a fold. Lines past the end go to
+    // the last line.
+    pub fn goto_line(&mut self, n: usize) {
+        let target = n.saturating_sub(1);
+        let mut cursor = LeafCursor::new(self.values.clone()).expect("values should still exist");
+        let mut line = 0;
+        loop {
+            let next_line = line + cursor.lines_spanned(&self.folds);
+            if next_line > target {
+                break;
+            }
+            let mut next = cursor.clone();
+            if next.advance(&self.folds).is_none() {
+                break;
+            }
+            cursor = next;
+            line = next_line;
+        }
+        self.cursor = cursor;
+        self.reveal_cursor();
+    }
+    // Moves the cursor to the start of the n'th top level value, counting from 1. Returns false if
+    // there aren't that many.
+    pub fn goto_document(&mut self, n: usize) -> bool {
+        match n
+            .checked_sub(1)
+            .and_then(|top_index| self.cursor.document_start(top_index))
+        {
+            Some(cursor) => {
+                self.cursor = cursor;
+                self.reveal_cursor();
+                true
+            }
+            None => false,
+        }
+    }
+    // Moves the cursor to the start of the next (or previous, if backward) top level value. Returns
+    // false if there isn't one.
+    pub fn step_document(&mut self, backward: bool) -> bool {
+        let mut cursor = self.cursor.clone();
+        let moved = if backward {
+            cursor.prev_document()
+        } else {
+            cursor.next_document()
+        };
+        if moved.is_none() {
+            return false;
+        }
+        self.cursor = cursor;
+        self.reveal_cursor();
+        true
+    }
+    // Scrolls so the first line of the cursor is in the middle of the screen, or as close as it can
+    // get near the top of the document
+    pub fn center_cursor(&mut self) {
+        self.scroll_to(self.cursor.clone());
+        for _ in 0..self.rect.height / 2 {
+            if self
+                .scroll
+                .regress(&self.folds, self.wrap_width())
+                .is_none()
+            {
+                break;
+            }
+        }
+    }
+    // Scrolls so the first line of value_cursor is at the top of the screen
+    fn scroll_to(&mut self, value_cursor: LeafCursor) {
+        let line = value_cursor.current_line(&self.folds, self.wrap_width());
+        let line_cursor = LineCursor::new_at_start(line.render(self.display), self.wrap_width());
+        self.scroll = GlobalCursor {
+            value_cursor,
+            line_cursor,
+            display: self.display,
+        };
+    }
+    // Returns false (and does nothing) if this would delete the only value left
+    pub fn delete_at_cursor(&mut self) -> bool {
+        let cursor = match self.cursor.delete_focus() {
+            Some(cursor) => cursor,
+            None => return false,
+        };
+        self.push_undo();
+        let deleted = self.cursor.to_path().strip_position();
+        self.folds = self
+            .folds
+            .drain()
+            .filter_map(|(fold, descendants)| {
+                Some((path_after_deletion(fold, &deleted)?, descendants))
+            })
+            .collect();
+        self.bookmarks = self
+            .bookmarks
+            .drain()
+            .filter_map(|(mark, path)| Some((mark, path.after_deletion(&deleted)?)))
+            .collect();
+        let scroll_path = self.scroll.value_cursor.to_path().after_deletion(&deleted);
+        self.set_edited_values(cursor, scroll_path);
+        true
+    }
+    pub fn replace_at_cursor(&mut self, value: JV) {
+        self.push_undo();
+        let cursor = self.cursor.replace_focus(value);
+        let scroll_path = self.scroll.value_cursor.to_path();
+        self.set_edited_values(cursor, Some(scroll_path));
+        // Marks on the old value won't fit if it's now a container
+        let bookmarks = std::mem::take(&mut self.bookmarks);
+        self.inherit_bookmarks(bookmarks);
+    }
+    // Returns false (and does nothing) if the cursor isn't under a key, or new_key is taken
+    pub fn rename_key(&mut self, new_key: &str) -> bool {
+        let (cursor, new_indices) = match self.cursor.rename_key(new_key) {
+            Some(renamed) => renamed,
+            None => return false,
+        };
+        self.push_undo();
+        let mut object = self.cursor.to_path().strip_position();
+        object.1.pop();
+        self.folds = self
+            .folds
+            .drain()
+            .map(|(fold, descendants)| {
+                (path_after_reorder(fold, &object, &new_indices), descendants)
+            })
+            .collect();
+        self.bookmarks = self
+            .bookmarks
+            .drain()
+            .map(|(mark, path)| (mark, path.after_reorder(&object, &new_indices)))
+            .collect();
+        let scroll_path = self
+            .scroll
+            .value_cursor
+            .to_path()
+            .after_reorder(&object, &new_indices);
+        self.set_edited_values(cursor, Some(scroll_path));
+        true
+    }
+    // Adds key to the object under the cursor (or the one it's in), and moves onto the new value.
+    // Returns false (and does nothing) if there's no such object, or it already has key.
+    pub fn insert_key(&mut self, key: &str, value: JV) -> bool {
+        let object = match self.cursor.enclosing_container() {
+            Some(object) => object,
+            None => return false,
+        };
+        let (cursor, new_indices) = match object.insert_key(key, value) {
+            Some(inserted) => inserted,
+            None => return false,
+        };
+        self.push_undo();
+        let object = object.to_path().strip_position();
+        self.folds = self
+            .folds
+            .drain()
+            .map(|(fold, descendants)| {
+                (path_after_reorder(fold, &object, &new_indices), descendants)
+            })
+            .collect();
+        self.bookmarks = self
+            .bookmarks
+            .drain()
+            .map(|(mark, path)| (mark, path.after_reorder(&object, &new_indices)))
+            .collect();
+        let scroll_path = self
+            .scroll
+            .value_cursor
+            .to_path()
+            .after_reorder(&object, &new_indices);
+        self.unfold_around(cursor.to_path());
+        self.set_edited_values(cursor, Some(scroll_path));
+        true
+    }
+    // Adds value to the end of the array under the cursor (or the one it's in), and moves onto it.
+    // Returns false (and does nothing) if there's no such array.
+    pub fn append_element(&mut self, value: JV) -> bool {
+        let cursor = match self
+            .cursor
+            .enclosing_container()
+            .and_then(|array| array.append_element(value))
+        {
+            Some(cursor) => cursor,
+            None => return false,
+        };
+        self.push_undo();
+        // Nothing moves, so the folds and marks all still fit
+        let scroll_path = self.scroll.value_cursor.to_path();
+        self.unfold_around(cursor.to_path());
+        self.set_edited_values(cursor, Some(scroll_path));
+        true
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            values: self.values.clone(),
+            cursor: self.cursor.to_path(),
+            folds: self.folds.clone(),
+            sort_keys: self.sort_keys,
+        }
+    }
+    // Remembers the current state before an edit. A new edit means there's nothing left to redo.
+    fn push_undo(&mut self) {
+        if self.undo_stack.len() == UNDO_DEPTH {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(self.snapshot());
+        self.redo_stack.clear();
+    }
+    fn restore(&mut self, snapshot: Snapshot) {
+        let cursor = LeafCursor::from_path(snapshot.values, &snapshot.cursor);
+        let scroll_path = self.scroll.value_cursor.to_path();
+        self.folds = snapshot.folds;
+        self.sort_keys = snapshot.sort_keys;
+        self.set_edited_values(cursor, Some(scroll_path));
+        self.clamp_to_folds();
+    }
+    // Goes back to before the last edit. Returns false if there's nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let snapshot = match self.undo_stack.pop() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.redo_stack.push(self.snapshot());
+        self.restore(snapshot);
+        true
+    }
+    // Puts back the last edit undone. Returns false if there's nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let snapshot = match self.redo_stack.pop() {
+            Some(snapshot) => snapshot,
+            None => return false,
+        };
+        self.undo_stack.push(self.snapshot());
+        self.restore(snapshot);
+        true
+    }
+    // Switches to the values cursor points into, keeping the scroll where it was if it still
+    // exists.
+    fn set_edited_values(&mut self, cursor: LeafCursor, scroll_path: Option<ValuePath>) {
+        self.values = cursor.jsons.clone();
+        self.unsorted_values = None;
+        // The stashed folds' paths may not point at the same values anymore
+        self.grep_stash = None;
+        self.line_count.set(None);
+        let scroll = scroll_path
+            .and_then(|path| LeafCursor::try_from_path(self.values.clone(), &path))
+            .unwrap_or_else(|| cursor.clone());
+        self.scroll_to(scroll);
+        self.cursor = cursor;
+        self.reveal_cursor();
+    }
+    pub fn first_sibling(&mut self) {
+        let mut cursor = self.cursor.clone();
+        cursor.first_sibling();
+        self.jump_to(cursor);
+    }
+    pub fn last_sibling(&mut self) {
+        let mut cursor = self.cursor.clone();
+        cursor.last_sibling();
+        self.jump_to(cursor);
+    }
+    // What to store for a newly folded container
+    fn fold_info(&self, json: &JV) -> Fold {
+        if self.show_descendants {
+            Fold::Descendants(descendant_count(json))
+        } else {
+            Fold::Children
+        }
+    }
+    pub fn toggle_descendant_counts(&mut self) {
+        self.show_descendants = !self.show_descendants;
+        let folds: Vec<_> = self
+            .folds
+            .iter()
+            .filter(|(_, fold)| **fold != Fold::Inline)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for (top_index, path) in folds {
+            let json = LeafCursor::at_indices(self.values.clone(), top_index, path.clone())
+                .expect("Folds should point at values")
+                .focus;
+            let fold = self.fold_info(&json);
+            self.folds.insert((top_index, path), fold);
+        }
+        // A folded line under the scroll may wrap differently now
+        let scroll_path = self.scroll.value_cursor.to_path().strip_position();
+        if self.folds.contains_key(&scroll_path) {
+            self.scroll_to(self.scroll.value_cursor.clone());
+        }
     }
     pub fn unfold_around_cursor(&mut self) {
-        let mut path = self.cursor.to_path().strip_position();
-        while !path.1.is_empty() {
-            self.folds.remove(&path);
-            path.1.pop();
+        self.unfold_around(self.cursor.to_path());
+    }
+    // Unfolds the value at path and everything it's in
+    fn unfold_around(&mut self, path: ValuePath) {
+        let (top_index, mut frames) = path.strip_position();
+        loop {
+            self.folds.remove(&(top_index, frames.clone()));
+            if frames.pop().is_none() {
+                break;
+            }
         }
     }
-    pub fn toggle_fold(&mut self) {
+    pub fn toggle_fold(&mut self) -> Result<(), FoldError> {
+        if self.cursor.focus_position == FocusPosition::Value {
+            return Err(FoldError::Leaf);
+        }
         let path = self.cursor.to_path().strip_position();
-        if self.folds.contains(&path) {
+        if self.folds.contains_key(&path) {
             self.folds.remove(&path);
         } else {
-            self.folds.insert(path);
+            self.folds.insert(path, self.fold_info(&self.cursor.focus));
             if let FocusPosition::End = self.cursor.focus_position {
                 self.cursor.focus_position = FocusPosition::Start;
             }
@@ -546,55 +1860,439 @@ impl JsonView {
                 .value_cursor
                 .descends_from_or_matches(&self.cursor)
             {
-                let line = self.cursor.current_line(&self.folds, self.rect.width);
-                let line_cursor = LineCursor::new_at_start(line.render(), self.rect.width);
+                let line = self.cursor.current_line(&self.folds, self.wrap_width());
+                let line_cursor =
+                    LineCursor::new_at_start(line.render(self.display), self.wrap_width());
                 self.scroll = GlobalCursor {
                     value_cursor: self.cursor.clone(),
                     // Note: this is okay because you can only fold objects and arrays
                     line_cursor,
+                    display: self.display,
                 };
             }
         }
+        Ok(())
+    }
+    pub fn cursor_is_folded(&self) -> bool {
+        self.folds
+            .contains_key(&self.cursor.to_path().strip_position())
+    }
+    // Folds or unfolds the container under the cursor, and every container inside it.
+    pub fn fold_recursive(&mut self, fold: bool) -> Result<(), FoldError> {
+        if self.cursor.focus_position == FocusPosition::Value {
+            return Err(FoldError::Leaf);
+        }
+        self.cursor.focus_position = FocusPosition::Start;
+        let scroll_path = self.scroll.value_cursor.to_path().strip_position();
+        let scroll_was_folded = self.folds.contains_key(&scroll_path);
+        let root = self.cursor.clone();
+        // Walk without folds, so we find containers inside existing folds too
+        let mut cursor = root.clone();
+        loop {
+            if cursor.focus_position == FocusPosition::Start {
+                let path = cursor.to_path().strip_position();
+                let foldable = is_foldable(&cursor.focus);
+                if !fold {
+                    self.folds.remove(&path);
+                } else if foldable {
+                    let info = self.fold_info(&cursor.focus);
+                    self.folds.insert(path, info);
+                }
+            }
+            if cursor.advance(&Folds::new()).is_none() || !cursor.descends_from_or_matches(&root) {
+                break;
+            }
+            // The root's closing bracket
+            if cursor.frames.len() == root.frames.len() {
+                break;
+            }
+        }
+        if !fold && scroll_was_folded && !self.folds.contains_key(&scroll_path) {
+            // The folded line is replaced by the container's opening line
+            self.scroll_to(self.scroll.value_cursor.clone());
+        }
+        self.clamp_to_folds();
+        Ok(())
+    }
+    pub fn fold_to_depth(&mut self, depth: usize) {
+        // Walking with only the new folds means we skip the contents of anything we just folded,
+        // but still find containers hidden under pre-existing folds.
+        let mut new_folds = Folds::new();
+        let mut cursor = LeafCursor::new(self.values.clone()).expect("values should still exist");
+        loop {
+            if cursor.frames.len() == depth && cursor.focus_position == FocusPosition::Start {
+                let foldable = is_foldable(&cursor.focus);
+                if foldable {
+                    let fold = self.fold_info(&cursor.focus);
+                    new_folds.insert(cursor.to_path().strip_position(), fold);
+                }
+            }
+            if cursor.advance(&new_folds).is_none() {
+                break;
+            }
+        }
+        self.folds.extend(new_folds);
+        self.clamp_to_folds();
+    }
+    // Folds every container beside the path to the cursor, leaving only its ancestors open.
+    // Jumping somewhere else reopens the path to it (see unfold_around_cursor).
+    pub fn fold_siblings(&mut self) {
+        let top_index = self.cursor.top_index;
+        let mut ancestor_path = Vec::new();
+        for frame in self.cursor.frames.iter() {
+            let children: Vec<JV> = match frame {
+                CursorFrame::Array { json, .. } => {
+                    (0..json.len()).filter_map(|i| json.get(i)).collect()
+                }
+                CursorFrame::Object { json, .. } => {
+                    json.clone().into_iter().map(|(_, child)| child).collect()
+                }
+            };
+            for (i, child) in children.iter().enumerate() {
+                let foldable = is_foldable(child);
+                if i != frame.index() && foldable {
+                    let mut path = ancestor_path.clone();
+                    path.push(i);
+                    self.folds.insert((top_index, path), self.fold_info(child));
+                }
+            }
+            ancestor_path.push(frame.index());
+        }
+        self.clamp_to_folds();
+    }
+    // Folds every container that doesn't lead to a match for re, so only the matches and their
+    // ancestors are left open. The folds from before are stashed for ungrep. Returns the number of
+    // matches; if there are none, nothing changes.
+    pub fn grep(&mut self, re: &Regex, scope: SearchScope) -> usize {
+        let mut on_route = HashSet::new();
+        let mut containers = Vec::new();
+        let mut hits = 0;
+        let mock_folds = Folds::new();
+        let mut cursor = LeafCursor::new(self.values.clone()).expect("values should still exist");
+        loop {
+            if cursor.focus_position != FocusPosition::End && cursor.regex_matches(re, scope) {
+                hits += 1;
+                let (top_index, path) = cursor.to_path().strip_position();
+                for depth in 0..path.len() {
+                    on_route.insert((top_index, path[..depth].to_vec()));
+                }
+            }
+            let foldable = is_foldable(&cursor.focus);
+            if cursor.focus_position == FocusPosition::Start && foldable {
+                containers.push((
+                    cursor.to_path().strip_position(),
+                    self.fold_info(&cursor.focus),
+                ));
+            }
+            if cursor.advance(&mock_folds).is_none() {
+                break;
+            }
+        }
+        if hits == 0 {
+            return 0;
+        }
+        // Grepping again while grepping still goes back to the folds from before either
+        let old_folds = self.grep_stash.take().unwrap_or_else(|| self.folds.clone());
+        // Small containers off the route can stay drawn on one line
+        let mut new_folds: Folds = old_folds
+            .iter()
+            .filter(|(path, fold)| **fold == Fold::Inline && !on_route.contains(*path))
+            .map(|(path, fold)| (path.clone(), *fold))
+            .collect();
+        for (path, fold) in containers {
+            if !on_route.contains(&path) {
+                new_folds.entry(path).or_insert(fold);
+            }
+        }
+        self.folds = new_folds;
+        self.grep_stash = Some(old_folds);
+        let mut value_cursor = self.scroll.value_cursor.clone();
+        value_cursor.focus_position = FocusPosition::Start;
+        self.scroll_to(value_cursor);
+        self.clamp_to_folds();
+        hits
+    }
+    // Puts back the folds from before grep. Returns false if grep mode wasn't on.
+    pub fn ungrep(&mut self) -> bool {
+        let folds = match self.grep_stash.take() {
+            Some(folds) => folds,
+            None => return false,
+        };
+        self.folds = folds;
+        let mut value_cursor = self.scroll.value_cursor.clone();
+        value_cursor.focus_position = FocusPosition::Start;
+        self.scroll_to(value_cursor);
+        self.clamp_to_folds();
+        true
+    }
+    pub fn is_grepping(&self) -> bool {
+        self.grep_stash.is_some()
+    }
+    // Small containers drawn on one line stay that way.
+    pub fn unfold_all(&mut self) {
+        let scroll_path = self.scroll.value_cursor.to_path().strip_position();
+        let scroll_was_folded =
+            matches!(self.folds.get(&scroll_path), Some(fold) if *fold != Fold::Inline);
+        self.folds.retain(|_, fold| *fold == Fold::Inline);
+        if scroll_was_folded {
+            // The folded line is replaced by the container's opening line, so start from the top
+            // of it rather than wherever we were in the (possibly wrapped) folded line.
+            let mut value_cursor = self.scroll.value_cursor.clone();
+            value_cursor.focus_position = FocusPosition::Start;
+            let line = value_cursor.current_line(&self.folds, self.wrap_width());
+            let line_cursor =
+                LineCursor::new_at_start(line.render(self.display), self.wrap_width());
+            self.scroll = GlobalCursor {
+                value_cursor,
+                line_cursor,
+                display: self.display,
+            };
+        }
+        self.resize_to(self.rect);
+    }
+    // Switches between sorting object keys and showing them in their original order. Folds, marks,
+    // the cursor and the scroll follow their values to wherever they end up.
+    pub fn toggle_sort_keys(&mut self) {
+        let values: Rc<[JV]> = if self.sort_keys {
+            self.sort_keys = false;
+            match self.unsorted_values.take() {
+                Some(values) => values,
+                // Edited since sorting, so there's no original order to go back to
+                None => return,
+            }
+        } else {
+            self.sort_keys = true;
+            self.unsorted_values = Some(self.values.clone());
+            self.values.iter().map(sort_keys).collect()
+        };
+        let old_values = std::mem::replace(&mut self.values, values.clone());
+        self.folds = self
+            .folds
+            .drain()
+            .filter_map(|(fold, info)| {
+                Some((path_after_key_reorder(fold, &old_values, &values)?, info))
+            })
+            .collect();
+        self.bookmarks = self
+            .bookmarks
+            .drain()
+            .filter_map(|(mark, path)| Some((mark, path.after_key_reorder(&old_values, &values)?)))
+            .collect();
+        let cursor_path = self
+            .cursor
+            .to_path()
+            .after_key_reorder(&old_values, &values)
+            .expect("Cursor should survive sorting keys");
+        if let Some(stash) = self.grep_stash.as_mut() {
+            *stash = stash
+                .drain()
+                .filter_map(|(fold, info)| {
+                    Some((path_after_key_reorder(fold, &old_values, &values)?, info))
+                })
+                .collect();
+        }
+        self.cursor = LeafCursor::from_path(values.clone(), &cursor_path);
+        let scroll_path = self
+            .scroll
+            .value_cursor
+            .to_path()
+            .after_key_reorder(&old_values, &values)
+            .expect("Scroll should survive sorting keys");
+        self.scroll_to(LeafCursor::from_path(values, &scroll_path));
+        self.reveal_cursor();
+    }
+    // Switches between drawing containers over several lines, and drawing the ones that fit in
+    // limit columns on a single line. Inlined containers act like folds: the cursor skips over
+    // them, and toggling the fold on one draws it in full.
+    pub fn toggle_inline(&mut self, limit: usize) {
+        if self.inline_width.take().is_some() {
+            let scroll_path = self.scroll.value_cursor.to_path().strip_position();
+            let scroll_was_inline = self.folds.get(&scroll_path) == Some(&Fold::Inline);
+            self.folds.retain(|_, fold| *fold != Fold::Inline);
+            if scroll_was_inline {
+                self.scroll_to(self.scroll.value_cursor.clone());
+            }
+        } else {
+            self.inline_width = Some(limit);
+            self.inline_containers(0..self.values.len());
+            self.clamp_to_folds();
+        }
+    }
+    // Inlines the small containers in the given top level values, leaving folded ones alone.
+    fn inline_containers(&mut self, top_indices: Range<usize>) {
+        let limit = match self.inline_width {
+            Some(limit) => limit,
+            None => return,
+        };
+        let mut cursor =
+            match LeafCursor::at_indices(self.values.clone(), top_indices.start, Vec::new()) {
+                Some(cursor) => cursor,
+                None => return,
+            };
+        // Walking with only the new inlines means we don't look inside anything already inlined
+        let mut inlined = Folds::new();
+        while cursor.top_index < top_indices.end {
+            let path = cursor.to_path().strip_position();
+            if cursor.focus_position == FocusPosition::Start
+                && !self.folds.contains_key(&path)
+                && inline_width(&cursor.focus, limit, self.display).is_some()
+            {
+                inlined.insert(path, Fold::Inline);
+            }
+            if cursor.advance(&inlined).is_none() {
+                break;
+            }
+        }
+        self.folds.extend(inlined);
+    }
+    // Moves the cursor and scroll out of any folded containers, and then makes sure the cursor is
+    // still on screen.
+    fn clamp_to_folds(&mut self) {
+        if let Some(cursor) = self.cursor.outermost_folded_ancestor(&self.folds) {
+            self.cursor = cursor;
+        }
+        let scroll_path = self.scroll.value_cursor.to_path().strip_position();
+        let scroll_cursor = match self
+            .scroll
+            .value_cursor
+            .outermost_folded_ancestor(&self.folds)
+        {
+            Some(cursor) => Some(cursor),
+            // The line itself was replaced by its folded version
+            None if self.folds.contains_key(&scroll_path) => Some(self.scroll.value_cursor.clone()),
+            None => None,
+        };
+        if let Some(value_cursor) = scroll_cursor {
+            let line = value_cursor.current_line(&self.folds, self.wrap_width());
+            let line_cursor =
+                LineCursor::new_at_start(line.render(self.display), self.wrap_width());
+            self.scroll = GlobalCursor {
+                value_cursor,
+                line_cursor,
+                display: self.display,
+            };
+        }
+        self.resize_to(self.rect);
     }
     pub fn advance_cursor(&mut self) {
         let visible_range = self.visible_range(&self.folds);
         if !visible_range.contains_value_end(&self.cursor.to_path()) {
-            self.scroll.advance(&self.folds, self.rect.width);
+            self.scroll.advance(&self.folds, self.wrap_width());
             return;
         }
         self.cursor.advance(&self.folds);
         if !visible_range.contains_value(&self.cursor.to_path()) {
-            self.scroll.advance(&self.folds, self.rect.width);
+            self.scroll.advance(&self.folds, self.wrap_width());
         }
     }
     pub fn regress_cursor(&mut self) {
         let visible_range = self.visible_range(&self.folds);
         if !visible_range.contains_value_start(&self.cursor.to_path()) {
-            self.scroll.regress(&self.folds, self.rect.width);
+            self.scroll.regress(&self.folds, self.wrap_width());
             return;
         }
         self.cursor.regress(&self.folds);
         if !visible_range.contains_value(&self.cursor.to_path()) {
-            self.scroll.regress(&self.folds, self.rect.width);
+            self.scroll.regress(&self.folds, self.wrap_width());
         }
     }
     pub fn resize_to(&mut self, json_rect: Rect) {
         self.rect = json_rect;
-        self.scroll.resize_to(json_rect);
+        self.scroll.resize_to(Rect {
+            width: self.wrap_width(),
+            ..json_rect
+        });
         while self.cursor.to_path() < **self.visible_range(&self.folds).value_range().start() {
-            self.scroll.regress(&self.folds, self.rect.width);
+            self.scroll.regress(&self.folds, self.wrap_width());
         }
         while self.cursor.to_path() > **self.visible_range(&self.folds).value_range().end() {
-            self.scroll.advance(&self.folds, self.rect.width);
+            self.scroll.advance(&self.folds, self.wrap_width());
+        }
+    }
+    // The lines as drawn with the current folds and wrapped at width: all of them, or height lines
+    // starting from the top of the screen. The cursor isn't highlighted.
+    fn export_lines(&self, width: u16, height: Option<u16>, theme: &Theme) -> Vec<Spans<'static>> {
+        let mut scroll = match height {
+            Some(_) => self.scroll.clone(),
+            None => GlobalCursor::new(self.values.clone(), width, &self.folds, self.display)
+                .expect("Views always have values"),
+        };
+        scroll.resize_to(Rect { width, ..self.rect });
+        let mut lines = vec![scroll.current_line().to_spans(false, theme)];
+        while height.is_none_or(|height| lines.len() < height as usize) {
+            if scroll.advance(&self.folds, width).is_none() {
+                break;
+            }
+            lines.push(scroll.current_line().to_spans(false, theme));
+        }
+        lines
+    }
+    pub fn render_to_string(&self, width: u16, height: Option<u16>) -> String {
+        let mut out = String::new();
+        for line in self.export_lines(width, height, &Theme::plain()) {
+            for span in line.0 {
+                out.push_str(&span.content);
+            }
+            out.push('\n');
         }
+        out
     }
-    pub fn save_to(&self, path: &str) -> std::io::Result<()> {
+    // Like render_to_string, but keeps the colors of theme
+    pub fn render_to_html(&self, width: u16, height: Option<u16>, theme: &Theme) -> String {
+        let mut out = format!("<pre style=\"{}\">\n", css(theme.base));
+        for line in self.export_lines(width, height, theme) {
+            // Neighbouring spans often share a style, like the quotes and contents of a string
+            let mut runs: Vec<(Style, String)> = Vec::new();
+            for span in line.0 {
+                match runs.last_mut() {
+                    Some((style, content)) if *style == span.style => {
+                        content.push_str(&span.content)
+                    }
+                    _ => runs.push((span.style, span.content.into_owned())),
+                }
+            }
+            for (style, content) in runs {
+                let content = html_escape(&content);
+                let style = css(style);
+                if style.is_empty() {
+                    out.push_str(&content);
+                } else {
+                    out.push_str(&format!("<span style=\"{}\">{}</span>", style, content));
+                }
+            }
+            out.push('\n');
+        }
+        out.push_str("</pre>\n");
+        out
+    }
+    // Writes out the whole document as it's drawn: as HTML if the path ends in .html or .htm, and
+    // as plain text otherwise.
+    pub fn export_to(&self, path: &str, theme: &Theme) -> std::io::Result<()> {
+        let is_html = path.ends_with(".html") || path.ends_with(".htm");
+        let out = if is_html {
+            self.render_to_html(self.wrap_width(), None, theme)
+        } else {
+            self.render_to_string(self.wrap_width(), None)
+        };
+        fs::write(path, out)
+    }
+    pub fn save_to(&self, path: &str, pretty: bool) -> std::io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        write_values(&self.values, &mut file, pretty)?;
+        file.flush()
+    }
+    pub fn save_subtree_to(&self, path: &str, pretty: bool) -> std::io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        write_values(&[self.cursor.focused_value()], &mut file, pretty)?;
+        file.flush()
+    }
+    pub fn save_to_yaml(&self, path: &str) -> std::io::Result<()> {
         let mut file = std::fs::File::create(path)?;
         for (i, v) in self.values.iter().enumerate() {
             if i != 0 {
-                write!(file, "\n")?;
+                writeln!(file, "---")?;
             }
-            serde_json::to_writer_pretty(&mut file, v)?;
+            serde_yaml::to_writer(&mut file, v).map_err(io::Error::other)?;
         }
         Ok(())
     }
@@ -605,9 +2303,19 @@ pub struct GlobalPathRange {
     start: GlobalPath,
     end: GlobalPath,
     end_is_last_line: bool,
+    line_count: usize,
 }
 
 impl GlobalPathRange {
+    pub fn start(&self) -> &GlobalPath {
+        &self.start
+    }
+    pub fn end(&self) -> &GlobalPath {
+        &self.end
+    }
+    pub fn line_count(&self) -> usize {
+        self.line_count
+    }
     pub fn value_range(&self) -> RangeInclusive<&ValuePath> {
         &self.start.value_path..=&self.end.value_path
     }
@@ -636,12 +2344,23 @@ impl GlobalPathRange {
 
 #[cfg(test)]
 mod tests {
-    use super::JsonView;
-    use crate::{cursor::GlobalCursor, jq::jv::JV, testing::arb_json};
+    use super::{
+        fuzzy_score, parse_ndjson, parse_ndjson_lossy, write_values, FoldError, JsonView,
+        LoadProgress, View, ViewError, ViewForest, ViewForestIndex, ViewTree, ViewTreeIndex,
+    };
+    use crate::{
+        cursor::{parse_path, Fold, Folds, GlobalCursor, LeafCursor, SearchScope},
+        jq::{jv::JV, query::DEFAULT_RESULT_LIMIT},
+        layout::{JexLayout, LayoutMode, DEFAULT_SPLIT_PERCENT},
+        lines::{DisplayOptions, LineCursor},
+        testing::arb_json,
+        theme::Theme,
+    };
     use pretty_assertions::assert_eq;
     use proptest::proptest;
-    use serde_json::{Deserializer, Value};
-    use std::{collections::HashSet, fs, io};
+    use regex::Regex;
+    use serde_json::{json, Deserializer, Value};
+    use std::{collections::HashSet, fs, io, rc::Rc, time::Duration};
     use tui::layout::Rect;
     const DUMMY_RECT: Rect = Rect {
         x: 1,
@@ -649,12 +2368,24 @@ mod tests {
         width: 135,
         height: 70,
     };
+    fn test_layout() -> JexLayout {
+        JexLayout::new(
+            DUMMY_RECT,
+            false,
+            false,
+            LayoutMode::SideBySide,
+            DEFAULT_SPLIT_PERCENT,
+        )
+    }
     const TINY_RECT: Rect = Rect {
         x: 1,
         y: 1,
         width: 15,
         height: 20,
     };
+    fn fold_paths(view: &JsonView) -> HashSet<(usize, Vec<usize>)> {
+        view.folds.keys().cloned().collect()
+    }
     fn check_folds(values: Vec<Value>) {
         let jsons: Vec<JV> = values.iter().map(|v| v.into()).collect();
         let mut view = match JsonView::new(jsons, DUMMY_RECT) {
@@ -663,11 +2394,12 @@ mod tests {
         };
         loop {
             let saved_cursor = view.cursor.clone();
-            view.toggle_fold();
-            view.toggle_fold();
+            if view.toggle_fold().is_ok() {
+                view.toggle_fold().unwrap();
+            }
             // Folding resets you to the top of the fold
             view.cursor = saved_cursor;
-            assert_eq!(view.folds, HashSet::new());
+            assert_eq!(fold_paths(&view), HashSet::new());
             if view.cursor.advance(&view.folds).is_none() {
                 break;
             }
@@ -680,6 +2412,1125 @@ mod tests {
         }
     }
     #[test]
+    fn unit_apply_query_error() {
+        let jsons: Vec<JV> = vec![(&json!([1])).into(), (&json!({"a": 2})).into()];
+        let view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        match view.apply_query(".[0]", DUMMY_RECT, DEFAULT_RESULT_LIMIT) {
+            View::Error(ViewError::Query(err)) => assert_eq!(err.input_index(), Some(1)),
+            _ => panic!("Expected a query error"),
+        }
+        match view.apply_query(".[", DUMMY_RECT, DEFAULT_RESULT_LIMIT) {
+            View::Error(ViewError::Query(err)) => assert_eq!(err.input_index(), None),
+            _ => panic!("Expected a query error"),
+        }
+    }
+    #[test]
+    fn unit_set_display() {
+        let glyphs = DisplayOptions {
+            literal_glyphs: true,
+            ..DisplayOptions::default()
+        };
+        let mut tree =
+            ViewTree::new_from_values(vec![(&json!([null])).into()], "test".into(), test_layout());
+        tree.set_display(glyphs);
+        // New queries are drawn like the view they're run on
+        tree.push_trivial_child(DUMMY_RECT);
+        tree.recompute_children(DUMMY_RECT, DEFAULT_RESULT_LIMIT);
+        for (_, child) in tree.children.iter() {
+            match &child.view_frame.view {
+                View::Json(Some(view)) => assert_eq!(view.display, glyphs),
+                _ => panic!("Expected a json view"),
+            }
+        }
+        match &tree.view_frame.view {
+            View::Json(Some(view)) => {
+                assert_eq!(view.render_to_string(DUMMY_RECT.width, None), "[\n  ∅\n]\n")
+            }
+            _ => panic!("Expected a json view"),
+        }
+    }
+    #[test]
+    fn unit_apply_query_truncated() {
+        let jsons: Vec<JV> = vec![(&json!([1, 2, 3])).into()];
+        let view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        match view.apply_query(".[]", DUMMY_RECT, 2) {
+            View::Json(Some(results)) => {
+                assert!(results.truncated);
+                assert_eq!(results.values.len(), 2);
+            }
+            _ => panic!("Expected a json view"),
+        }
+        match view.apply_query(".[0]", DUMMY_RECT, 2) {
+            View::Json(Some(results)) => assert!(!results.truncated),
+            _ => panic!("Expected a json view"),
+        }
+    }
+    #[test]
+    fn unit_fold_to_depth() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"a": [1, {"b": 2}], "c": {}, "d": {"e": [3]}})).into(),
+            (&json!([[4]])).into(),
+        ];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.fold_to_depth(1);
+        let expected: HashSet<(usize, Vec<usize>)> = vec![(0, vec![0]), (0, vec![2]), (1, vec![0])]
+            .into_iter()
+            .collect();
+        assert_eq!(fold_paths(&view), expected);
+    }
+    #[test]
+    fn unit_fold_to_depth_moves_cursor() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2]})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        // Put the cursor on the `2`
+        for _ in 0..3 {
+            view.advance_cursor();
+        }
+        view.fold_to_depth(1);
+        let mut expected = LeafCursor::new(view.values.clone()).unwrap();
+        expected.advance(&view.folds);
+        assert_eq!(view.cursor, expected);
+    }
+    #[test]
+    fn unit_cursor_at_screen_row() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2]})).into()];
+        let view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let mut expected = LeafCursor::new(view.values.clone()).unwrap();
+        for _ in 0..3 {
+            expected.advance(&view.folds);
+        }
+        assert_eq!(view.cursor_at_screen_row(3), Some(expected));
+        assert_eq!(view.cursor_at_screen_row(6), None);
+    }
+    #[test]
+    fn unit_append_values() {
+        let jsons: Vec<JV> = vec![(&json!(1.0)).into(), (&json!([2.0, 3.0])).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        for _ in 0..3 {
+            view.advance_cursor();
+        }
+        let path = view.cursor.to_path();
+        view.append_values(vec![(&json!(4.0)).into()]);
+        assert_eq!(view.values.len(), 3);
+        assert_eq!(view.cursor.to_path(), path);
+    }
+    #[test]
+    fn unit_new_from_reader_pending_load() {
+        let input: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+        let input_len = input.len() as u64;
+        let layout = test_layout();
+        let (mut tree, pending) =
+            ViewTree::new_from_reader(io::Cursor::new(input), "test".into(), layout).unwrap();
+        let pending = pending.expect("Expected the load to still be pending");
+        let view = match &mut tree.view_frame.view {
+            View::Json(Some(view)) => view,
+            _ => panic!("Expected a json view"),
+        };
+        assert!(view.has_pending_load);
+        assert!(view.values.len() < 1000);
+        loop {
+            let (loaded, status) = pending.poll(Duration::from_secs(1));
+            assert!(loaded.skipped.is_empty());
+            view.append_values(loaded.values);
+            if let Some(result) = status {
+                result.unwrap();
+                break;
+            }
+        }
+        let expected: Vec<JV> = (0..1000).map(|i| (&json!(i as f64)).into()).collect();
+        assert_eq!(&*view.values, &expected[..]);
+        assert_eq!(pending.progress().bytes_read, input_len);
+    }
+    #[test]
+    fn unit_new_from_reader_parse_error() {
+        let layout = test_layout();
+        let input = "{\"a\": 1,\n \"b\": }";
+        let (tree, pending) =
+            ViewTree::new_from_reader(io::Cursor::new(input), "test".into(), layout).unwrap();
+        assert!(pending.is_none());
+        match &tree.view_frame.view {
+            View::Error(err) => {
+                let messages = err.messages();
+                assert_eq!(messages[0], "Couldn't parse test:");
+                assert!(messages[1].contains("line 2"), "{:?}", messages);
+            }
+            _ => panic!("Expected an error view"),
+        }
+    }
+    #[test]
+    fn unit_new_from_reader_lossy_integers() {
+        let load = |input: &'static str| {
+            ViewTree::new_from_reader(io::Cursor::new(input), "test".into(), test_layout()).unwrap()
+        };
+        let (tree, pending) =
+            load("[9007199254740993, 1]\n9007199254740992\n{\"a\": -1234567890123456789}\n");
+        assert!(pending.is_none());
+        assert_eq!(tree.lossy_integers, 2);
+        // Each load keeps its own count
+        let (tree, pending) = load("1\n2\n");
+        assert!(pending.is_none());
+        assert_eq!(tree.lossy_integers, 0);
+        // The rest of a bigger file is counted as it's polled in
+        let input: String = (0..1000).map(|_| "9007199254740993\n").collect();
+        let (tree, pending) =
+            ViewTree::new_from_reader(io::Cursor::new(input), "test".into(), test_layout())
+                .unwrap();
+        let pending = pending.expect("Expected the load to still be pending");
+        let mut lossy_integers = tree.lossy_integers;
+        loop {
+            let (loaded, status) = pending.poll(Duration::from_secs(1));
+            lossy_integers += loaded.lossy_integers;
+            if status.is_some() {
+                break;
+            }
+        }
+        assert_eq!(lossy_integers, 1000);
+    }
+    #[test]
+    fn unit_new_from_reader_skips_bad_values() {
+        let input = "{\"a\": 1}\n{\"a\": }\n[2]\n";
+        let (tree, pending) =
+            ViewTree::new_from_reader(io::Cursor::new(input), "test".into(), test_layout())
+                .unwrap();
+        assert!(pending.is_none());
+        match &tree.view_frame.view {
+            View::Json(Some(view)) => {
+                let expected: Vec<JV> = vec![(&json!({"a": 1.0})).into(), (&json!([2.0])).into()];
+                assert_eq!(&*view.values, &expected[..]);
+            }
+            _ => panic!("Expected a json view"),
+        }
+        assert_eq!(
+            tree.skipped_values,
+            vec!["line 2, column 7: expected value".to_owned()]
+        );
+        // Several bad values are fine, as long as there are more good ones
+        let input = "1\ntru\n2\nnul x\n3\n";
+        let (tree, _) =
+            ViewTree::new_from_reader(io::Cursor::new(input), "test".into(), test_layout())
+                .unwrap();
+        assert_eq!(tree.skipped_values.len(), 2);
+        assert!(tree.skipped_values[1].starts_with("line 4, "));
+        match &tree.view_frame.view {
+            View::Json(Some(view)) => assert_eq!(view.values.len(), 3),
+            _ => panic!("Expected a json view"),
+        }
+    }
+    #[test]
+    fn unit_load_progress_display() {
+        let progress = LoadProgress {
+            bytes_read: 5_260_000,
+            total_bytes: None,
+        };
+        assert_eq!(progress.to_string(), "5.3 MB");
+        let progress = LoadProgress {
+            total_bytes: Some(40_000_000),
+            ..progress
+        };
+        assert_eq!(progress.to_string(), "5.3 MB of 40.0 MB");
+    }
+    #[test]
+    fn unit_remove_view() {
+        let layout = test_layout();
+        let new_tree = |name: &str| {
+            let values: Vec<JV> = vec![(&json!(1)).into()];
+            ViewTree::new_from_values(values, name.into(), layout)
+        };
+        let mut first = new_tree("first");
+        first.push_trivial_child(layout.right);
+        first.children[0].1.push_trivial_child(layout.right);
+        let mut forest = ViewForest {
+            trees: vec![first, new_tree("second")],
+        };
+        let index = |tree, path: &[usize]| ViewForestIndex {
+            tree,
+            within_tree: ViewTreeIndex {
+                path: path.to_vec(),
+            },
+        };
+        let mut grandchild = index(0, &[0, 0]);
+        let mut sibling = index(0, &[1]);
+        let mut other_tree = index(1, &[0]);
+        let check = |forest: &ViewForest, ix: &ViewForestIndex, name: &str| {
+            assert_eq!(forest.index(ix).unwrap().frame().name, name);
+        };
+        let removed = index(0, &[0]);
+        assert!(forest.remove(&removed).is_some());
+        for ix in [&mut grandchild, &mut sibling, &mut other_tree] {
+            ix.update_after_removal(&removed, &forest);
+        }
+        check(&forest, &grandchild, "first");
+        assert_eq!(grandchild.within_tree.path, Vec::<usize>::new());
+        check(&forest, &sibling, "New Query");
+        assert_eq!(sibling.within_tree.path, [0]);
+        check(&forest, &other_tree, "New Query");
+        assert_eq!(other_tree.tree, 1);
+        let removed = index(0, &[]);
+        assert!(forest.remove(&removed).is_some());
+        for ix in [&mut grandchild, &mut sibling, &mut other_tree] {
+            ix.update_after_removal(&removed, &forest);
+        }
+        check(&forest, &grandchild, "second");
+        check(&forest, &sibling, "second");
+        check(&forest, &other_tree, "New Query");
+        assert_eq!(other_tree.tree, 0);
+        // The last tree can't go
+        assert!(forest.remove(&index(0, &[])).is_none());
+        assert_eq!(forest.trees.len(), 1);
+    }
+    #[test]
+    fn unit_reload() {
+        let path = std::env::temp_dir().join(format!("jex_reload_{}.json", std::process::id()));
+        fs::write(&path, r#"{"a": [1, 2], "b": 3}"#).unwrap();
+        let layout = test_layout();
+        let (mut tree, pending) =
+            ViewTree::new_from_file(path.to_str().unwrap().to_owned(), layout).unwrap();
+        assert!(pending.is_none());
+        let cursor_path = match &mut tree.view_frame.view {
+            View::Json(Some(view)) => {
+                let cursor_path = parse_path(".b", &view.values).unwrap();
+                view.cursor = LeafCursor::from_path(view.values.clone(), &cursor_path);
+                cursor_path
+            }
+            _ => panic!("Expected a json view"),
+        };
+        fs::write(&path, r#"{"a": [1, 2], "b": 4}"#).unwrap();
+        tree.reload(layout, DEFAULT_RESULT_LIMIT);
+        match &tree.view_frame.view {
+            View::Json(Some(view)) => {
+                let expected: Vec<JV> = vec![(&json!({"a": [1, 2], "b": 4})).into()];
+                assert_eq!(&*view.values, &expected[..]);
+                assert_eq!(view.cursor.to_path(), cursor_path);
+            }
+            _ => panic!("Expected a json view"),
+        }
+        // The trivial query reran on the new values
+        match &tree.children[0].1.view_frame.view {
+            View::Json(Some(view)) => {
+                assert_eq!(view.values[0], (&json!({"a": [1, 2], "b": 4})).into())
+            }
+            _ => panic!("Expected a json view"),
+        }
+        fs::write(&path, "{").unwrap();
+        tree.reload(layout, DEFAULT_RESULT_LIMIT);
+        assert!(matches!(
+            tree.view_frame.view,
+            View::Error(ViewError::Load(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn unit_reload_skips_bad_lines() {
+        let path = std::env::temp_dir().join(format!("jex_reload_{}.jsonl", std::process::id()));
+        fs::write(&path, "1\n2\n").unwrap();
+        let (mut tree, _) =
+            ViewTree::new_from_file(path.to_str().unwrap().to_owned(), test_layout()).unwrap();
+        fs::write(&path, "1\n{\n3\n").unwrap();
+        tree.reload(test_layout(), DEFAULT_RESULT_LIMIT);
+        match &tree.view_frame.view {
+            View::Json(Some(view)) => {
+                let expected: Vec<JV> = vec![(&json!(1.0)).into(), (&json!(3.0)).into()];
+                assert_eq!(&*view.values, &expected[..]);
+            }
+            _ => panic!("Expected a json view"),
+        }
+        assert_eq!(tree.skipped_values.len(), 1);
+        assert!(tree.skipped_values[0].starts_with("line 2, "));
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn unit_parse_ndjson() {
+        let input: String = (0..1000).map(|i| format!("{{\"a\": {}}}\n", i)).collect();
+        let expected: Vec<JV> = (0..1000)
+            .map(|i| (&json!({ "a": i as f64 })).into())
+            .collect();
+        assert_eq!(parse_ndjson(&input).unwrap().values, expected);
+        assert!(parse_ndjson("{\n  \"a\": 1\n}\n").is_err());
+    }
+    #[test]
+    fn unit_parse_ndjson_lossy() {
+        let input = "{\"a\": 1}\n{\"a\": \n\n[2]\n";
+        let loaded = parse_ndjson_lossy(input).unwrap();
+        let (values, skipped) = (loaded.values, loaded.skipped);
+        let expected: Vec<JV> = vec![(&json!({"a": 1.0})).into(), (&json!([2.0])).into()];
+        assert_eq!(values, expected);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].starts_with("line 2, column "), "{:?}", skipped);
+        assert!(!skipped[0].contains("at line"), "{:?}", skipped);
+        assert!(parse_ndjson_lossy("{\n  \"a\": 1\n}\n").is_none());
+    }
+    #[test]
+    fn unit_write_values() {
+        let values: Vec<Value> = vec![
+            json!({"a": [1, {"b": null}], "c": "d\ne"}),
+            json!([]),
+            json!("f"),
+        ];
+        let jsons: Vec<JV> = values.iter().map(|v| v.into()).collect();
+        let mut compact = Vec::new();
+        write_values(&jsons, &mut compact, false).unwrap();
+        let compact = String::from_utf8(compact).unwrap();
+        let lines: Vec<Value> = compact
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, values);
+        let mut pretty = Vec::new();
+        write_values(&jsons, &mut pretty, true).unwrap();
+        let pretty = String::from_utf8(pretty).unwrap();
+        assert!(pretty.lines().count() > values.len());
+        let parsed: Vec<Value> = Deserializer::from_str(&pretty)
+            .into_iter()
+            .collect::<serde_json::Result<_>>()
+            .unwrap();
+        assert_eq!(parsed, values);
+        let mut single = Vec::new();
+        write_values(&jsons[..1], &mut single, true).unwrap();
+        let single: Value = serde_json::from_str(std::str::from_utf8(&single).unwrap()).unwrap();
+        assert_eq!(single, values[0]);
+    }
+    #[test]
+    fn unit_bookmarks() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2], "b": 3})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        // Put the cursor on the `2`
+        for _ in 0..3 {
+            view.advance_cursor();
+        }
+        let marked = view.cursor.clone();
+        view.set_mark('a');
+        view.regress_cursor();
+        view.regress_cursor();
+        view.toggle_fold().unwrap();
+        assert!(view.jump_to_mark('a'));
+        assert_eq!(view.cursor, marked);
+        assert!(view.folds.is_empty());
+        assert!(!view.jump_to_mark('b'));
+        let jsons: Vec<JV> = vec![(&json!({"a": [1]})).into()];
+        let mut new_view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        new_view.inherit_bookmarks(view.bookmarks.clone());
+        assert!(new_view.bookmarks.is_empty());
+    }
+    #[test]
+    fn unit_grep() {
+        let jsons: Vec<JV> = vec![(&json!({
+            "a": {"b": [1], "c": "needle"},
+            "d": [2, {"e": 3}],
+            "f": {"needle": []}
+        }))
+            .into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.folds.insert((0, vec![0]), Fold::Children);
+        let re = Regex::new("needle").unwrap();
+        assert_eq!(
+            view.grep(&Regex::new("haystack").unwrap(), SearchScope::Both),
+            0
+        );
+        assert!(!view.is_grepping());
+        assert_eq!(view.grep(&re, SearchScope::Both), 2);
+        assert!(view.is_grepping());
+        let expected: HashSet<(usize, Vec<usize>)> =
+            vec![(0, vec![0, 0]), (0, vec![1]), (0, vec![1, 1])]
+                .into_iter()
+                .collect();
+        assert_eq!(fold_paths(&view), expected);
+        // Only the key matches now, so a is folded too
+        assert_eq!(view.grep(&re, SearchScope::Keys), 1);
+        let expected: HashSet<(usize, Vec<usize>)> =
+            vec![(0, vec![0]), (0, vec![0, 0]), (0, vec![1]), (0, vec![1, 1])]
+                .into_iter()
+                .collect();
+        assert_eq!(fold_paths(&view), expected);
+        // Back to the folds from before the first grep
+        assert!(view.ungrep());
+        assert!(!view.is_grepping());
+        let expected: HashSet<(usize, Vec<usize>)> = vec![(0, vec![0])].into_iter().collect();
+        assert_eq!(fold_paths(&view), expected);
+        assert!(!view.ungrep());
+    }
+    #[test]
+    fn unit_fold_siblings() {
+        let jsons: Vec<JV> = vec![(&json!({
+            "a": {"b": [1], "c": [2, [3], {"d": 4}, []], "e": {"f": 5}},
+            "g": [6]
+        }))
+            .into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let path = parse_path(".a.c[0]", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.fold_siblings();
+        let expected: HashSet<(usize, Vec<usize>)> = vec![
+            (0, vec![1]),
+            (0, vec![0, 0]),
+            (0, vec![0, 2]),
+            (0, vec![0, 1, 1]),
+            (0, vec![0, 1, 2]),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(fold_paths(&view), expected);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![0, 1, 0]));
+        // Jumping into a folded sibling only opens that sibling
+        let path = parse_path(".a.c[1][0]", &view.values).unwrap();
+        view.jump_to(LeafCursor::from_path(view.values.clone(), &path));
+        let expected: HashSet<(usize, Vec<usize>)> = vec![
+            (0, vec![1]),
+            (0, vec![0, 0]),
+            (0, vec![0, 2]),
+            (0, vec![0, 1, 2]),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(fold_paths(&view), expected);
+    }
+    #[test]
+    fn unit_delete_at_cursor() {
+        let jsons: Vec<JV> = vec![(&json!([[1], [2], [3]])).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.folds.insert((0, vec![0]), Fold::Children);
+        view.folds.insert((0, vec![2]), Fold::Children);
+        let path = parse_path("[1]", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.set_mark('a');
+        assert!(view.delete_at_cursor());
+        assert_eq!(
+            view.values.iter().map(Value::from).collect::<Vec<_>>(),
+            vec![json!([[1.0], [3.0]])]
+        );
+        // The folded [3] moved back a slot, and the cursor is on it
+        let expected: HashSet<(usize, Vec<usize>)> =
+            vec![(0, vec![0]), (0, vec![1])].into_iter().collect();
+        assert_eq!(fold_paths(&view), expected);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![1]));
+        assert!(view.bookmarks.is_empty());
+        let jsons: Vec<JV> = vec![(&json!(1)).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        assert!(!view.delete_at_cursor());
+    }
+    #[test]
+    fn unit_undo() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1], "b": [2], "c": 3})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let values = |view: &JsonView| view.values.iter().map(Value::from).collect::<Vec<_>>();
+        assert!(!view.undo());
+        view.folds.insert((0, vec![1]), Fold::Children);
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        assert!(view.delete_at_cursor());
+        assert!(view.rename_key("d"));
+        assert_eq!(values(&view), vec![json!({"d": [2.0], "c": 3.0})]);
+        assert!(view.undo());
+        assert_eq!(values(&view), vec![json!({"b": [2.0], "c": 3.0})]);
+        assert!(view.undo());
+        assert_eq!(
+            values(&view),
+            vec![json!({"a": [1.0], "b": [2.0], "c": 3.0})]
+        );
+        // The cursor and folds are back where they were before the delete
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![0]));
+        let expected: HashSet<(usize, Vec<usize>)> = vec![(0, vec![1])].into_iter().collect();
+        assert_eq!(fold_paths(&view), expected);
+        assert!(!view.undo());
+        assert!(view.redo());
+        assert_eq!(values(&view), vec![json!({"b": [2.0], "c": 3.0})]);
+        // A new edit drops what's left to redo
+        view.replace_at_cursor((&json!(4)).into());
+        assert!(!view.redo());
+        assert!(view.undo());
+        assert_eq!(values(&view), vec![json!({"b": [2.0], "c": 3.0})]);
+    }
+    #[test]
+    fn unit_goto_line() {
+        let jsons: Vec<JV> = vec![(&json!([[1, 2], 3])).into(), (&json!(4)).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.goto_line(4);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![0, 1]));
+        // Line 4 is hidden in the fold starting on line 2
+        view.folds.insert((0, vec![0]), Fold::Children);
+        view.goto_line(4);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![0]));
+        view.goto_line(6);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![1]));
+        view.goto_line(100);
+        assert_eq!(view.cursor.to_path().strip_position(), (1, vec![]));
+        view.goto_line(0);
+        assert_eq!(
+            view.cursor.to_path(),
+            LeafCursor::new(view.values.clone()).unwrap().to_path()
+        );
+    }
+    #[test]
+    fn unit_goto_document() {
+        let jsons: Vec<JV> = vec![(&json!([1, 2])).into(), (&json!({"a": 3})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.goto_line(2);
+        assert!(view.goto_document(2));
+        assert_eq!(view.cursor.to_path().strip_position(), (1, vec![]));
+        assert!(view.goto_document(1));
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![]));
+        assert!(!view.goto_document(3));
+        assert!(!view.goto_document(0));
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![]));
+    }
+    #[test]
+    fn unit_step_document() {
+        let jsons: Vec<JV> = vec![(&json!([1, 2])).into(), (&json!({"a": 3})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.goto_line(2);
+        assert!(!view.step_document(true));
+        assert!(view.step_document(false));
+        assert_eq!(view.cursor.to_path().strip_position(), (1, vec![]));
+        assert!(!view.step_document(false));
+        assert_eq!(view.cursor.to_path().strip_position(), (1, vec![]));
+        view.goto_line(5);
+        assert!(view.step_document(true));
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![]));
+    }
+    #[test]
+    fn unit_embedded_json_query() {
+        let jsons: Vec<JV> = vec![(&json!({"a": "{\"b\": [1]}", "c": "{", "d": 1})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        assert_eq!(
+            view.embedded_json_query(),
+            Err("Not on a string".to_owned())
+        );
+        view.goto_line(2);
+        assert_eq!(view.embedded_json_query(), Ok(".a | fromjson".to_owned()));
+        view.goto_line(3);
+        let err = view.embedded_json_query().unwrap_err();
+        assert!(err.starts_with("Not valid json: "), "{}", err);
+    }
+    #[test]
+    fn unit_fuzzy_score() {
+        assert_eq!(fuzzy_score("id", "id"), Some(10));
+        assert_eq!(fuzzy_score("ID", "id"), Some(10));
+        assert_eq!(fuzzy_score("xz", "id"), None);
+        // Word starts beat letters in the middle of a word
+        assert!(fuzzy_score("un", "user_name") > fuzzy_score("un", "username"));
+        assert!(fuzzy_score("ua", "updatedAt") > fuzzy_score("ua", "user_name"));
+    }
+    #[test]
+    fn unit_fuzzy_find_key() {
+        let jsons: Vec<JV> = vec![(&json!({
+            "id": 1,
+            "updatedAt": 2,
+            "user_name": 3,
+            "username": {"id": 4}
+        }))
+            .into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let found = |view: &JsonView, query: &str| {
+            view.fuzzy_find_key(query).map(|path| path.strip_position())
+        };
+        assert_eq!(found(&view, "un"), Some((0, vec![2])));
+        assert_eq!(found(&view, "id"), Some((0, vec![0])));
+        assert_eq!(found(&view, "upat"), Some((0, vec![1])));
+        assert_eq!(found(&view, "qqq"), None);
+        assert_eq!(found(&view, ""), None);
+        // On a scalar, the keys searched are its siblings
+        view.goto_line(3);
+        assert_eq!(found(&view, "id"), Some((0, vec![0])));
+        // On an object, they're its own
+        view.goto_line(5);
+        assert_eq!(found(&view, "id"), Some((0, vec![3, 0])));
+    }
+    #[test]
+    fn unit_insert_key() {
+        let jsons: Vec<JV> = vec![(&json!({"a": {"b": 1}, "c": 2})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let values = |view: &JsonView| view.values.iter().map(Value::from).collect::<Vec<_>>();
+        view.folds.insert((0, vec![0]), Fold::Children);
+        // On a folded object, the key goes inside it, and it's opened up to show it
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        assert!(view.insert_key("d", (&json!([3])).into()));
+        assert_eq!(
+            values(&view),
+            vec![json!({"a": {"b": 1.0, "d": [3.0]}, "c": 2.0})]
+        );
+        assert_eq!(view.cursor.current_key().unwrap().value(), "d");
+        assert!(view.folds.is_empty());
+        assert!(!view.insert_key("b", (&json!(4)).into()));
+        // On a leaf, the key goes beside it
+        let path = parse_path(".c", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        assert!(view.insert_key("e", (&json!(4)).into()));
+        assert_eq!(
+            values(&view),
+            vec![json!({"a": {"b": 1.0, "d": [3.0]}, "c": 2.0, "e": 4.0})]
+        );
+        assert_eq!(Value::from(&view.cursor.focus), json!(4.0));
+        assert!(view.undo());
+        assert_eq!(
+            values(&view),
+            vec![json!({"a": {"b": 1.0, "d": [3.0]}, "c": 2.0})]
+        );
+        let jsons: Vec<JV> = vec![(&json!(1)).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        assert!(!view.insert_key("a", (&json!(2)).into()));
+    }
+    #[test]
+    fn unit_append_element() {
+        let jsons: Vec<JV> = vec![(&json!([[1], 2])).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let values = |view: &JsonView| view.values.iter().map(Value::from).collect::<Vec<_>>();
+        view.folds.insert((0, vec![]), Fold::Children);
+        // On a leaf, the element goes at the end of its array
+        let path = parse_path("[1]", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        assert!(view.append_element((&json!([])).into()));
+        assert_eq!(values(&view), vec![json!([[1.0], 2.0, []])]);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![2]));
+        // Into the empty array the cursor is now on
+        assert!(view.append_element((&json!("x")).into()));
+        assert_eq!(values(&view), vec![json!([[1.0], 2.0, ["x"]])]);
+        assert_eq!(view.cursor.to_path().strip_position(), (0, vec![2, 0]));
+        assert!(view.folds.is_empty());
+        let jsons: Vec<JV> = vec![(&json!({"a": 1})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        assert!(!view.append_element((&json!(2)).into()));
+    }
+    #[test]
+    fn unit_rename_key() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1], "b": [2], "c": [3]})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let path = parse_path(".c", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        view.set_mark('c');
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        assert!(!view.rename_key("b"));
+        assert!(view.rename_key("d"));
+        assert_eq!(view.cursor.current_key().unwrap().value(), "d");
+        // The fold and mark still point at .c, wherever it ended up
+        let c = parse_path(".c", &view.values).unwrap();
+        assert_eq!(view.bookmarks[&'c'], c);
+        let expected: HashSet<(usize, Vec<usize>)> = vec![c.strip_position()].into_iter().collect();
+        assert_eq!(fold_paths(&view), expected);
+    }
+    #[test]
+    fn unit_fold_leaf() {
+        let jsons: Vec<JV> = vec![(&json!({"a": 1})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        assert_eq!(view.toggle_fold(), Ok(()));
+        assert_eq!(view.folds.len(), 1);
+        assert_eq!(view.toggle_fold(), Ok(()));
+        view.advance_cursor();
+        assert_eq!(view.toggle_fold(), Err(FoldError::Leaf));
+        assert!(view.folds.is_empty());
+    }
+    #[test]
+    fn unit_descendant_counts() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, [2, 3]], "b": {"c": 4}})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        let folded_line = |view: &JsonView| {
+            let line = view.cursor.current_line(&view.folds, DUMMY_RECT.width);
+            LineCursor::new_at_start(line.render(view.display), DUMMY_RECT.width)
+                .current()
+                .unwrap()
+                .to_spans(false, &Theme::default())
+                .0
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect::<String>()
+        };
+        assert!(folded_line(&view).ends_with("(2 children)"));
+        view.toggle_descendant_counts();
+        assert!(folded_line(&view).ends_with("(4 descendants)"));
+        // New folds get counted too
+        let path = parse_path(".b", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        assert!(folded_line(&view).ends_with("(1 descendants)"));
+        view.toggle_descendant_counts();
+        assert!(folded_line(&view).ends_with("(1 children)"));
+    }
+    #[test]
+    fn unit_page_keeps_cursor_on_screen() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 20,
+            height: 5,
+        };
+        let long = "x".repeat(100);
+        let jsons: Vec<JV> = vec![(&json!(vec![long; 10])).into()];
+        let mut view = JsonView::new(jsons, rect).unwrap();
+        for _ in 0..3 {
+            view.page_down();
+            assert!(view
+                .visible_range(&view.folds)
+                .contains_value(&view.cursor.to_path()));
+        }
+        for _ in 0..3 {
+            view.page_up();
+            assert!(view
+                .visible_range(&view.folds)
+                .contains_value(&view.cursor.to_path()));
+        }
+    }
+    #[test]
+    fn unit_visible_paths() {
+        let jsons: Vec<JV> = vec![(&json!((0..100).collect::<Vec<_>>())).into()];
+        let mut view = JsonView::new(jsons, TINY_RECT).unwrap();
+        let values = view.values.clone();
+        let path = |path: &str| parse_path(path, &values).unwrap();
+        assert_eq!(view.visible_paths(), (path("."), path(".[18]")));
+        assert_eq!(view.visible_line_count(), TINY_RECT.height as usize);
+        for _ in 0..TINY_RECT.height {
+            view.advance_cursor();
+        }
+        let (start, end) = view.visible_paths();
+        assert!(start <= view.cursor.to_path() && view.cursor.to_path() <= end);
+        // Values shorter than the screen leave lines empty
+        let jsons: Vec<JV> = vec![(&json!([1, 2])).into()];
+        let short = JsonView::new(jsons, TINY_RECT).unwrap();
+        assert_eq!(short.visible_line_count(), 4);
+    }
+    #[test]
+    fn unit_center_cursor() {
+        let jsons: Vec<JV> = vec![(&json!((0..100).collect::<Vec<_>>())).into()];
+        let mut view = JsonView::new(jsons, TINY_RECT).unwrap();
+        let top = view.scroll.to_path();
+        for _ in 0..50 {
+            view.advance_cursor();
+        }
+        let cursor = view.cursor.to_path();
+        view.center_cursor();
+        assert_eq!(view.cursor.to_path(), cursor);
+        let mut scroll = view.scroll.clone();
+        for _ in 0..TINY_RECT.height / 2 {
+            scroll.advance(&view.folds, view.wrap_width());
+        }
+        assert_eq!(scroll.to_path().value_path, cursor);
+        // Near the top there's nothing to scroll up to
+        for _ in 0..48 {
+            view.regress_cursor();
+        }
+        view.center_cursor();
+        assert_eq!(view.scroll.to_path(), top);
+    }
+    #[test]
+    fn unit_half_page() {
+        let jsons: Vec<JV> = vec![(&json!((0..20).collect::<Vec<_>>())).into()];
+        let mut view = JsonView::new(jsons, TINY_RECT).unwrap();
+        let half = TINY_RECT.height / 2;
+        let top = view.scroll.to_path();
+        view.half_page_down();
+        let mut expected = GlobalCursor::new(
+            view.values.clone(),
+            view.wrap_width(),
+            &view.folds,
+            view.display,
+        )
+        .unwrap();
+        for _ in 0..half {
+            expected.advance(&view.folds, view.wrap_width());
+        }
+        assert_eq!(view.scroll.to_path(), expected.to_path());
+        // Clamped at the end of the document
+        for _ in 0..100 {
+            view.half_page_down();
+        }
+        assert!(view.cursor.clone().advance(&view.folds).is_none());
+        for _ in 0..100 {
+            view.half_page_up();
+        }
+        assert_eq!(view.scroll.to_path(), top);
+    }
+    #[test]
+    fn unit_horizontal_scroll() {
+        let long = "x".repeat(200);
+        let jsons: Vec<JV> = vec![(&json!([long, 1])).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let line_count = |view: &JsonView| {
+            let mut scroll = GlobalCursor::new(
+                view.values.clone(),
+                view.wrap_width(),
+                &view.folds,
+                view.display,
+            )
+            .unwrap();
+            let mut count = 1;
+            while let Some(()) = scroll.advance(&view.folds, view.wrap_width()) {
+                count += 1;
+            }
+            count
+        };
+        assert_eq!(line_count(&view), 5);
+        // Scrolling sideways does nothing while wrapping
+        view.scroll_right(8);
+        assert_eq!(view.h_scroll, 0);
+        view.toggle_wrap();
+        assert_eq!(line_count(&view), 4);
+        view.scroll_right(8);
+        assert_eq!(view.h_scroll, 8);
+        // Clamped so the end of the longest line stays on screen: 2 indent + 2 quotes + comma
+        view.scroll_right(1000);
+        assert_eq!(view.h_scroll, 205 - DUMMY_RECT.width);
+        view.scroll_left(1000);
+        assert_eq!(view.h_scroll, 0);
+        view.scroll_right(8);
+        view.toggle_wrap();
+        assert_eq!(view.h_scroll, 0);
+        assert_eq!(line_count(&view), 5);
+    }
+    #[test]
+    fn unit_render_to_string() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2], "b": "<b>"})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        assert_eq!(
+            view.render_to_string(DUMMY_RECT.width, None),
+            "{\n  \"a\" : [...], (2 children)\n  \"b\" : \"<b>\"\n}\n"
+        );
+        assert_eq!(
+            view.render_to_string(DUMMY_RECT.width, Some(2))
+                .lines()
+                .count(),
+            2
+        );
+        let html = view.render_to_html(DUMMY_RECT.width, None, &Theme::dark());
+        assert!(html.starts_with("<pre style=\"color:#ffffff;background-color:#000000;\">"));
+        assert!(html.contains("<span style=\"color:#00cd00;\">&quot;&lt;b&gt;&quot;</span>"));
+    }
+    #[test]
+    fn unit_inline() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2], "b": {"c": [3, 4, 5]}})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let lines = |view: &JsonView| {
+            let mut scroll = GlobalCursor::new(
+                view.values.clone(),
+                DUMMY_RECT.width,
+                &view.folds,
+                view.display,
+            )
+            .unwrap();
+            let mut lines = vec![scroll.current_line()];
+            while let Some(()) = scroll.advance(&view.folds, DUMMY_RECT.width) {
+                lines.push(scroll.current_line());
+            }
+            lines
+                .into_iter()
+                .map(|line| {
+                    line.to_spans(false, &Theme::default())
+                        .0
+                        .iter()
+                        .map(|span| span.content.as_ref())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let path = parse_path(".a[1]", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        // [1, 2] just fits, but [3, 4, 5] doesn't
+        view.toggle_inline(6);
+        assert_eq!(
+            lines(&view),
+            vec![
+                "{",
+                "  \"a\" : [1, 2],",
+                "  \"b\" : {",
+                "    \"c\" : [",
+                "      3,",
+                "      4,",
+                "      5",
+                "    ]",
+                "  }",
+                "}",
+            ]
+        );
+        // The cursor moves out of the inlined array, and skips over it
+        assert_eq!(
+            view.cursor.to_path(),
+            parse_path(".a", &view.values).unwrap()
+        );
+        view.cursor.advance(&view.folds);
+        assert_eq!(
+            view.cursor.to_path(),
+            parse_path(".b", &view.values).unwrap()
+        );
+        // Toggling the fold draws it in full
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        assert_eq!(
+            &lines(&view)[1..5],
+            ["  \"a\" : [", "    1,", "    2", "  ],"]
+        );
+        view.toggle_fold().unwrap();
+        assert_eq!(lines(&view)[1], "  \"a\" : [...], (2 children)");
+        view.unfold_all();
+        view.toggle_inline(6);
+        assert!(view.folds.is_empty());
+        assert_eq!(lines(&view).len(), 13);
+    }
+    #[test]
+    fn unit_inline_after_display_change() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [null, true], "b": ["x\ny"]})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let inlined = |view: &JsonView| -> HashSet<(usize, Vec<usize>)> {
+            view.folds
+                .iter()
+                .filter(|(_, fold)| **fold == Fold::Inline)
+                .map(|(path, _)| path.clone())
+                .collect()
+        };
+        let paths = |paths: &[&str], view: &JsonView| -> HashSet<(usize, Vec<usize>)> {
+            paths
+                .iter()
+                .map(|path| parse_path(path, &view.values).unwrap().strip_position())
+                .collect()
+        };
+        let path = parse_path(".a[1]", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        // [null, true] is too wide, but ["x\ny"] fits
+        view.toggle_inline(8);
+        assert_eq!(inlined(&view), paths(&[".b"], &view));
+        // [∅, ✓] fits, so it's inlined, and the cursor moves out of it
+        view.set_display(DisplayOptions {
+            literal_glyphs: true,
+            ..DisplayOptions::default()
+        });
+        assert_eq!(inlined(&view), paths(&[".a", ".b"], &view));
+        assert_eq!(
+            view.cursor.to_path(),
+            parse_path(".a", &view.values).unwrap()
+        );
+        // A string that ends lines can't go on one
+        view.set_display(DisplayOptions {
+            literal_newlines: true,
+            ..DisplayOptions::default()
+        });
+        assert!(inlined(&view).is_empty());
+        assert_eq!(
+            view.render_to_string(DUMMY_RECT.width, None),
+            "{\n  \"a\" : [\n    null,\n    true\n  ],\n  \"b\" : [\n    \"x\n     y\"\n  ]\n}\n"
+        );
+    }
+    #[test]
+    fn unit_fold_recursive() {
+        let jsons: Vec<JV> = vec![
+            (&json!({"a": {"b": [1, [2]], "c": {}}, "d": [3]})).into(),
+            (&json!([4])).into(),
+        ];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let paths = |paths: &[&str], view: &JsonView| -> HashSet<(usize, Vec<usize>)> {
+            paths
+                .iter()
+                .map(|path| parse_path(path, &view.values).unwrap().strip_position())
+                .collect()
+        };
+        let path = parse_path(".a.b[0]", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        assert_eq!(view.fold_recursive(true), Err(FoldError::Leaf));
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.fold_recursive(true).unwrap();
+        // Empty containers can't be folded, and siblings are left alone
+        assert_eq!(fold_paths(&view), paths(&[".a", ".a.b", ".a.b[1]"], &view));
+        assert!(view.cursor_is_folded());
+        view.fold_recursive(false).unwrap();
+        assert!(view.folds.is_empty());
+        // Folds inside the subtree are removed, but ones outside aren't
+        let path = parse_path(".d", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        view.cursor = LeafCursor::new(view.values.clone()).unwrap();
+        view.fold_recursive(true).unwrap();
+        let path = parse_path(".a", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.fold_recursive(false).unwrap();
+        assert_eq!(fold_paths(&view), paths(&[".", ".d"], &view));
+    }
+    #[test]
+    fn unit_inherit_from() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2], "b": {"c": [3]}, "d": [4]})).into()];
+        let mut old = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        for path in &[".a", ".b.c", ".d"] {
+            let path = parse_path(path, &old.values).unwrap();
+            old.cursor = LeafCursor::from_path(old.values.clone(), &path);
+            old.toggle_fold().unwrap();
+        }
+        old.toggle_descendant_counts();
+        let path = parse_path(".b", &old.values).unwrap();
+        old.cursor = LeafCursor::from_path(old.values.clone(), &path);
+        // The result of a query like `.d = 5`
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2], "b": {"c": [3]}, "d": 5})).into()];
+        let mut new = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        new.inherit_from(&mut old);
+        let paths: HashSet<_> = [".a", ".b.c"]
+            .iter()
+            .map(|path| parse_path(path, &new.values).unwrap().strip_position())
+            .collect();
+        assert_eq!(fold_paths(&new), paths);
+        assert!(new.show_descendants);
+        assert_eq!(new.cursor.to_path(), parse_path(".b", &new.values).unwrap());
+        // The cursor falls back to the start if its path is gone
+        let jsons: Vec<JV> = vec![(&json!([1])).into()];
+        let mut newer = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        newer.inherit_from(&mut new);
+        assert!(newer.folds.is_empty());
+        assert_eq!(
+            newer.cursor.to_path(),
+            parse_path(".", &newer.values).unwrap()
+        );
+    }
+    #[test]
+    fn unit_toggle_sort_keys() {
+        let jsons = vec![JV::parse_native(r#"{"b": {"d": [1], "c": 2}, "a": [3]}"#).unwrap()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        let unsorted = view.values.clone();
+        let path = parse_path(".b.d", &view.values).unwrap();
+        view.cursor = LeafCursor::from_path(view.values.clone(), &path);
+        view.toggle_fold().unwrap();
+        view.set_mark('m');
+        view.toggle_sort_keys();
+        // Paths follow the values to their sorted positions
+        let sorted_path = parse_path(".b.d", &view.values).unwrap();
+        assert_ne!(sorted_path, path);
+        assert_eq!(view.cursor.to_path(), sorted_path);
+        assert_eq!(
+            fold_paths(&view),
+            [sorted_path.clone().strip_position()].into()
+        );
+        assert_eq!(view.bookmarks[&'m'], sorted_path);
+        view.toggle_sort_keys();
+        assert!(Rc::ptr_eq(&view.values, &unsorted));
+        assert_eq!(view.cursor.to_path(), path);
+        assert_eq!(fold_paths(&view), [path.strip_position()].into());
+        // Edits made while sorted stay sorted
+        view.toggle_sort_keys();
+        view.delete_at_cursor();
+        let sorted = view.values.clone();
+        view.toggle_sort_keys();
+        assert!(!view.sort_keys);
+        assert!(Rc::ptr_eq(&view.values, &sorted));
+    }
+    #[test]
+    fn unit_unfold_all() {
+        let jsons: Vec<JV> = vec![(&json!({"a": [1, 2], "b": {"c": 3}})).into()];
+        let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
+        view.fold_to_depth(0);
+        let cursor = view.cursor.clone();
+        view.unfold_all();
+        assert_eq!(fold_paths(&view), HashSet::new());
+        assert_eq!(view.cursor, cursor);
+        assert_eq!(view.scroll.value_cursor, cursor);
+        // The scroll line should be the opening bracket, not the folded summary
+        let line = cursor.current_line(&view.folds, DUMMY_RECT.width);
+        let expected = LineCursor::new_at_start(line.render(view.display), DUMMY_RECT.width)
+            .current()
+            .unwrap();
+        assert_eq!(view.scroll.current_line(), expected);
+    }
+    #[test]
     fn unit_folds() {
         let json_path = "testdata/example.json";
         let f = fs::File::open(&json_path).unwrap();
@@ -689,8 +3540,13 @@ mod tests {
             .collect::<Result<Vec<JV>, _>>()
             .unwrap();
         let mut view = JsonView::new(jsons, DUMMY_RECT).unwrap();
-        view.scroll =
-            GlobalCursor::new_end(view.values.clone(), DUMMY_RECT.width, &HashSet::new()).unwrap();
+        view.scroll = GlobalCursor::new_end(
+            view.values.clone(),
+            DUMMY_RECT.width,
+            &Folds::new(),
+            view.display,
+        )
+        .unwrap();
         view.cursor = view.scroll.value_cursor.clone();
         let line_limit = 20;
         let rect = Rect {
@@ -702,8 +3558,8 @@ mod tests {
         for _ in 0..line_limit - 1 {
             view.scroll.regress(&view.folds, DUMMY_RECT.width);
         }
-        view.toggle_fold();
-        view.render(rect, true);
+        view.toggle_fold().unwrap();
+        view.render(rect, true, false, None, &Theme::default());
     }
     #[test]
     fn unit_scroll_render() {
@@ -721,12 +3577,12 @@ mod tests {
             ..DUMMY_RECT
         };
         let right_view = JsonView::new(jsons, right_rect).unwrap();
-        let folds = HashSet::new();
-        view.render(DUMMY_RECT, true);
-        right_view.render(right_rect, true);
+        let folds = Folds::new();
+        view.render(DUMMY_RECT, true, false, None, &Theme::default());
+        right_view.render(right_rect, true, false, None, &Theme::default());
         while let Some(()) = view.scroll.advance(&folds, DUMMY_RECT.width) {
-            view.render(DUMMY_RECT, true);
-            right_view.render(right_rect, true);
+            view.render(DUMMY_RECT, true, false, None, &Theme::default());
+            right_view.render(right_rect, true, false, None, &Theme::default());
         }
     }
     #[test]
@@ -739,6 +3595,6 @@ mod tests {
             .collect::<Result<Vec<JV>, _>>()
             .unwrap();
         let view = JsonView::new(jsons, TINY_RECT).unwrap();
-        view.render(TINY_RECT, true);
+        view.render(TINY_RECT, true, false, None, &Theme::default());
     }
 }
@@ -0,0 +1,121 @@
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, SystemTime},
+};
+
+// Writes closer together than this are reported as one change
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+// Watches files for changes on disk. Editors often save by writing a new file and renaming it over
+// the old one, which would lose a watch on the file itself, so this watches the directories they're
+// in instead.
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    receiver: Receiver<DebouncedEvent>,
+    watched_dirs: HashSet<PathBuf>,
+    // When jex last wrote each of these files itself, so those writes aren't reported as changes
+    own_writes: HashMap<PathBuf, SystemTime>,
+}
+
+impl FileWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = notify::watcher(sender, DEBOUNCE)?;
+        Ok(FileWatcher {
+            watcher,
+            receiver,
+            watched_dirs: HashSet::new(),
+            own_writes: HashMap::new(),
+        })
+    }
+    // Starts watching path, if it isn't already being watched.
+    pub fn watch(&mut self, path: &str) -> notify::Result<()> {
+        let dir = match absolute_path(path).and_then(|path| Some(path.parent()?.to_owned())) {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        if !self.watched_dirs.contains(&dir) {
+            self.watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+            self.watched_dirs.insert(dir);
+        }
+        Ok(())
+    }
+    // Call after jex writes path itself. Until something else writes it, the file keeps the
+    // modification time it has now, so that's how the watcher tells the two apart.
+    pub fn ignore_own_write(&mut self, path: &str) {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        if let (Some(path), Ok(modified)) = (absolute_path(path), modified) {
+            self.own_writes.insert(path, modified);
+        }
+    }
+    // Paths written since the last call, without waiting for more.
+    pub fn changed(&self) -> HashSet<PathBuf> {
+        self.receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => Some(path),
+                DebouncedEvent::Rename(_, path) => Some(path),
+                _ => None,
+            })
+            .filter(|path| !self.is_own_write(path))
+            .collect()
+    }
+    fn is_own_write(&self, path: &Path) -> bool {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        match (self.own_writes.get(path), modified) {
+            (Some(written), Ok(modified)) => *written == modified,
+            _ => false,
+        }
+    }
+}
+
+// The path events for path are reported under. The file itself might not exist (for a moment) when
+// it's being replaced, so only its directory is canonicalized.
+pub fn absolute_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Some(dir.canonicalize().ok()?.join(path.file_name()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{absolute_path, FileWatcher};
+    use std::{fs, thread, time::Duration};
+
+    #[test]
+    fn unit_file_watcher() {
+        let dir = std::env::temp_dir().join(format!("jex_watch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watched.json");
+        fs::write(&path, "1").unwrap();
+        let path = path.to_str().unwrap();
+        let mut watcher = FileWatcher::new().unwrap();
+        watcher.watch(path).unwrap();
+        assert!(watcher.changed().is_empty());
+        // Replaced the way editors save, rather than written in place
+        let tmp_path = dir.join("watched.json.tmp");
+        fs::write(&tmp_path, "2").unwrap();
+        fs::rename(&tmp_path, path).unwrap();
+        thread::sleep(Duration::from_secs(1));
+        assert!(watcher.changed().contains(&absolute_path(path).unwrap()));
+        fs::write(path, "3").unwrap();
+        thread::sleep(Duration::from_secs(1));
+        assert!(watcher.changed().contains(&absolute_path(path).unwrap()));
+        // Jex's own saves don't count, but the next outside write still does
+        fs::write(path, "4").unwrap();
+        watcher.ignore_own_write(path);
+        thread::sleep(Duration::from_secs(1));
+        assert!(watcher.changed().is_empty());
+        fs::write(path, "5").unwrap();
+        thread::sleep(Duration::from_secs(1));
+        assert!(watcher.changed().contains(&absolute_path(path).unwrap()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}